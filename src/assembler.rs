@@ -3,12 +3,45 @@ use std::{collections::HashMap, fs};
 use bytes::{BufMut, BytesMut};
 use regex::Regex;
 
-use crate::instructions::Word;
+use crate::{float::RoundingMode, instructions::Word};
+
+/// Where an instruction came from in the acasm source.
+#[derive(Debug, Clone)]
+pub struct SourceInfo {
+    pub line: usize, // 1 indexed line in the file
+    pub label: Option<String>,
+    pub label_offset: usize, // instructions since `label`
+    pub text: String,
+}
+impl SourceInfo {
+    /// `label+offset`, or just the line number when no label comes before it.
+    pub fn location(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{}+{}", label, self.label_offset),
+            None => format!("line {}", self.line),
+        }
+    }
+}
+
+pub fn assemble_file(filename: &str) -> (BytesMut, Vec<Word>, Vec<SourceInfo>) {
+    let file_content = fs::read_to_string(filename).unwrap();
+    assemble_source(&file_content)
+}
+
+pub fn assemble_source(acasm: &str) -> (BytesMut, Vec<Word>, Vec<SourceInfo>) {
+    assemble(&preprocessor(acasm))
+}
+
+/// Where every label in the memory section starts.
+#[cfg(test)]
+pub fn memory_labels(filename: &str) -> HashMap<String, usize> {
+    let file_content = preprocessor(&fs::read_to_string(filename).unwrap());
+    let re = Regex::new(r"\.memory([\s\S]*)\.instructions").unwrap();
 
-pub fn assemble_file(filename: &str) -> (BytesMut, Vec<Word>) {
-    let mut file_content = fs::read_to_string(filename).unwrap();
-    file_content = preprocessor(&file_content);
-    assemble(&file_content)
+    match re.captures(&file_content) {
+        Some(captures) => create_memory(captures.get(1).unwrap().as_str()).1,
+        None => HashMap::new(),
+    }
 }
 
 fn preprocessor(acasm: &str) -> String {
@@ -19,7 +52,7 @@ fn preprocessor(acasm: &str) -> String {
         .join("\n")
 }
 
-fn assemble(acasm: &str) -> (BytesMut, Vec<Word>) {
+fn assemble(acasm: &str) -> (BytesMut, Vec<Word>, Vec<SourceInfo>) {
     let re = Regex::new(r"(?:(?:\.memory)([\s\S]*))?(?:\.instructions)([\s\S]*)").unwrap();
 
     let captures = re.captures(acasm).unwrap();
@@ -28,10 +61,12 @@ fn assemble(acasm: &str) -> (BytesMut, Vec<Word>) {
         None => (BytesMut::with_capacity(4049), HashMap::new()),
     };
 
-    let inst_acasm = captures.get(2).unwrap().as_str();
-    let instructions = create_instructions(inst_acasm, label_locations);
+    let inst_acasm = captures.get(2).unwrap();
+    let line_offset = acasm[..inst_acasm.start()].matches('\n').count();
+    let (instructions, source) =
+        create_instructions(inst_acasm.as_str(), line_offset, label_locations);
 
-    return (memory, instructions);
+    return (memory, instructions, source);
 }
 
 fn create_memory(acasm: &str) -> (BytesMut, HashMap<String, usize>) {
@@ -71,7 +106,7 @@ fn int_directive(memory: &mut BytesMut, arguments: &str) -> usize {
         .inspect(|i| memory.put_i32(*i))
         .count();
 
-    return (count * 4) as usize;
+    return count * 4;
 }
 
 fn float_directive(memory: &mut BytesMut, arguments: &str) -> usize {
@@ -81,13 +116,13 @@ fn float_directive(memory: &mut BytesMut, arguments: &str) -> usize {
         .inspect(|i| memory.put_f32(*i))
         .count();
 
-    return (count * 4) as usize;
+    return count * 4;
 }
 
 fn space_directive(memory: &mut BytesMut, arguments: &str) -> usize {
     let n: usize = arguments.trim().parse().unwrap();
     memory.put_bytes(0, n);
-    return n as usize;
+    return n;
 }
 
 fn file_directive(memory: &mut BytesMut, arguments: &str) -> usize {
@@ -97,14 +132,21 @@ fn file_directive(memory: &mut BytesMut, arguments: &str) -> usize {
     return content.len() as usize;
 }
 
-fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<Word> {
+fn create_instructions(
+    acasm: &str,
+    line_offset: usize,
+    mut labels: HashMap<String, usize>,
+) -> (Vec<Word>, Vec<SourceInfo>) {
     let mut instructions = Vec::new();
+    let mut source = Vec::new();
 
-    let lines: Vec<&str> = acasm
+    let numbered_lines: Vec<(usize, &str)> = acasm
         .lines()
-        .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
+        .enumerate()
+        .map(|(i, l)| (line_offset + i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty())
         .collect();
+    let lines: Vec<&str> = numbered_lines.iter().map(|(_, l)| *l).collect();
 
     let mut pc = 0;
     for line in &lines {
@@ -116,7 +158,15 @@ fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<W
         }
     }
 
-    for line in lines.iter().filter(|l| !l.ends_with(':')) {
+    let mut current_label = None;
+    let mut label_offset = 0;
+    for (line_number, line) in numbered_lines {
+        if line.ends_with(':') {
+            current_label = Some(line[0..line.len() - 1].to_string());
+            label_offset = 0;
+            continue;
+        }
+
         let split: Vec<&str> = line.split_whitespace().collect();
         let op = split[0];
 
@@ -129,7 +179,8 @@ fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<W
                         return format!("${}", labels.get(*arg).unwrap());
                     } else {
                         return match op {
-                            "be" | "bne" | "bg" | "bge" | "bl" | "ble" => {
+                            "be" | "bne" | "bg" | "bge" | "bl" | "ble" | "bgu" | "bgeu" | "blu"
+                            | "bleu" => {
                                 (*labels.get(*arg).unwrap() as i32) - (instructions.len() as i32)
                             }
                             _ => *labels.get(*arg).unwrap() as i32,
@@ -156,20 +207,55 @@ fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<W
             "mult" => Word::multiply(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
             "multno" => Word::multiply_no_overflow(p_reg(&args[0]), p_reg(&args[1])),
             "div" => Word::divide(p_reg(&args[0]), p_reg(&args[1])),
+            "multu" => Word::multiply_unsigned(p_reg(&args[0]), p_reg(&args[1])),
+            "divu" => Word::divide_unsigned(p_reg(&args[0]), p_reg(&args[1])),
+            "rem" => Word::remainder(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "remu" => Word::remainder_unsigned(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
             "cmp" => Word::compare(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "cmpu" => Word::compare_unsigned(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "slt" => Word::set_less_than(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "sltu" => {
+                Word::set_less_than_unsigned(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2]))
+            }
             "and" => Word::bit_and(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
             "andi" => Word::bit_and_immediate(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "or" => Word::bit_or(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
             "ori" => Word::bit_or_immediate(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "xor" => Word::bit_xor(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "xori" => Word::bit_xor_immediate(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "nor" => Word::bit_nor(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "not" => Word::bit_not(p_reg(&args[0]), p_reg(&args[1])),
             "neg" => Word::neg(p_reg(&args[0]), p_reg(&args[1])),
             "lsft" => Word::left_shift(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "rsft" => Word::right_shift(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "rsftl" => Word::right_shift_logical(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "lsftv" => Word::left_shift_variable(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "rsftv" => {
+                Word::right_shift_variable(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2]))
+            }
+            "rsftlv" => Word::right_shift_logical_variable(
+                p_reg(&args[0]),
+                p_reg(&args[1]),
+                p_reg(&args[2]),
+            ),
             "be" => Word::branch_equal(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "bne" => Word::branch_not_equal(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "bg" => Word::branch_greater(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "bge" => Word::branch_greater_equal(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "bl" => Word::branch_less(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "ble" => Word::branch_less_equal(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "bgu" => {
+                Word::branch_greater_unsigned(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2]))
+            }
+            "bgeu" => Word::branch_greater_equal_unsigned(
+                p_reg(&args[0]),
+                p_reg(&args[1]),
+                p_i32(&args[2]),
+            ),
+            "blu" => Word::branch_less_unsigned(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "bleu" => {
+                Word::branch_less_equal_unsigned(p_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2]))
+            }
             "j" => Word::jump_immediate(p_i32(&args[0])),
             "jr" => Word::jump_reg(p_reg(&args[0])),
             "jal" => Word::jump_and_link(p_reg(&args[0]), p_i32(&args[1])),
@@ -181,8 +267,33 @@ fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<W
             "fmult" => Word::fmultiply(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
             "fdiv" => Word::fdivide(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
             "fcmp" => Word::fcompare(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "fma" => Word::fmultiply_add(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "fms" => Word::fmultiply_subtract(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "fsqrt" => Word::fsqrt(p_reg(&args[0]), p_reg(&args[1])),
+            "fmin" => Word::fmin(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "fmax" => Word::fmax(p_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "fabs" => Word::fabs(p_reg(&args[0]), p_reg(&args[1])),
+            "fneg" => Word::fneg(p_reg(&args[0]), p_reg(&args[1])),
+            "itof" => Word::int_to_float(p_reg(&args[0]), p_reg(&args[1])),
+            "ftoi" => Word::float_to_int(
+                p_reg(&args[0]),
+                p_reg(&args[1]),
+                match args.get(2).map(String::as_str) {
+                    Some("dyn") => None,
+                    Some(mode) => Some(p_rounding(mode)),
+                    None => Some(RoundingMode::TowardZero),
+                },
+            ),
+            "frflags" => Word::fread_flags(p_reg(&args[0])),
+            "fclrflags" => Word::fclear_flags(),
+            "frrm" => Word::fread_rounding(p_reg(&args[0])),
+            "fsrm" => Word::fset_rounding(p_rounding(&args[0])),
             "lv" => Word::v_load_memory(p_v_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
             "sv" => Word::v_store_memory(p_v_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "lvs" => Word::v_load_strided(p_v_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "svs" => Word::v_store_strided(p_v_reg(&args[0]), p_reg(&args[1]), p_reg(&args[2])),
+            "lvx" => Word::v_gather(p_v_reg(&args[0]), p_reg(&args[1]), p_v_reg(&args[2])),
+            "svx" => Word::v_scatter(p_v_reg(&args[0]), p_reg(&args[1]), p_v_reg(&args[2])),
             "vadd" => Word::v_add(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
             "vsub" => Word::v_subtract(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
             "vmult" => Word::v_multiply(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
@@ -194,6 +305,60 @@ fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<W
             "vfmult" => Word::v_fmultiply(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
             "vfdiv" => Word::v_fdivide(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
             "vsum" => Word::v_sum(p_reg(&args[0]), p_reg(&args[1]), p_v_reg(&args[2])),
+            "vlen" => Word::v_length(p_reg(&args[0])),
+            "vbcast" => Word::v_broadcast(p_v_reg(&args[0]), p_reg(&args[1])),
+            "vext" => Word::v_extract(p_reg(&args[0]), p_v_reg(&args[1]), p_i32(&args[2])),
+            "vins" => Word::v_insert(p_v_reg(&args[0]), p_reg(&args[1]), p_i32(&args[2])),
+            "vshuf" => Word::v_shuffle(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vmin" => Word::v_min(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vmax" => Word::v_max(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vfmin" => Word::v_fmin(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vfmax" => Word::v_fmax(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vabs" => Word::v_abs(p_v_reg(&args[0]), p_v_reg(&args[1])),
+            "vfabs" => Word::v_fabs(p_v_reg(&args[0]), p_v_reg(&args[1])),
+            "vcmpeq" => {
+                Word::v_compare_equal(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vcmplt" => {
+                Word::v_compare_less(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vcmple" => {
+                Word::v_compare_less_equal(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfcmpeq" => {
+                Word::v_fcompare_equal(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfcmplt" => {
+                Word::v_fcompare_less(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfcmple" => {
+                Word::v_fcompare_less_equal(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vand" => Word::v_and(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vor" => Word::v_or(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vblend" => Word::v_blend(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vaddm" => Word::v_add_masked(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2])),
+            "vsubm" => {
+                Word::v_subtract_masked(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vmultm" => {
+                Word::v_multiply_masked(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfaddm" => {
+                Word::v_fadd_masked(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfsubm" => {
+                Word::v_fsubtract_masked(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfmultm" => {
+                Word::v_fmultiply_masked(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfma" => {
+                Word::v_fmultiply_add(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
+            "vfms" => {
+                Word::v_fmultiply_subtract(p_v_reg(&args[0]), p_v_reg(&args[1]), p_v_reg(&args[2]))
+            }
             "mfhi" => Word::move_from_high(p_reg(&args[0])),
             "mflo" => Word::move_from_low(p_reg(&args[0])),
             "mv" => Word::add_immediate(p_reg(&args[0]), p_reg(&args[1]), 0),
@@ -204,9 +369,16 @@ fn create_instructions(acasm: &str, mut labels: HashMap<String, usize>) -> Vec<W
         };
 
         instructions.push(word);
+        source.push(SourceInfo {
+            line: line_number,
+            label: current_label.clone(),
+            label_offset,
+            text: line.to_string(),
+        });
+        label_offset += 1;
     }
 
-    return instructions;
+    return (instructions, source);
 }
 
 fn p_reg(reg: &str) -> u32 {
@@ -233,6 +405,10 @@ fn p_i32(immediate: &str) -> i32 {
     immediate.parse().unwrap()
 }
 
+fn p_rounding(mode: &str) -> RoundingMode {
+    RoundingMode::parse(mode).unwrap_or_else(|| panic!("{} is not a rounding mode", mode))
+}
+
 fn p_f32(immediate: &str) -> f32 {
     immediate.parse().unwrap()
 }
@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+use crate::{assembler::assemble_file, cpu::CpuConfig, cpu::CPU};
+
+/// How fast the simulator got through a program with the default configuration.
+pub struct BenchResult {
+    pub program: String,
+    pub cycles: u64,
+    pub best: Duration, // of every repeat
+}
+impl BenchResult {
+    pub fn cycles_per_second(&self) -> f64 {
+        self.cycles as f64 / self.best.as_secs_f64()
+    }
+}
+
+/// Runs each program `repeat` times, keeping the quickest so noise from the rest of the
+/// machine doesn't count.
+pub fn bench(programs: &[String], config: CpuConfig, repeat: usize) -> Vec<BenchResult> {
+    let mut results = Vec::new();
+
+    for program in programs {
+        let (memory, instructions, _) = assemble_file(program);
+        let mut best = Duration::MAX;
+        let mut cycles = 0;
+
+        for _ in 0..repeat.max(1) {
+            let mut cpu = CPU::new(config.clone());
+            cpu.set_memory(memory.clone());
+            cpu.load_program(instructions.clone())
+                .unwrap_or_else(|e| panic!("{}: {}", program, e));
+
+            let start = Instant::now();
+            cpu.run_detailed(u64::MAX);
+            best = best.min(start.elapsed());
+            cycles = cpu.stats().cycles;
+        }
+
+        results.push(BenchResult {
+            program: program.clone(),
+            cycles,
+            best,
+        });
+    }
+
+    results
+}
+
+pub fn report(results: &[BenchResult]) -> String {
+    let mut out = format!(
+        "{:<32} {:>10} {:>10} {:>14}\n",
+        "Program", "Cycles", "Seconds", "Cycles/s"
+    );
+    for result in results {
+        out += &format!(
+            "{:<32} {:>10} {:>10.3} {:>14.0}\n",
+            result.program,
+            result.cycles,
+            result.best.as_secs_f64(),
+            result.cycles_per_second()
+        );
+    }
+    out
+}
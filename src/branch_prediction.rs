@@ -1,19 +1,269 @@
-use std::collections::HashMap;
+use core::fmt::{self, Display};
+use std::{collections::VecDeque, str::FromStr};
 
-use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, ValueEnum)]
+/// Parameters shared by the table based predictors. Parsed from the options part of a
+/// predictor spec, e.g. the `hist=8,bits=3` in `local:hist=8,bits=3`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PredictorParams {
+    pub history_len: u32,
+    pub counter_bits: u32,
+    pub index_bits: u32,
+    pub tag_bits: u32,
+    /// counter state new entries start in. `None` starts them weakly biased towards
+    /// whatever the branch did the first time it was seen.
+    pub initial_state: Option<u32>,
+}
+impl PredictorParams {
+    fn new(history_len: u32, counter_bits: u32) -> Self {
+        Self {
+            history_len,
+            counter_bits,
+            index_bits: 10,
+            tag_bits: 0,
+            initial_state: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(1..=32).contains(&self.history_len) {
+            return Err(format!("hist must be 1..=32, got {}", self.history_len));
+        }
+        if !(1..=16).contains(&self.counter_bits) {
+            return Err(format!("bits must be 1..=16, got {}", self.counter_bits));
+        }
+        if !(1..=24).contains(&self.index_bits) {
+            return Err(format!("index must be 1..=24, got {}", self.index_bits));
+        }
+        if self.tag_bits > 32 {
+            return Err(format!("tag must be 0..=32, got {}", self.tag_bits));
+        }
+        if let Some(state) = self.initial_state {
+            if state >= 1 << self.counter_bits {
+                return Err(format!(
+                    "init {} does not fit in a {} bit counter",
+                    state, self.counter_bits
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// what to predict for a branch that has no entry in the table yet.
+    fn predict_missing(&self) -> bool {
+        // first prediction assumes we dont take because of loops!
+        self.initial_state
+            .is_some_and(|state| SaturatingCounter::new(state, self.counter_bits).predict())
+    }
+
+    /// the counter a new entry holds after training it on its first outcome.
+    fn first_counter(&self, taken: bool) -> SaturatingCounter {
+        match (self.initial_state, taken) {
+            (Some(state), _) => {
+                let mut counter = SaturatingCounter::new(state, self.counter_bits);
+                counter.update(taken);
+                counter
+            }
+            (None, true) => SaturatingCounter::new_taken(self.counter_bits),
+            (None, false) => SaturatingCounter::new_not_taken(self.counter_bits),
+        }
+    }
+
+    fn write_options(&self, f: &mut fmt::Formatter, with_history: bool) -> fmt::Result {
+        if with_history {
+            write!(f, "hist={},", self.history_len)?;
+        }
+        write!(
+            f,
+            "bits={},index={},tag={}",
+            self.counter_bits, self.index_bits, self.tag_bits
+        )?;
+        if let Some(state) = self.initial_state {
+            write!(f, ",init={}", state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of the loop predictor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoopParams {
+    pub index_bits: u32,
+    pub tag_bits: u32,
+    /// how many times in a row a trip count has to repeat before it overrides the base.
+    pub confidence: u32,
+    /// width of the iteration counters, so the longest loop it can learn.
+    pub iter_bits: u32,
+}
+impl LoopParams {
+    fn new() -> Self {
+        Self {
+            index_bits: 6,
+            tag_bits: 8,
+            confidence: 2,
+            iter_bits: 14,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(1..=24).contains(&self.index_bits) {
+            return Err(format!("index must be 1..=24, got {}", self.index_bits));
+        }
+        if self.tag_bits > 32 {
+            return Err(format!("tag must be 0..=32, got {}", self.tag_bits));
+        }
+        if !(1..=LOOP_MAX_CONFIDENCE).contains(&self.confidence) {
+            return Err(format!(
+                "conf must be 1..={}, got {}",
+                LOOP_MAX_CONFIDENCE, self.confidence
+            ));
+        }
+        if !(1..=31).contains(&self.iter_bits) {
+            return Err(format!("iter must be 1..=31, got {}", self.iter_bits));
+        }
+        Ok(())
+    }
+}
+
+/// splits `key=value,key=value` into its pairs.
+fn parse_options(options: &str) -> Result<Vec<(&str, u32)>, String> {
+    options
+        .split(',')
+        .map(|o| o.trim())
+        .filter(|o| !o.is_empty())
+        .map(|option| {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", option))?;
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("'{}' is not a number", value))?;
+            Ok((key.trim(), value))
+        })
+        .collect()
+}
+
+/// Which branch predictor the core uses, and how it is sized.
+///
+/// Specs are written as `kind[:key=value,...]`. The kinds are `always-take`, `never-take`,
+/// `bimodal` (per branch saturating counters), `local` (per branch history selecting a
+/// counter) and `gshare` (global history xor pc selecting a counter). The table based kinds
+/// take the options `bits`, `index`, `tag` and `init`, and `local` and `gshare` also `hist`. The older names
+/// `one-bit-saturating`, `two-bit-saturating` and `five-bit-history` still work.
+///
+/// A loop predictor can be put in front of any of them with `loop[:options]+base`, e.g.
+/// `loop:conf=3+gshare:hist=12`. It takes `index`, `tag`, `conf` and `iter`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BranchPredictionMode {
     AlwaysTake,
     NeverTake,
-    OneBitSaturating,
-    TwoBitSaturating,
-    FiveBitHistory,
-    // HistoryTwoBitSaturating(u32),
+    Bimodal(PredictorParams),
+    LocalHistory(PredictorParams),
+    GShare(PredictorParams),
+    Loop(LoopParams, Box<BranchPredictionMode>),
 }
+impl FromStr for BranchPredictionMode {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+
+        if spec == "loop" || spec.starts_with("loop:") || spec.starts_with("loop+") {
+            let (loop_spec, base_spec) = spec
+                .split_once('+')
+                .ok_or("the loop predictor needs a base predictor, e.g. loop+gshare")?;
+            let (_, options) = loop_spec.split_once(':').unwrap_or((loop_spec, ""));
+
+            let mut params = LoopParams::new();
+            for (key, value) in parse_options(options)? {
+                match key {
+                    "index" => params.index_bits = value,
+                    "tag" => params.tag_bits = value,
+                    "conf" => params.confidence = value,
+                    "iter" => params.iter_bits = value,
+                    other => return Err(format!("unknown loop predictor option '{}'", other)),
+                }
+            }
+            params.validate()?;
+
+            return Ok(Self::Loop(params, Box::new(base_spec.parse()?)));
+        }
+
+        let (kind, options) = spec.split_once(':').unwrap_or((spec, ""));
+
+        let mut mode = match kind {
+            "always-take" => Self::AlwaysTake,
+            "never-take" => Self::NeverTake,
+            "bimodal" | "two-bit-saturating" => Self::Bimodal(PredictorParams::new(1, 2)),
+            "one-bit-saturating" => Self::Bimodal(PredictorParams::new(1, 1)),
+            "local" | "five-bit-history" => Self::LocalHistory(PredictorParams::new(5, 2)),
+            "gshare" => Self::GShare(PredictorParams::new(10, 2)),
+            other => return Err(format!("unknown branch predictor '{}'", other)),
+        };
+
+        let takes_history = !matches!(mode, Self::Bimodal(_));
+        let params = match &mut mode {
+            Self::AlwaysTake | Self::NeverTake | Self::Loop(_, _) => None,
+            Self::Bimodal(params) | Self::LocalHistory(params) | Self::GShare(params) => {
+                Some(params)
+            }
+        };
 
-#[derive(Debug)]
+        let options = parse_options(options)?;
+        match params {
+            Some(params) => {
+                for (key, value) in options {
+                    match key {
+                        "hist" if takes_history => params.history_len = value,
+                        "hist" => return Err(format!("'{}' does not keep any history", kind)),
+                        "bits" => params.counter_bits = value,
+                        "index" => params.index_bits = value,
+                        "tag" => params.tag_bits = value,
+                        "init" => params.initial_state = Some(value),
+                        other => return Err(format!("unknown predictor option '{}'", other)),
+                    }
+                }
+                params.validate()?;
+            }
+            None => {
+                if !options.is_empty() {
+                    return Err(format!("'{}' does not take any options", kind));
+                }
+            }
+        }
+
+        Ok(mode)
+    }
+}
+impl Display for BranchPredictionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::AlwaysTake => write!(f, "always-take"),
+            Self::NeverTake => write!(f, "never-take"),
+            Self::Bimodal(params) => {
+                write!(f, "bimodal:")?;
+                params.write_options(f, false)
+            }
+            Self::LocalHistory(params) => {
+                write!(f, "local:")?;
+                params.write_options(f, true)
+            }
+            Self::GShare(params) => {
+                write!(f, "gshare:")?;
+                params.write_options(f, true)
+            }
+            Self::Loop(params, base) => write!(
+                f,
+                "loop:index={},tag={},conf={},iter={}+{}",
+                params.index_bits, params.tag_bits, params.confidence, params.iter_bits, base
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct SaturatingCounter {
     state: u32,
     bits: u32,
@@ -39,15 +289,120 @@ impl SaturatingCounter {
         self.state = self.state.saturating_sub(1);
     }
 
+    pub fn update(&mut self, taken: bool) {
+        if taken {
+            self.update_taken();
+        } else {
+            self.update_not_taken();
+        }
+    }
+
     pub fn predict(&self) -> bool {
         self.state >= 1 << (self.bits - 1)
     }
 }
 
+/// A direct mapped table of predictor state. Keys are xor-folded down to `index_bits` bits
+/// so different branches can alias onto the same entry like they would in hardware. When
+/// `tag_bits` is non zero the bits above the index are kept as a partial tag and a
+/// mismatching tag is treated as a miss.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PredictorTable<T> {
+    index_bits: u32,
+    tag_bits: u32,
+    entries: Vec<Option<(usize, T)>>, // (tag, state)
+}
+impl<T: Clone> PredictorTable<T> {
+    pub fn new(index_bits: u32, tag_bits: u32) -> Self {
+        assert!(
+            (1..=24).contains(&index_bits),
+            "Predictor tables need between 1 and 24 index bits!"
+        );
+
+        Self {
+            index_bits,
+            tag_bits,
+            entries: vec![None; 1 << index_bits],
+        }
+    }
+
+    fn index(&self, key: usize) -> usize {
+        let mask = (1 << self.index_bits) - 1;
+        let mut key = key;
+        let mut index = 0;
+        while key != 0 {
+            index ^= key & mask;
+            key >>= self.index_bits;
+        }
+        index
+    }
+
+    fn tag(&self, key: usize) -> usize {
+        (key >> self.index_bits) & ((1 << self.tag_bits) - 1)
+    }
+
+    pub fn get(&self, key: usize) -> Option<&T> {
+        let tag = self.tag(key);
+        match &self.entries[self.index(key)] {
+            Some((entry_tag, state)) if *entry_tag == tag => Some(state),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        let tag = self.tag(key);
+        let index = self.index(key);
+        match &mut self.entries[index] {
+            Some((entry_tag, state)) if *entry_tag == tag => Some(state),
+            _ => None,
+        }
+    }
+
+    /// replaces whatever is in the entry `key` maps to.
+    pub fn insert(&mut self, key: usize, state: T) {
+        let index = self.index(key);
+        self.entries[index] = Some((self.tag(key), state));
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entries
+            .iter_mut()
+            .filter_map(|entry| entry.as_mut().map(|(_, state)| state))
+    }
+
+    /// bits needed to build this table when each entry holds `state_bits` of state.
+    /// tagged tables also need a valid bit per entry.
+    pub fn storage_bits(&self, state_bits: u64) -> u64 {
+        let tag_bits = match self.tag_bits {
+            0 => 0,
+            tag_bits => tag_bits as u64 + 1,
+        };
+        self.entries.len() as u64 * (state_bits + tag_bits)
+    }
+}
+
 pub trait BranchPredictor {
     fn predict(&mut self, pc: usize) -> bool;
     fn update(&mut self, pc: usize, taken: bool);
     fn flush(&mut self) {}
+    /// How many bits of state this predictor would need in hardware.
+    fn storage_bits(&self) -> u64;
+    /// Extra counters specific to this predictor, reported alongside the program stats.
+    fn component_stats(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+    /// The predictor's tables and history, for checkpoints.
+    fn save(&self) -> Vec<u8>;
+    /// Takes back state from `save` on a predictor built with the same mode.
+    fn restore(&mut self, state: &[u8]);
+}
+
+fn save_state<T: Serialize>(state: &T) -> Vec<u8> {
+    bincode::serialize(state).expect("couldn't save branch predictor")
+}
+
+fn restore_state<T: DeserializeOwned>(state: &[u8]) -> T {
+    bincode::deserialize(state).expect("branch predictor state is from a different mode")
 }
 
 pub struct CoreBranchPredictor {
@@ -55,20 +410,26 @@ pub struct CoreBranchPredictor {
 }
 impl CoreBranchPredictor {
     pub fn new(mode: BranchPredictionMode) -> Self {
-        let bp: Box<dyn BranchPredictor> = match mode {
+        Self {
+            bp: Self::build(mode),
+        }
+    }
+
+    fn build(mode: BranchPredictionMode) -> Box<dyn BranchPredictor> {
+        match mode {
             BranchPredictionMode::AlwaysTake => Box::new(StaticBranchPredictor::new(true)),
             BranchPredictionMode::NeverTake => Box::new(StaticBranchPredictor::new(false)),
-            BranchPredictionMode::OneBitSaturating => Box::new(SaturatingBranchPredictor::new(1)),
-            BranchPredictionMode::TwoBitSaturating => Box::new(SaturatingBranchPredictor::new(2)),
-            // BranchPredictionMode::HistoryTwoBitSaturating(history_len) => {
-            // Box::new(HistoryTwoBitSaturatingPredictor::new(history_len))
-            // }
-            BranchPredictionMode::FiveBitHistory => {
-                Box::new(HistoryTwoBitSaturatingPredictor::new(5))
+            BranchPredictionMode::Bimodal(params) => {
+                Box::new(SaturatingBranchPredictor::new(params))
             }
-        };
-
-        Self { bp }
+            BranchPredictionMode::LocalHistory(params) => {
+                Box::new(LocalHistoryPredictor::new(params))
+            }
+            BranchPredictionMode::GShare(params) => Box::new(GShareBranchPredictor::new(params)),
+            BranchPredictionMode::Loop(params, base) => {
+                Box::new(LoopPredictor::new(params, Self::build(*base)))
+            }
+        }
     }
 }
 impl BranchPredictor for CoreBranchPredictor {
@@ -83,8 +444,25 @@ impl BranchPredictor for CoreBranchPredictor {
     fn flush(&mut self) {
         self.bp.flush();
     }
+
+    fn storage_bits(&self) -> u64 {
+        self.bp.storage_bits()
+    }
+
+    fn component_stats(&self) -> Vec<(&'static str, u64)> {
+        self.bp.component_stats()
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.bp.save()
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        self.bp.restore(state);
+    }
 }
 
+#[derive(Serialize, Deserialize)]
 struct StaticBranchPredictor {
     should_take: bool,
 }
@@ -101,101 +479,468 @@ impl BranchPredictor for StaticBranchPredictor {
     fn update(&mut self, _pc: usize, _taken: bool) {
         // nothing to update, dont do anything
     }
+
+    fn storage_bits(&self) -> u64 {
+        0
+    }
+
+    fn save(&self) -> Vec<u8> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        *self = restore_state(state);
+    }
+}
+
+/// trains the counter `key` maps to, allocating it if the table missed.
+fn train_counter(
+    table: &mut PredictorTable<SaturatingCounter>,
+    params: &PredictorParams,
+    key: usize,
+    taken: bool,
+) {
+    match table.get_mut(key) {
+        Some(counter) => counter.update(taken),
+        None => table.insert(key, params.first_counter(taken)),
+    }
+}
+
+/// shifts `taken` into the bottom of a `len` bit history register.
+fn push_history(history: u32, taken: bool, len: u32) -> u32 {
+    (((history as u64) << 1 | taken as u64) & ((1 << len) - 1)) as u32
 }
 
+#[derive(Serialize, Deserialize)]
 struct SaturatingBranchPredictor {
-    bits: u32,
-    state_machines: HashMap<usize, SaturatingCounter>, // pc -> state machine
+    params: PredictorParams,
+    state_machines: PredictorTable<SaturatingCounter>, // pc -> state machine
 }
 impl SaturatingBranchPredictor {
-    fn new(bits: u32) -> Self {
+    fn new(params: PredictorParams) -> Self {
         Self {
-            bits,
-            state_machines: HashMap::new(),
+            params,
+            state_machines: PredictorTable::new(params.index_bits, params.tag_bits),
         }
     }
 }
 impl BranchPredictor for SaturatingBranchPredictor {
     fn predict(&mut self, pc: usize) -> bool {
-        self.state_machines.get(&pc).map_or(
-            // first prediction assumes we dont take because of loops!
-            false,
-            |s| s.predict(),
-        )
+        self.state_machines
+            .get(pc)
+            .map_or_else(|| self.params.predict_missing(), |s| s.predict())
     }
 
     fn update(&mut self, pc: usize, taken: bool) {
-        let state = self.state_machines.get_mut(&pc);
+        train_counter(&mut self.state_machines, &self.params, pc, taken);
+    }
 
-        match (state, taken) {
-            (Some(state), true) => state.update_taken(),
-            (Some(state), false) => state.update_not_taken(),
-            (None, true) => {
-                self.state_machines
-                    .insert(pc, SaturatingCounter::new_taken(self.bits));
-            }
-            (None, false) => {
-                self.state_machines
-                    .insert(pc, SaturatingCounter::new_not_taken(self.bits));
-            }
-        };
+    fn storage_bits(&self) -> u64 {
+        self.state_machines
+            .storage_bits(self.params.counter_bits as u64)
+    }
+
+    fn save(&self) -> Vec<u8> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        *self = restore_state(state);
     }
 }
 
-struct HistoryTwoBitSaturatingPredictor {
-    history_len: u32,
-    spec_history: HashMap<usize, u32>, // pc -> history
-    lhr: HashMap<usize, u32>,          // pc -> history
-    histories: HashMap<(usize, u32), SaturatingCounter>, // (pc, hr) -> predictor
+/// Two level predictor where each branch keeps its own history, and that history picks
+/// which counter predicts it.
+#[derive(Serialize, Deserialize)]
+struct LocalHistoryPredictor {
+    params: PredictorParams,
+    spec_history: PredictorTable<u32>,            // pc -> history
+    lhr: PredictorTable<u32>,                     // pc -> history
+    histories: PredictorTable<SaturatingCounter>, // (pc, hr) -> predictor
 }
-impl HistoryTwoBitSaturatingPredictor {
-    pub fn new(history_len: u32) -> Self {
+impl LocalHistoryPredictor {
+    pub fn new(params: PredictorParams) -> Self {
         Self {
-            history_len,
-            spec_history: HashMap::new(),
-            lhr: HashMap::new(),
-            histories: HashMap::new(),
+            params,
+            spec_history: PredictorTable::new(params.index_bits, params.tag_bits),
+            lhr: PredictorTable::new(params.index_bits, params.tag_bits),
+            histories: PredictorTable::new(params.index_bits, params.tag_bits),
         }
     }
+
+    fn pattern_key(&self, pc: usize, history: u32) -> usize {
+        (pc << self.params.history_len) | history as usize
+    }
 }
-impl BranchPredictor for HistoryTwoBitSaturatingPredictor {
+impl BranchPredictor for LocalHistoryPredictor {
     fn flush(&mut self) {
         self.spec_history = self.lhr.clone();
     }
 
     fn predict(&mut self, pc: usize) -> bool {
-        let mut spec_history = *self.spec_history.get(&pc).unwrap_or(&0);
-        let counter = self.histories.get(&(pc, spec_history));
-        let prediction = counter.map_or(false, |c| c.predict());
+        let spec_history = *self.spec_history.get(pc).unwrap_or(&0);
+        let prediction = self
+            .histories
+            .get(self.pattern_key(pc, spec_history))
+            .map_or_else(|| self.params.predict_missing(), |c| c.predict());
+
+        self.spec_history.insert(
+            pc,
+            push_history(spec_history, prediction, self.params.history_len),
+        );
+
+        return prediction;
+    }
+
+    fn update(&mut self, pc: usize, taken: bool) {
+        let history = *self.lhr.get(pc).unwrap_or(&0);
+        let key = self.pattern_key(pc, history);
+        train_counter(&mut self.histories, &self.params, key, taken);
+
+        self.lhr
+            .insert(pc, push_history(history, taken, self.params.history_len));
+    }
+
+    fn storage_bits(&self) -> u64 {
+        let history_len = self.params.history_len as u64;
+        // the speculative history is a second copy of the history table
+        self.spec_history.storage_bits(history_len)
+            + self.lhr.storage_bits(history_len)
+            + self.histories.storage_bits(self.params.counter_bits as u64)
+    }
+
+    fn save(&self) -> Vec<u8> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        *self = restore_state(state);
+    }
+}
+
+/// Two level predictor with one history register shared by every branch, xored with the pc
+/// to pick a counter.
+#[derive(Serialize, Deserialize)]
+struct GShareBranchPredictor {
+    params: PredictorParams,
+    spec_history: u32,
+    ghr: u32,
+    histories: PredictorTable<SaturatingCounter>, // pc ^ ghr -> predictor
+}
+impl GShareBranchPredictor {
+    pub fn new(params: PredictorParams) -> Self {
+        Self {
+            params,
+            spec_history: 0,
+            ghr: 0,
+            histories: PredictorTable::new(params.index_bits, params.tag_bits),
+        }
+    }
+}
+impl BranchPredictor for GShareBranchPredictor {
+    fn flush(&mut self) {
+        self.spec_history = self.ghr;
+    }
+
+    fn predict(&mut self, pc: usize) -> bool {
+        let prediction = self
+            .histories
+            .get(pc ^ self.spec_history as usize)
+            .map_or_else(|| self.params.predict_missing(), |c| c.predict());
+
+        self.spec_history = push_history(self.spec_history, prediction, self.params.history_len);
+
+        return prediction;
+    }
+
+    fn update(&mut self, pc: usize, taken: bool) {
+        train_counter(
+            &mut self.histories,
+            &self.params,
+            pc ^ self.ghr as usize,
+            taken,
+        );
+
+        self.ghr = push_history(self.ghr, taken, self.params.history_len);
+    }
+
+    fn storage_bits(&self) -> u64 {
+        // committed and speculative global history registers
+        2 * self.params.history_len as u64
+            + self.histories.storage_bits(self.params.counter_bits as u64)
+    }
+
+    fn save(&self) -> Vec<u8> {
+        save_state(self)
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        *self = restore_state(state);
+    }
+}
+
+const LOOP_MAX_CONFIDENCE: u32 = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LoopEntry {
+    loop_direction: bool, // the outcome that keeps the loop going
+    trip_count: u32,      // how many times loop_direction happens before the exit
+    iteration: u32,       // committed iterations of the current trip
+    spec_iteration: u32,  // iterations including ones predicted but not yet committed
+    confidence: u32,
+}
+
+/// Learns the trip count of counted loops and predicts their exit, which counter based
+/// predictors always get wrong. Once a trip count has repeated `confidence` times it
+/// overrides the base predictor, otherwise the base prediction is used.
+struct LoopPredictor {
+    params: LoopParams,
+    base: Box<dyn BranchPredictor>,
+    entries: PredictorTable<LoopEntry>,        // pc -> loop
+    in_flight: VecDeque<(Option<bool>, bool)>, // (loop prediction if it overrode, base prediction)
+    overrides: u64,
+    overrides_correct: u64,
+    overrides_fixing_base: u64,
+}
+impl LoopPredictor {
+    fn new(params: LoopParams, base: Box<dyn BranchPredictor>) -> Self {
+        Self {
+            params,
+            base,
+            entries: PredictorTable::new(params.index_bits, params.tag_bits),
+            in_flight: VecDeque::new(),
+            overrides: 0,
+            overrides_correct: 0,
+            overrides_fixing_base: 0,
+        }
+    }
+}
+impl BranchPredictor for LoopPredictor {
+    fn flush(&mut self) {
+        self.base.flush();
+        self.in_flight.clear();
+        for entry in self.entries.values_mut() {
+            entry.spec_iteration = entry.iteration;
+        }
+    }
+
+    fn predict(&mut self, pc: usize) -> bool {
+        let base_prediction = self.base.predict(pc);
+        let confidence = self.params.confidence;
 
-        spec_history = ((spec_history << 1) | (prediction as u32)) << (32 - self.history_len)
-            >> (32 - self.history_len);
+        let loop_prediction = self.entries.get(pc).and_then(|entry| {
+            (entry.confidence >= confidence).then_some(if entry.spec_iteration < entry.trip_count {
+                entry.loop_direction
+            } else {
+                !entry.loop_direction
+            })
+        });
+        let prediction = loop_prediction.unwrap_or(base_prediction);
 
-        self.spec_history.insert(pc, spec_history);
+        // keep following the loop speculatively whichever prediction won
+        if let Some(entry) = self.entries.get_mut(pc) {
+            if prediction == entry.loop_direction {
+                entry.spec_iteration += 1;
+            } else {
+                entry.spec_iteration = 0;
+            }
+        }
 
+        self.in_flight.push_back((loop_prediction, base_prediction));
         return prediction;
     }
 
     fn update(&mut self, pc: usize, taken: bool) {
-        let mut history = *self.lhr.get(&pc).unwrap_or(&0);
-        let state = self.histories.get_mut(&(pc, history));
+        self.base.update(pc, taken);
 
-        match (state, taken) {
-            (Some(state), true) => state.update_taken(),
-            (Some(state), false) => state.update_not_taken(),
-            (None, true) => {
-                self.histories
-                    .insert((pc, history), SaturatingCounter::new_taken(2));
+        if let Some((Some(loop_prediction), base_prediction)) = self.in_flight.pop_front() {
+            self.overrides += 1;
+            if loop_prediction == taken {
+                self.overrides_correct += 1;
+                if base_prediction != taken {
+                    self.overrides_fixing_base += 1;
+                }
             }
-            (None, false) => {
-                self.histories
-                    .insert((pc, history), SaturatingCounter::new_not_taken(2));
+        }
+
+        let max_iteration = (1 << self.params.iter_bits) - 1;
+        match self.entries.get_mut(pc) {
+            Some(entry) if taken == entry.loop_direction => {
+                entry.iteration += 1;
+                if entry.iteration > max_iteration {
+                    // too long to learn, give the entry up
+                    entry.iteration = 0;
+                    entry.trip_count = 0;
+                    entry.confidence = 0;
+                } else if entry.iteration > entry.trip_count {
+                    entry.confidence = 0;
+                }
             }
-        };
+            Some(entry) if entry.iteration == 0 => {
+                // exited without ever looping, we guessed the loop direction wrong
+                entry.loop_direction = taken;
+                entry.iteration = 1;
+                entry.trip_count = 0;
+                entry.confidence = 0;
+            }
+            Some(entry) => {
+                if entry.iteration == entry.trip_count {
+                    entry.confidence = (entry.confidence + 1).min(LOOP_MAX_CONFIDENCE);
+                } else {
+                    entry.trip_count = entry.iteration;
+                    entry.confidence = 0;
+                }
+                entry.iteration = 0;
+            }
+            None => self.entries.insert(
+                pc,
+                LoopEntry {
+                    loop_direction: taken,
+                    trip_count: 0,
+                    iteration: 1,
+                    spec_iteration: 1,
+                    confidence: 0,
+                },
+            ),
+        }
+    }
+
+    fn storage_bits(&self) -> u64 {
+        let iter_bits = self.params.iter_bits as u64;
+        // direction, 3 bit confidence, and trip/iteration/speculative iteration counters
+        self.entries.storage_bits(1 + 3 + 3 * iter_bits) + self.base.storage_bits()
+    }
+
+    fn component_stats(&self) -> Vec<(&'static str, u64)> {
+        let mut stats = vec![
+            ("Loop Predictor Overrides", self.overrides),
+            ("Loop Predictor Overrides Correct", self.overrides_correct),
+            (
+                "Loop Predictor Overrides Fixing Base",
+                self.overrides_fixing_base,
+            ),
+        ];
+        stats.append(&mut self.base.component_stats());
+        stats
+    }
+
+    fn save(&self) -> Vec<u8> {
+        save_state(&(
+            &self.entries,
+            &self.in_flight,
+            self.overrides,
+            self.overrides_correct,
+            self.overrides_fixing_base,
+            self.base.save(),
+        ))
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        let base;
+        (
+            self.entries,
+            self.in_flight,
+            self.overrides,
+            self.overrides_correct,
+            self.overrides_fixing_base,
+            base,
+        ) = restore_state::<(_, _, _, _, _, Vec<u8>)>(state);
+        self.base.restore(&base);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip_through_their_specs() {
+        for spec in [
+            "always-take",
+            "never-take",
+            "bimodal:bits=3,index=8,tag=0",
+            "local:hist=8,bits=3,index=10,tag=4,init=2",
+            "gshare:hist=12,bits=2,index=12,tag=0",
+            "loop:index=6,tag=8,conf=3,iter=10+gshare:hist=12,bits=2,index=12,tag=0",
+        ] {
+            let mode: BranchPredictionMode = spec.parse().unwrap();
+            assert_eq!(mode.to_string(), spec);
+            assert_eq!(mode.to_string().parse::<BranchPredictionMode>(), Ok(mode));
+        }
+
+        assert_eq!(
+            "two-bit-saturating".parse(),
+            Ok(BranchPredictionMode::Bimodal(PredictorParams::new(1, 2)))
+        );
+        assert_eq!(
+            "local:bits=3".parse(),
+            Ok(BranchPredictionMode::LocalHistory(PredictorParams {
+                counter_bits: 3,
+                ..PredictorParams::new(5, 2)
+            }))
+        );
+    }
+
+    #[test]
+    fn bad_specs_are_rejected() {
+        for spec in [
+            "tournament",
+            "gshare:hist",
+            "gshare:hist=x",
+            "gshare:size=4",
+            "gshare:hist=0",
+            "local:bits=17",
+            "bimodal:bits=2,init=4",
+            "bimodal:hist=4",
+            "one-bit-saturating:hist=4",
+            "always-take:bits=2",
+            "loop",
+            "loop:conf=8+gshare",
+            "loop:hist=3+gshare",
+            "loop+tournament",
+        ] {
+            assert!(
+                spec.parse::<BranchPredictionMode>().is_err(),
+                "{} was accepted",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn loop_predictor_learns_a_trip_count_and_overrides_its_base() {
+        let mode = "loop:conf=2+bimodal".parse().unwrap();
+        let mut bp = CoreBranchPredictor::new(mode);
+        let pc = 0x40;
+        // taken 5 times then falls through, which a counter always gets wrong
+        let trip = [true, true, true, true, true, false];
+
+        let mut mispredicted = Vec::new();
+        for _ in 0..6 {
+            let mut wrong = 0;
+            for &taken in trip.iter() {
+                let predicted = bp.predict(pc);
+                bp.update(pc, taken);
+                // like the core, which flushes once the mispredicted branch commits
+                if predicted != taken {
+                    wrong += 1;
+                    bp.flush();
+                }
+            }
+            mispredicted.push(wrong);
+        }
 
-        history =
-            ((history << 1) | (taken as u32)) << (32 - self.history_len) >> (32 - self.history_len);
+        // the counter misses every exit until the same trip count has been seen twice more
+        assert_eq!(mispredicted[1..], [1, 1, 0, 0, 0]);
 
-        self.lhr.insert(pc, history);
+        let stats = bp.component_stats();
+        let stat = |name| stats.iter().find(|(n, _)| *n == name).unwrap().1;
+        assert_eq!(stat("Loop Predictor Overrides"), 3 * trip.len() as u64);
+        assert_eq!(
+            stat("Loop Predictor Overrides Correct"),
+            3 * trip.len() as u64
+        );
+        // only the exits, the counter already gets the rest right
+        assert_eq!(stat("Loop Predictor Overrides Fixing Base"), 3);
     }
 }
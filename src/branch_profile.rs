@@ -0,0 +1,102 @@
+use std::{collections::HashMap, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::assembler::SourceInfo;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BranchRecord {
+    pub executions: u64,
+    pub taken: u64,
+    pub mispredictions: u64,
+}
+impl BranchRecord {
+    pub fn taken_rate(&self) -> f64 {
+        100.0 * self.taken as f64 / self.executions as f64
+    }
+
+    pub fn mispredict_rate(&self) -> f64 {
+        100.0 * self.mispredictions as f64 / self.executions as f64
+    }
+}
+
+/// Committed outcomes of every static branch, keyed by pc.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BranchProfile {
+    records: HashMap<usize, BranchRecord>,
+}
+impl BranchProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, pc: usize, taken: bool, mispredicted: bool) {
+        let record = self.records.entry(pc).or_default();
+        record.executions += 1;
+        record.taken += taken as u64;
+        record.mispredictions += mispredicted as u64;
+    }
+
+    /// branches with the most mispredictions first.
+    pub fn sorted(&self) -> Vec<(usize, BranchRecord)> {
+        let mut records: Vec<(usize, BranchRecord)> =
+            self.records.iter().map(|(pc, r)| (*pc, *r)).collect();
+        records.sort_by(|(pc_a, a), (pc_b, b)| {
+            b.mispredictions
+                .cmp(&a.mispredictions)
+                .then(b.executions.cmp(&a.executions))
+                .then(pc_a.cmp(pc_b))
+        });
+        records
+    }
+
+    pub fn report(&self, source: &[SourceInfo]) -> String {
+        let mut out = String::from("Branch profile:\n");
+        out += &format!(
+            "{:>6} {:>6}  {:<24} {:>10} {:>7} {:>10} {:>9}\n",
+            "PC", "Line", "Location", "Executed", "Taken%", "Mispred", "Mispred%"
+        );
+
+        for (pc, record) in self.sorted() {
+            let (line, location) = source.get(pc).map_or((String::new(), String::new()), |s| {
+                (s.line.to_string(), s.location())
+            });
+            out += &format!(
+                "{:>6} {:>6}  {:<24} {:>10} {:>7.2} {:>10} {:>9.2}\n",
+                pc,
+                line,
+                location,
+                record.executions,
+                record.taken_rate(),
+                record.mispredictions,
+                record.mispredict_rate()
+            );
+        }
+
+        out
+    }
+
+    pub fn write_csv(&self, path: &str, source: &[SourceInfo]) -> io::Result<()> {
+        let mut out = String::from(
+            "pc,line,location,source,executions,taken,taken_rate,mispredictions,mispredict_rate\n",
+        );
+
+        for (pc, record) in self.sorted() {
+            let info = source.get(pc);
+            out += &format!(
+                "{},{},{},\"{}\",{},{},{:.4},{},{:.4}\n",
+                pc,
+                info.map_or(String::new(), |s| s.line.to_string()),
+                info.map_or(String::new(), |s| s.location()),
+                info.map_or(String::new(), |s| s.text.replace('"', "\"\"")),
+                record.executions,
+                record.taken,
+                record.taken_rate(),
+                record.mispredictions,
+                record.mispredict_rate()
+            );
+        }
+
+        fs::write(path, out)
+    }
+}
@@ -0,0 +1,203 @@
+use core::fmt::{self, Display};
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::branch_prediction::{BranchPredictionMode, BranchPredictor, CoreBranchPredictor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BranchTraceRecord {
+    pub pc: usize,
+    pub taken: bool,
+    pub target: usize,
+}
+
+/// Every conditional branch the program committed, in program order. Saved as text with one
+/// `pc taken target` line per branch after an `instructions n` line giving how many
+/// instructions the program committed in total.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BranchTrace {
+    pub records: Vec<BranchTraceRecord>,
+    pub instructions: u64,
+}
+impl BranchTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, pc: usize, taken: bool, target: usize) {
+        self.records.push(BranchTraceRecord { pc, taken, target });
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let mut out = String::from("# aca branch trace: pc taken target\n");
+        out += &format!("instructions {}\n", self.instructions);
+        for record in &self.records {
+            out += &format!("{} {} {}\n", record.pc, record.taken as u8, record.target);
+        }
+
+        fs::write(path, out)
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut trace = Self::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = || format!("{}:{}: malformed trace line '{}'", path, i + 1, line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            match fields[..] {
+                ["instructions", count] => {
+                    trace.instructions = count.parse().map_err(|_| bad_line())?;
+                }
+                [pc, taken, target] => trace.record(
+                    pc.parse().map_err(|_| bad_line())?,
+                    match taken {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(bad_line()),
+                    },
+                    target.parse().map_err(|_| bad_line())?,
+                ),
+                _ => return Err(bad_line()),
+            }
+        }
+
+        Ok(trace)
+    }
+
+    /// Runs the trace through a fresh predictor. Every branch is predicted then
+    /// immediately trained on its outcome, and flushed if it was wrong, so unlike in the
+    /// pipeline no prediction is made with older branches still unresolved.
+    pub fn replay(&self, mode: BranchPredictionMode) -> ReplayResult {
+        let mut branch_predictor = CoreBranchPredictor::new(mode.clone());
+        let mut mispredictions = 0;
+
+        for record in &self.records {
+            let predicted = branch_predictor.predict(record.pc);
+            branch_predictor.update(record.pc, record.taken);
+            // the pipeline throws away what it fetched after a mispredicted branch
+            if predicted != record.taken {
+                mispredictions += 1;
+                branch_predictor.flush();
+            }
+        }
+
+        ReplayResult {
+            mode,
+            branches: self.records.len() as u64,
+            mispredictions,
+            instructions: self.instructions,
+            storage_bits: branch_predictor.storage_bits(),
+            component_stats: branch_predictor.component_stats(),
+        }
+    }
+}
+
+pub struct ReplayResult {
+    pub mode: BranchPredictionMode,
+    pub branches: u64,
+    pub mispredictions: u64,
+    pub instructions: u64,
+    pub storage_bits: u64,
+    pub component_stats: Vec<(&'static str, u64)>,
+}
+impl ReplayResult {
+    pub fn accuracy(&self) -> f64 {
+        100.0 * (self.branches - self.mispredictions) as f64 / self.branches as f64
+    }
+
+    /// mispredictions per thousand instructions
+    pub fn mpki(&self) -> f64 {
+        1000.0 * self.mispredictions as f64 / self.instructions as f64
+    }
+}
+impl Display for ReplayResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}:", self.mode)?;
+        writeln!(f, " - Branches: {}", self.branches)?;
+        writeln!(f, " - Mispredictions: {}", self.mispredictions)?;
+        writeln!(f, " - Accuracy: {:.2}", self.accuracy())?;
+        writeln!(f, " - MPKI: {:.3}", self.mpki())?;
+        writeln!(f, " - Storage: {} bits", self.storage_bits)?;
+        for (name, value) in &self.component_stats {
+            writeln!(f, " - {}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::{
+        assembler::assemble_source,
+        cpu::{CpuConfig, CPU},
+    };
+
+    /// a loop nest with a fixed inner trip count and an inner branch that alternates
+    const LOOPS: &str = "
+.instructions
+    main:
+        li $1 0
+        li $5 0
+        li $6 0
+    outer:
+        li $2 0
+    inner:
+        andi $3 $2 1
+        be $3 $6 skip
+        addi $5 $5 1
+    skip:
+        addi $2 $2 1
+        li $4 6
+        bl $2 $4 inner
+        addi $1 $1 1
+        li $4 20
+        bl $1 $4 outer
+        exit $5
+";
+
+    #[test]
+    fn replaying_a_written_trace_mispredicts_like_the_core() {
+        let path = env::temp_dir().join(format!("aca-branch-trace-{}.txt", process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let (memory, instructions, _) = assemble_source(LOOPS);
+
+        for spec in ["never-take", "bimodal", "loop+bimodal"] {
+            let mode: BranchPredictionMode = spec.parse().unwrap();
+            let mut cpu = CPU::new(CpuConfig {
+                branch_predictor_mode: mode.clone(),
+                record_branch_trace: true,
+                ..CpuConfig::default()
+            });
+            cpu.set_memory(memory.clone());
+            cpu.load_program(instructions.clone()).unwrap();
+            cpu.run_detailed(u64::MAX);
+            let stats = cpu.finish();
+
+            let trace = stats.branch_trace.unwrap();
+            trace.write(&path).unwrap();
+            let read = BranchTrace::read(&path).unwrap();
+            assert_eq!(read.records, trace.records);
+            assert_eq!(read.instructions, stats.instructions_commited);
+
+            let replayed = read.replay(mode);
+            assert_eq!(replayed.branches, stats.committed_predicted_branches);
+            assert_eq!(
+                replayed.mispredictions, stats.committed_mispredicions,
+                "{}",
+                spec
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs;
+
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commiter::FlushCause, cpu::CpuConfig, dispatcher::Dispatcher, execution_units::ExecutionUnit,
+    fetcher::Fetcher, instructions::Word, register_alias_table::RegisterAliasTable,
+    registers::Registers, reorder_buffer::ReorderBuffer, reservation_station::ReservationStation,
+    stats::StatsTracker,
+};
+
+const MAGIC: &[u8] = b"aca checkpoint 2\n";
+
+/// When to take a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckpointAt {
+    Cycle(u64),
+    Instruction(u64), // once this many have committed
+}
+impl CheckpointAt {
+    pub fn reached(&self, stats: &StatsTracker) -> bool {
+        match self {
+            Self::Cycle(cycle) => stats.cycles >= *cycle,
+            Self::Instruction(instructions) => stats.instructions_commited >= *instructions,
+        }
+    }
+}
+
+/// Everything a `CPU` needs to carry on from the middle of a program, including every
+/// instruction in flight and the stats so far. Saved with bincode after a short header.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub config: CpuConfig,
+    pub instructions: Vec<Word>,
+    pub registers: Registers,
+    pub should_flush: Option<FlushCause>,
+    pub refilling: Option<FlushCause>,
+    pub branch_predictor: Vec<u8>, // from `BranchPredictor::save`
+    pub memory: BytesMut,
+    pub rat: RegisterAliasTable,
+    pub rob: ReorderBuffer,
+    pub fetcher: Fetcher,
+    pub dispatcher: Dispatcher,
+    pub reservation_stations: Vec<ReservationStation>,
+    pub execution_units: Vec<ExecutionUnit>,
+    pub stats_tracker: StatsTracker,
+}
+impl Checkpoint {
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let mut out = MAGIC.to_vec();
+        bincode::serialize_into(&mut out, self).expect("couldn't serialise checkpoint");
+
+        fs::write(path, out)
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let content = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let Some(state) = content.strip_prefix(MAGIC) else {
+            return Err(format!(
+                "{}: not a checkpoint from this version of aca",
+                path
+            ));
+        };
+
+        bincode::deserialize(state).map_err(|e| format!("{}: corrupt checkpoint: {}", path, e))
+    }
+}
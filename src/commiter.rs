@@ -1,23 +1,36 @@
-use std::{fs, i32};
+use std::fs;
 
 use bytes::{BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     branch_prediction::BranchPredictor,
     branch_prediction::CoreBranchPredictor,
+    float::{self, RoundingMode},
     instructions::{Op, Register},
+    pipeline_trace::PipelineStage,
     register_alias_table::{RegisterAliasTable, Tag},
     registers::Registers,
     reorder_buffer::{Destination, ReorderBuffer, RobState, RobType, RobValue},
     reservation_station::ReservationStation,
-    stats::StatsTracker,
+    stats::{StatsTracker, Written},
 };
 
+/// Why committing an instruction threw away everything younger than it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FlushCause {
+    Mispredict, // a branch went the other way, or somewhere other than predicted
+    Serialize,  // younger instructions were dispatched with state it changed, like fsrm
+    Exit,       // the program exited or stopped with an error
+}
+
 #[derive(Debug)]
-pub struct Commiter;
+pub struct Commiter {
+    float_traps: u32, // float exceptions that stop the program
+}
 impl Commiter {
-    pub fn new() -> Self {
-        Self
+    pub fn new(float_traps: u32) -> Self {
+        Self { float_traps }
     }
 
     pub fn commit_finished(
@@ -25,34 +38,65 @@ impl Commiter {
         registers: &mut Registers,
         rat: &mut RegisterAliasTable,
         rob: &mut ReorderBuffer,
-        reservation_stations: &mut Vec<ReservationStation>,
+        reservation_stations: &mut [ReservationStation],
         memory: &mut BytesMut,
-        should_flush: &mut bool,
+        should_flush: &mut Option<FlushCause>,
         stats_tracker: &mut StatsTracker,
         branch_predictor: &mut CoreBranchPredictor,
     ) {
-        for inst in rob.retire() {
+        let mut retired = rob.retire().into_iter();
+        for inst in retired.by_ref() {
             stats_tracker.instructions_commited += 1;
+            stats_tracker.pipeline_event(inst.seq, inst.pc, PipelineStage::Commit);
 
             if inst.op == Op::Exit {
-                println!("Program exited with value {}", inst.value.to_value());
+                stats_tracker.exit_value = Some(inst.value.to_value());
                 registers.set(Register::ProgramCounter, i32::MAX);
-                *should_flush = true;
+                *should_flush = Some(FlushCause::Exit);
                 break;
             }
 
-            if let RobState::Errored(error) = inst.state {
-                println!("Program exited with error {}", error);
+            if let RobState::Errored(error) = &inst.state {
+                stats_tracker.exit_error = Some(error.clone());
                 registers.set(Register::ProgramCounter, i32::MAX);
-                *should_flush = true;
+                *should_flush = Some(FlushCause::Exit);
                 break;
             }
 
+            let trapped = inst.float_flags & self.float_traps;
+            if trapped != 0 {
+                stats_tracker.exit_error = Some(format!(
+                    "floating point exception ({}) at pc {}",
+                    float::flag_names(trapped),
+                    inst.pc
+                ));
+                registers.set(Register::ProgramCounter, i32::MAX);
+                *should_flush = Some(FlushCause::Exit);
+                break;
+            }
+            registers.raise_float_flags(inst.float_flags);
+
+            match inst.op {
+                Op::FClearFlags => registers.clear_float_flags(),
+                Op::FSetRounding => {
+                    let mode = RoundingMode::from_immediate(inst.value.to_value())
+                        .expect("fsrm needs a rounding mode");
+                    registers.set_rounding_mode(mode);
+
+                    // everything younger was dispatched with the old mode
+                    registers.set(Register::ProgramCounter, inst.pc as i32 + 1);
+                    *should_flush = Some(FlushCause::Serialize);
+                    break;
+                }
+                _ => (),
+            }
+
             match inst.destination {
                 Destination::Memory(addr) => match inst.value {
                     RobValue::Value(value) => {
                         if inst.op == Op::StoreChar {
-                            (&mut memory[addr..(addr + 1)]).put_u8(value as u8)
+                            (&mut memory[addr..(addr + 1)]).put_u8(value as u8);
+                            stats_tracker.committed_write(inst.pc, Written::Memory(addr, 1));
                         } else if inst.op == Op::Save {
                             let len = value as usize;
                             let mut contents = vec![
@@ -62,12 +106,26 @@ impl Commiter {
                             contents.append(&mut memory[addr..(addr + len)].to_vec());
                             fs::write("assets/output.ppm", contents).expect("cant write");
                         } else {
-                            (&mut memory[addr..(addr + 4)]).put_i32(value)
+                            (&mut memory[addr..(addr + 4)]).put_i32(value);
+                            stats_tracker.committed_write(inst.pc, Written::Memory(addr, 4));
                         }
                     }
-                    RobValue::Vector(value) => (&mut memory[addr..(addr + 16)]).put_u128(value),
+                    RobValue::Vector(value) => {
+                        value.write(&mut memory[addr..(addr + value.bytes())]);
+                        stats_tracker
+                            .committed_write(inst.pc, Written::Memory(addr, value.bytes()));
+                    }
                     _ => panic!("cant set memory on overflow value"),
                 },
+                Destination::Lanes(addresses) => {
+                    // in lane order, so the last lane wins when a scatter repeats an address
+                    for (addr, lane) in addresses.lanes().iter().zip(inst.value.to_vector().lanes())
+                    {
+                        let addr = *addr as usize;
+                        (&mut memory[addr..(addr + 4)]).put_u32(*lane);
+                        stats_tracker.committed_write(inst.pc, Written::Memory(addr, 4));
+                    }
+                }
                 Destination::Reg(reg) => {
                     // load actual value from memory
                     if !reg.is_vector() {
@@ -75,6 +133,10 @@ impl Commiter {
                             // we are either multiply or divide. so just set the regs and continue
                             registers.set(Register::High, val1);
                             registers.set(Register::Low, val2);
+                            stats_tracker
+                                .committed_write(inst.pc, Written::Register(Register::High));
+                            stats_tracker
+                                .committed_write(inst.pc, Written::Register(Register::Low));
 
                             reservation_stations
                                 .iter_mut()
@@ -99,12 +161,17 @@ impl Commiter {
                             let addr = memory.len();
                             memory.put_bytes(0, inst.value.to_value() as usize);
                             value = addr as i32;
+                        } else if inst.op == Op::FReadFlags {
+                            value = registers.float_flags() as i32;
+                        } else if inst.op == Op::FReadRounding {
+                            value = registers.rounding_mode().to_immediate();
                         }
 
                         // if not correct branch predict - we do nothing if predicted correctly
                         if !(inst.inst == RobType::Branch && value == -1) {
                             // update value in registers
                             registers.set(reg, value);
+                            stats_tracker.committed_write(inst.pc, Written::Register(reg));
 
                             // propogate to the reservation stations too
                             reservation_stations.iter_mut().for_each(|rs| {
@@ -122,6 +189,12 @@ impl Commiter {
                         if inst.op.is_predictable_branch() {
                             branch_predictor.update(inst.pc, inst.taken);
                             stats_tracker.committed_predicted_branches += 1;
+                            stats_tracker
+                                .branch_profile
+                                .record(inst.pc, inst.taken, value != -1);
+                            if let Some(trace) = stats_tracker.branch_trace.as_mut() {
+                                trace.record(inst.pc, inst.taken, inst.target.unwrap_or(0));
+                            }
                         }
 
                         if inst.inst == RobType::Branch && value != -1 {
@@ -129,7 +202,7 @@ impl Commiter {
                                 stats_tracker.committed_mispredicions += 1;
                                 stats_tracker.branch_mispredictions += 1;
                             }
-                            *should_flush = true;
+                            *should_flush = Some(FlushCause::Mispredict);
                             break;
                         }
                     } else if reg.is_vector() {
@@ -137,6 +210,7 @@ impl Commiter {
 
                         // update value in registers
                         registers.set_vector(reg, value);
+                        stats_tracker.committed_write(inst.pc, Written::Register(reg));
 
                         // propogate to the reservation stations too
                         reservation_stations
@@ -154,5 +228,10 @@ impl Commiter {
                 Destination::None => (),
             }
         }
+
+        // anything retired after a flush was younger than whatever caused it
+        for inst in retired {
+            stats_tracker.pipeline_event(inst.seq, inst.pc, PipelineStage::Squash);
+        }
     }
 }
@@ -1,18 +1,31 @@
+use std::collections::VecDeque;
+
 use bytes::BytesMut;
 
+use serde::{Deserialize, Serialize};
+
 use crate::branch_prediction::{BranchPredictionMode, BranchPredictor, CoreBranchPredictor};
-use crate::commiter::Commiter;
+use crate::branch_trace::BranchTrace;
+use crate::checkpoint::{Checkpoint, CheckpointAt};
+use crate::commiter::{Commiter, FlushCause};
 use crate::dispatcher::Dispatcher;
 use crate::execution_units::{EUType, ExecutionUnit};
-use crate::fetcher::Fetcher;
+use crate::fetcher::{FetchedWord, Fetcher};
+use crate::float;
+use crate::instruction_profile::InstructionProfile;
 use crate::instructions::{Register, Word};
+use crate::pipeline_trace::{PipelineEvent, PipelineStage, PipelineTrace};
 use crate::register_alias_table::RegisterAliasTable;
-use crate::registers::Registers;
-use crate::reorder_buffer::ReorderBuffer;
+use crate::registers::{Registers, GENERAL_REGISTERS};
+use crate::reorder_buffer::{ReorderBuffer, RobState};
 use crate::reservation_station::ReservationStation;
-use crate::stats::StatsTracker;
+use crate::sampling::Sampler;
+use crate::stalls::{CommitStall, StallStats};
+use crate::stats::{CommittedWrite, StatsTracker};
+use crate::vcd::VcdWriter;
 use crate::Args;
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CpuConfig {
     pub rob_size: usize,
     pub rob_max_retire: usize,
@@ -30,7 +43,121 @@ pub struct CpuConfig {
     pub eu_lsu_num: usize,
     pub eu_branch_num: usize,
     pub branch_predictor_mode: BranchPredictionMode,
+    pub vector_registers: usize,
+    pub vector_width: usize, // bits
+    pub float_traps: u32,
     pub print_memory: bool,
+    pub record_branch_trace: bool,
+    pub profile_instructions: bool,
+}
+impl CpuConfig {
+    pub fn vector_lanes(&self) -> usize {
+        self.vector_width / 32
+    }
+
+    /// Whether every register the program uses exists on this configuration.
+    pub fn check_program(&self, instructions: &[Word]) -> Result<(), String> {
+        for (pc, word) in instructions.iter().enumerate() {
+            for reg in word.registers() {
+                match reg {
+                    Register::Vector(r) if r as usize >= self.vector_registers => {
+                        return Err(format!(
+                            "{} at pc {} doesn't exist, there are {} vector registers",
+                            reg, pc, self.vector_registers
+                        ));
+                    }
+                    Register::General(_) if reg.index() >= GENERAL_REGISTERS => {
+                        return Err(format!("{} at pc {} doesn't exist", reg, pc));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// (flag, value) for every flag that sizes the core or changes what the program does, which
+    /// a checkpoint can only carry on with.
+    pub fn structure(&self) -> Vec<(&'static str, String)> {
+        let traps = match float::flag_names(self.float_traps) {
+            traps if traps.is_empty() => String::from("none"),
+            traps => traps,
+        };
+
+        vec![
+            ("rob_size", self.rob_size.to_string()),
+            ("rob_max_retire", self.rob_max_retire.to_string()),
+            ("fetch_amount", self.fetch_amount.to_string()),
+            (
+                "fetch_buffer_capacity",
+                self.fetch_buffer_capacity.to_string(),
+            ),
+            ("dispatch_amount", self.dispatch_amount.to_string()),
+            ("rs_alu_size", self.rs_alu_size.to_string()),
+            ("rs_fpu_size", self.rs_fpu_size.to_string()),
+            ("rs_vpu_size", self.rs_vpu_size.to_string()),
+            ("rs_lsu_size", self.rs_lsu_size.to_string()),
+            ("rs_branch_size", self.rs_branch_size.to_string()),
+            ("eu_alu_num", self.eu_alu_num.to_string()),
+            ("eu_fpu_num", self.eu_fpu_num.to_string()),
+            ("eu_vpu_num", self.eu_vpu_num.to_string()),
+            ("eu_lsu_num", self.eu_lsu_num.to_string()),
+            ("eu_branch_num", self.eu_branch_num.to_string()),
+            (
+                "branch_predictor_mode",
+                self.branch_predictor_mode.to_string(),
+            ),
+            ("vector_registers", self.vector_registers.to_string()),
+            ("vector_width", self.vector_width.to_string()),
+            ("fp_traps", traps),
+        ]
+    }
+
+    /// Whether a checkpoint taken with `checkpoint` can carry on with this config, which it can
+    /// unless one of the `given` flags asks for a different structure.
+    pub fn check_restore(&self, checkpoint: &CpuConfig, given: &[String]) -> Result<(), String> {
+        for ((flag, ours), (_, theirs)) in self.structure().into_iter().zip(checkpoint.structure())
+        {
+            if ours != theirs && given.iter().any(|g| g == flag) {
+                return Err(format!(
+                    "--{} {} doesn't match the checkpoint, which was taken with {}",
+                    flag.replace('_', "-"),
+                    ours,
+                    theirs
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+/// The same as running with no options.
+impl Default for CpuConfig {
+    fn default() -> Self {
+        Self {
+            rob_size: 32,
+            rob_max_retire: 8,
+            fetch_amount: 8,
+            fetch_buffer_capacity: 8,
+            dispatch_amount: 8,
+            rs_alu_size: 6,
+            rs_fpu_size: 4,
+            rs_vpu_size: 2,
+            rs_lsu_size: 2,
+            rs_branch_size: 2,
+            eu_alu_num: 3,
+            eu_fpu_num: 2,
+            eu_vpu_num: 1,
+            eu_lsu_num: 1,
+            eu_branch_num: 1,
+            branch_predictor_mode: "bimodal".parse().expect("bimodal is a predictor"),
+            vector_registers: 8,
+            vector_width: 128,
+            float_traps: 0,
+            print_memory: false,
+            record_branch_trace: false,
+            profile_instructions: false,
+        }
+    }
 }
 impl From<Args> for CpuConfig {
     fn from(value: Args) -> Self {
@@ -50,10 +177,13 @@ impl From<Args> for CpuConfig {
             eu_vpu_num: value.eu_vpu_num,
             eu_lsu_num: value.eu_lsu_num,
             eu_branch_num: value.eu_branch_num,
-            branch_predictor_mode: value
-                .branch_predictor_mode
-                .unwrap_or(BranchPredictionMode::TwoBitSaturating),
+            branch_predictor_mode: value.branch_predictor_mode,
+            vector_registers: value.vector_registers,
+            vector_width: value.vector_width,
+            float_traps: value.fp_traps,
             print_memory: value.print_memory,
+            record_branch_trace: value.branch_trace.is_some(),
+            profile_instructions: value.profile || value.profile_csv.is_some(),
         }
     }
 }
@@ -61,7 +191,8 @@ impl From<Args> for CpuConfig {
 pub struct CPU {
     instructions: Vec<Word>,
     registers: Registers,
-    should_flush: bool,
+    should_flush: Option<FlushCause>,
+    refilling: Option<FlushCause>, // the rob hasn't had anything in it since this flush
     branch_predictor: CoreBranchPredictor,
     memory: BytesMut,
     rat: RegisterAliasTable,
@@ -72,6 +203,10 @@ pub struct CPU {
     execution_units: Vec<ExecutionUnit>,
     commiter: Commiter,
     stats_tracker: StatsTracker,
+    pipeline_trace: Option<PipelineTrace>,
+    sampler: Option<Sampler>,
+    vcd: Option<VcdWriter>,
+    checkpoint_at: Option<(CheckpointAt, String)>, // and the path to write it to
     config: CpuConfig,
 }
 impl CPU {
@@ -98,12 +233,13 @@ impl CPU {
             config.eu_lsu_num
         ]);
 
-        CPU {
+        let mut cpu = CPU {
             instructions: Vec::new(),
-            registers: Registers::new(),
-            rat: RegisterAliasTable::new(),
+            registers: Registers::new(config.vector_registers, config.vector_lanes()),
+            rat: RegisterAliasTable::new(config.vector_registers),
             rob: ReorderBuffer::new(config.rob_size, config.rob_max_retire),
-            should_flush: false,
+            should_flush: None,
+            refilling: None,
             memory: BytesMut::new(),
             branch_predictor: CoreBranchPredictor::new(config.branch_predictor_mode.clone()),
             fetcher: Fetcher::new(config.fetch_amount, config.fetch_buffer_capacity),
@@ -117,35 +253,418 @@ impl CPU {
                 ReservationStation::new(1, EUType::System),
             ],
             execution_units,
-            commiter: Commiter::new(),
+            commiter: Commiter::new(config.float_traps),
             stats_tracker: StatsTracker::new(),
+            pipeline_trace: None,
+            sampler: None,
+            vcd: None,
+            checkpoint_at: None,
             config,
+        };
+
+        cpu.stats_tracker.stalls = StallStats::new(cpu.config.rob_max_retire);
+        cpu.track_occupancy();
+        if cpu.config.record_branch_trace {
+            cpu.stats_tracker.branch_trace = Some(BranchTrace::new());
+        }
+        if cpu.config.profile_instructions {
+            cpu.stats_tracker.instruction_profile = Some(InstructionProfile::new());
+            cpu.stats_tracker.trace_pipeline = true;
         }
+
+        cpu
     }
 
     pub fn set_memory(&mut self, memory: BytesMut) {
         self.memory = memory;
     }
 
-    pub fn run_program(&mut self, instructions: Vec<Word>) -> StatsTracker {
+    /// Carries on from a checkpoint. Structures are sized by the checkpoint's config, only
+    /// what gets recorded about the run is taken from `config`, see `CpuConfig::check_restore`.
+    pub fn restore(checkpoint: Checkpoint, config: CpuConfig) -> Self {
+        let mut cpu = CPU::new(CpuConfig {
+            print_memory: config.print_memory,
+            record_branch_trace: config.record_branch_trace,
+            profile_instructions: config.profile_instructions,
+            ..checkpoint.config
+        });
+
+        let mut stats_tracker = checkpoint.stats_tracker;
+        stats_tracker.branch_trace = stats_tracker
+            .branch_trace
+            .filter(|_| cpu.config.record_branch_trace)
+            .or(cpu.stats_tracker.branch_trace.take());
+        stats_tracker.instruction_profile = stats_tracker
+            .instruction_profile
+            .filter(|_| cpu.config.profile_instructions)
+            .or(cpu.stats_tracker.instruction_profile.take());
+        stats_tracker.trace_pipeline = cpu.stats_tracker.trace_pipeline;
+
+        cpu.instructions = checkpoint.instructions;
+        cpu.registers = checkpoint.registers;
+        cpu.should_flush = checkpoint.should_flush;
+        cpu.refilling = checkpoint.refilling;
+        cpu.branch_predictor.restore(&checkpoint.branch_predictor);
+        cpu.memory = checkpoint.memory;
+        cpu.rat = checkpoint.rat;
+        cpu.rob = checkpoint.rob;
+        cpu.fetcher = checkpoint.fetcher;
+        cpu.dispatcher = checkpoint.dispatcher;
+        cpu.reservation_stations = checkpoint.reservation_stations;
+        cpu.execution_units = checkpoint.execution_units;
+        cpu.stats_tracker = stats_tracker;
+        cpu
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            config: self.config.clone(),
+            instructions: self.instructions.clone(),
+            registers: self.registers.clone(),
+            should_flush: self.should_flush,
+            refilling: self.refilling,
+            branch_predictor: self.branch_predictor.save(),
+            memory: self.memory.clone(),
+            rat: self.rat.clone(),
+            rob: self.rob.clone(),
+            fetcher: self.fetcher.clone(),
+            dispatcher: self.dispatcher.clone(),
+            reservation_stations: self.reservation_stations.clone(),
+            execution_units: self.execution_units.clone(),
+            stats_tracker: self.stats_tracker.clone(),
+        }
+    }
+
+    /// Writes a checkpoint to `path` once `at` is reached.
+    pub fn set_checkpoint(&mut self, at: CheckpointAt, path: String) {
+        self.checkpoint_at = Some((at, path));
+    }
+
+    pub fn set_pipeline_trace(&mut self, trace: PipelineTrace) {
+        self.pipeline_trace = Some(trace);
+        self.stats_tracker.trace_pipeline = true;
+    }
+
+    pub fn set_sampler(&mut self, mut sampler: Sampler) {
+        sampler
+            .start(&self.stats_tracker)
+            .expect("couldn't write samples");
+        self.sampler = Some(sampler);
+    }
+
+    pub fn set_vcd(&mut self, vcd: VcdWriter) {
+        self.vcd = Some(vcd);
+        self.stats_tracker.trace_pipeline = true;
+    }
+
+    pub fn load_program(&mut self, instructions: Vec<Word>) -> Result<(), String> {
+        self.config.check_program(&instructions)?;
         self.instructions = instructions;
-        self.run();
-        return self.stats_tracker;
+        self.stats_tracker.branch_predictor_storage_bits = self.branch_predictor.storage_bits();
+        Ok(())
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        !self.is_finished()
+            || self.registers.pc() < self.instructions.len()
+            || self.should_flush.is_some()
     }
 
-    fn run(&mut self) {
-        while !self.is_finished() || self.stats_tracker.cycles == 0 || self.should_flush {
-            self.should_flush = false;
-            self.cycle();
-            self.stats_tracker.cycles += 1;
-            // self.print_dbg();
-            // if self.stats_tracker.cycles >= 10 {
-            // return;
-            // }
-            // println!("{:?}", self.rob.buffer);
+    /// Runs a single cycle, returning what happened to each instruction if pipeline events are
+    /// being recorded.
+    pub fn step(&mut self) -> Vec<PipelineEvent> {
+        self.should_flush = None;
+        self.cycle();
+
+        let events = std::mem::take(&mut self.stats_tracker.pipeline_events);
+        if let Some(profile) = self.stats_tracker.instruction_profile.as_mut() {
+            for event in events.iter() {
+                profile.record(self.stats_tracker.cycles, event);
+            }
+        }
+        if let Some(trace) = self.pipeline_trace.as_mut() {
+            for event in events.iter() {
+                trace
+                    .record(self.stats_tracker.cycles, *event)
+                    .expect("couldn't write pipeline trace");
+            }
+        }
+        if let Some(mut vcd) = self.vcd.take() {
+            vcd.record(self, self.stats_tracker.cycles, &events)
+                .expect("couldn't write vcd");
+            self.vcd = Some(vcd);
         }
 
-        self.print_end_dbg();
+        self.record_occupancy();
+        self.stats_tracker.cycles += 1;
+        if let Some(sampler) = self.sampler.as_mut() {
+            sampler
+                .sample(&self.stats_tracker)
+                .expect("couldn't write samples");
+        }
+        if let Some((at, path)) = &self.checkpoint_at {
+            if at.reached(&self.stats_tracker) {
+                self.checkpoint()
+                    .write(path)
+                    .expect("couldn't write checkpoint");
+                self.checkpoint_at = None;
+            }
+        }
+        return events;
+    }
+
+    pub fn finish(&mut self) -> StatsTracker {
+        if let Some((at, path)) = self.checkpoint_at.take() {
+            eprintln!("program finished before {:?}, {} wasn't written", at, path);
+        }
+        if let Some(trace) = self.pipeline_trace.take() {
+            trace.finish().expect("couldn't write pipeline trace");
+        }
+        if let Some(sampler) = self.sampler.take() {
+            sampler
+                .finish(&self.stats_tracker)
+                .expect("couldn't write samples");
+        }
+        if let Some(vcd) = self.vcd.take() {
+            vcd.finish(self.stats_tracker.cycles)
+                .expect("couldn't write vcd");
+        }
+
+        self.stats_tracker.branch_predictor_stats = self.branch_predictor.component_stats();
+        let instructions_commited = self.stats_tracker.instructions_commited;
+        if let Some(trace) = self.stats_tracker.branch_trace.as_mut() {
+            trace.instructions = instructions_commited;
+        }
+        return self.stats_tracker.clone();
+    }
+
+    /// Runs up to `instructions` more instructions functionally, each one on its own without
+    /// counting cycles or stats, so only the branch predictor is trained. Anything in flight is
+    /// thrown away first and run again. Returns how many ran.
+    pub fn fast_forward(&mut self, instructions: u64) -> u64 {
+        self.squash_to_committed();
+
+        let mut stats = StatsTracker::new();
+        while stats.instructions_commited < instructions
+            && self.registers.pc() < self.instructions.len()
+        {
+            self.execute_one(&mut stats);
+        }
+
+        self.stats_tracker.exit_value = self.stats_tracker.exit_value.or(stats.exit_value);
+        self.stats_tracker.exit_error = self.stats_tracker.exit_error.take().or(stats.exit_error);
+        self.stats_tracker.instructions_fast_forwarded += stats.instructions_commited;
+        return stats.instructions_commited;
+    }
+
+    /// Steps until at least `instructions` more have committed or the program ends.
+    pub fn run_detailed(&mut self, instructions: u64) {
+        let until = self
+            .stats_tracker
+            .instructions_commited
+            .saturating_add(instructions);
+        while self.is_running() && self.stats_tracker.instructions_commited < until {
+            self.step();
+        }
+    }
+
+    /// Makes `step` return the cycle's pipeline events without writing a pipeline trace.
+    pub fn record_pipeline_events(&mut self) {
+        self.stats_tracker.trace_pipeline = true;
+    }
+
+    /// Keeps what each committed instruction wrote until `take_committed_writes`.
+    pub fn record_committed_writes(&mut self) {
+        self.stats_tracker.record_writes = true;
+    }
+
+    pub fn take_committed_writes(&mut self) -> Vec<CommittedWrite> {
+        std::mem::take(&mut self.stats_tracker.committed_writes)
+    }
+
+    pub fn instructions(&self) -> &[Word] {
+        &self.instructions
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &BytesMut {
+        &self.memory
+    }
+
+    pub fn rat(&self) -> &RegisterAliasTable {
+        &self.rat
+    }
+
+    pub fn rob(&self) -> &ReorderBuffer {
+        &self.rob
+    }
+
+    pub fn fetch_buffer(&self) -> &VecDeque<FetchedWord> {
+        &self.fetcher.buffer
+    }
+
+    pub fn reservation_stations(&self) -> &[ReservationStation] {
+        &self.reservation_stations
+    }
+
+    pub fn execution_units(&self) -> &[ExecutionUnit] {
+        &self.execution_units
+    }
+
+    pub fn config(&self) -> &CpuConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &StatsTracker {
+        &self.stats_tracker
+    }
+
+    /// whether the last cycle flushed the pipeline.
+    pub fn is_flushing(&self) -> bool {
+        self.should_flush.is_some()
+    }
+
+    /// execution unit types in the order they were created, without repeats.
+    fn eu_types(&self) -> Vec<EUType> {
+        let mut eu_types: Vec<EUType> = self.execution_units.iter().map(|eu| eu.flavour).collect();
+        eu_types.dedup();
+        return eu_types;
+    }
+
+    fn track_occupancy(&mut self) {
+        let occupancy = &mut self.stats_tracker.occupancy;
+        occupancy.track(
+            String::from("fetch buffer"),
+            self.config.fetch_buffer_capacity,
+        );
+        occupancy.track(String::from("rob"), self.rob.capacity());
+        for rs in self.reservation_stations.iter() {
+            occupancy.track(format!("rs {:?}", rs.reserves_for()), rs.capacity());
+        }
+        for eu_type in self.eu_types() {
+            let units = self
+                .execution_units
+                .iter()
+                .filter(|eu| eu.flavour == eu_type)
+                .count();
+            self.stats_tracker
+                .occupancy
+                .track(format!("eu {:?}", eu_type), units);
+        }
+    }
+
+    /// in the same order as `track_occupancy`.
+    fn record_occupancy(&mut self) {
+        let mut occupancies = vec![self.fetcher.buffer.len(), self.rob.len()];
+        occupancies.extend(self.reservation_stations.iter().map(|rs| rs.len()));
+        for eu_type in self.eu_types() {
+            let busy = self
+                .execution_units
+                .iter()
+                .filter(|eu| eu.flavour == eu_type && eu.is_busy())
+                .count();
+            occupancies.push(busy);
+        }
+
+        self.stats_tracker.occupancy.record(occupancies);
+    }
+
+    fn flush(&mut self) {
+        if self.stats_tracker.trace_pipeline {
+            self.squash_in_flight();
+        }
+
+        self.execution_units.iter_mut().for_each(|eu| eu.flush());
+        self.reservation_stations
+            .iter_mut()
+            .for_each(|rs| rs.flush());
+        self.dispatcher.flush();
+        self.fetcher.flush();
+        self.rob.flush();
+        self.rat.flush();
+        self.branch_predictor.flush();
+    }
+
+    /// Throws away everything in flight and points the pc back at the oldest of it, leaving
+    /// only what has committed.
+    fn squash_to_committed(&mut self) {
+        let oldest = self.rob.oldest().map(|inst| inst.pc).or(self
+            .fetcher
+            .buffer
+            .front()
+            .map(|fetched| fetched.pc));
+
+        if let Some(pc) = oldest {
+            self.flush();
+            self.registers.set(Register::ProgramCounter, pc as i32);
+        }
+    }
+
+    /// Runs the next instruction through every stage on its own, all in one go.
+    fn execute_one(&mut self, stats: &mut StatsTracker) {
+        self.fetcher.fetch_one(
+            &self.instructions,
+            &mut self.registers,
+            &mut self.branch_predictor,
+            stats,
+        );
+        self.dispatcher.dispatch(
+            &mut self.fetcher,
+            &mut self.registers,
+            &mut self.rat,
+            &mut self.rob,
+            &mut self.reservation_stations,
+            stats,
+        );
+
+        for i in 0..self.reservation_stations.len() {
+            let eu_type = self.reservation_stations[i].reserves_for();
+            if let Some(rs_inst) = self.reservation_stations[i].take_oldest_valid(&mut self.rob) {
+                let eu = self
+                    .execution_units
+                    .iter_mut()
+                    .find(|eu| eu.flavour == eu_type)
+                    .unwrap_or_else(|| panic!("there are no {:?} execution units", eu_type));
+                eu.start(rs_inst.to_exe_inst(), &mut self.rob);
+                while eu.is_busy() {
+                    eu.cycle(
+                        &mut self.branch_predictor,
+                        &mut self.rob,
+                        &mut self.reservation_stations,
+                        &mut self.memory,
+                    );
+                }
+            }
+        }
+
+        self.commiter.commit_finished(
+            &mut self.registers,
+            &mut self.rat,
+            &mut self.rob,
+            &mut self.reservation_stations,
+            &mut self.memory,
+            &mut self.should_flush,
+            stats,
+            &mut self.branch_predictor,
+        );
+        if self.should_flush.take().is_some() {
+            self.flush();
+        }
+    }
+
+    /// everything still in flight when we flush never commits.
+    fn squash_in_flight(&mut self) {
+        for inst in self.rob.buffer.iter().flatten() {
+            self.stats_tracker
+                .pipeline_event(inst.seq, inst.pc, PipelineStage::Squash);
+        }
+        for fetched in self.fetcher.buffer.iter() {
+            self.stats_tracker
+                .pipeline_event(fetched.seq, fetched.pc, PipelineStage::Squash);
+        }
     }
 
     fn cycle(&mut self) {
@@ -155,6 +674,7 @@ impl CPU {
         // we have 5 stages: fetch, decode/issue, dispatch, execute, commit
 
         // commit
+        let commited_before = self.stats_tracker.instructions_commited;
         self.commiter.commit_finished(
             &mut self.registers,
             &mut self.rat,
@@ -166,28 +686,30 @@ impl CPU {
             &mut self.branch_predictor,
         );
 
+        let commited = self.stats_tracker.instructions_commited - commited_before;
+        let stall = self.commit_stall();
+        self.stats_tracker.stalls.record_commit(commited, stall);
+
         // hand should flush!
-        if self.should_flush {
-            self.execution_units.iter_mut().for_each(|eu| eu.flush());
-            self.reservation_stations
-                .iter_mut()
-                .for_each(|rs| rs.flush());
-            self.dispatcher.flush();
-            self.fetcher.flush();
-            self.rob.flush();
-            self.rat.flush();
-            self.branch_predictor.flush();
+        if self.should_flush.is_some() {
+            self.refilling = self.should_flush;
+            self.flush();
             return;
         }
 
         // execute
         for eu in self.execution_units.iter_mut() {
+            let running = eu.inst.map(|inst| (inst.seq, inst.pc));
             eu.cycle(
                 &mut self.branch_predictor,
                 &mut self.rob,
                 &mut self.reservation_stations,
                 &mut self.memory,
             );
+            if let (Some((seq, pc)), None) = (running, eu.inst) {
+                self.stats_tracker
+                    .pipeline_event(seq, pc, PipelineStage::Complete);
+            }
         }
 
         // issue
@@ -197,6 +719,11 @@ impl CPU {
                     if rs.reserves_for() == eu.flavour {
                         if let Some(rs_inst) = rs.take_oldest_valid(&mut self.rob) {
                             eu.start(rs_inst.to_exe_inst(), &mut self.rob);
+                            self.stats_tracker.pipeline_event(
+                                rs_inst.seq,
+                                rs_inst.pc,
+                                PipelineStage::Issue,
+                            );
                             break;
                         }
                     }
@@ -213,6 +740,9 @@ impl CPU {
             &mut self.reservation_stations,
             &mut self.stats_tracker,
         );
+        if !self.rob.is_empty() {
+            self.refilling = None;
+        }
 
         // fetch
         self.fetcher.fetch(
@@ -223,6 +753,70 @@ impl CPU {
         );
     }
 
+    /// Why the oldest instruction can't commit yet, only meaningful when fewer than
+    /// `rob_max_retire` committed this cycle.
+    fn commit_stall(&self) -> CommitStall {
+        let flushed = |cause| match cause {
+            Some(FlushCause::Mispredict) => CommitStall::BranchMispredict,
+            Some(FlushCause::Serialize) => CommitStall::Serialize,
+            // nothing left to commit once we've exited
+            Some(FlushCause::Exit) | None => CommitStall::Frontend,
+        };
+        if self.should_flush.is_some() {
+            return flushed(self.should_flush);
+        }
+
+        let Some(oldest) = self.rob.oldest() else {
+            return flushed(self.refilling);
+        };
+
+        // a full window is what stops us hiding whatever the oldest is waiting for
+        if self.rob.is_full() {
+            return CommitStall::RobFull;
+        }
+        if let Some(next) = self.fetcher.buffer.front() {
+            let next_eu_type = next.word.op().needs_eu_type();
+            let rs_full = self
+                .reservation_stations
+                .iter()
+                .filter(|rs| rs.reserves_for() == next_eu_type)
+                .all(|rs| rs.is_full());
+            if rs_full {
+                return CommitStall::RsFull(next_eu_type);
+            }
+        }
+
+        let eu_type = oldest.op.needs_eu_type();
+        if oldest.state == RobState::Executing {
+            return CommitStall::Execute(eu_type);
+        }
+
+        let waiting = self
+            .reservation_stations
+            .iter()
+            .flat_map(|rs| rs.buffer.iter())
+            .find(|inst| inst.rob_index == oldest.index);
+        if let Some(inst) = waiting {
+            if !inst.operands_ready() {
+                return CommitStall::Operands;
+            }
+            if inst.has_memory_dependence(&self.rob) {
+                return CommitStall::MemoryDependence;
+            }
+        }
+
+        let all_busy = self
+            .execution_units
+            .iter()
+            .filter(|eu| eu.flavour == eu_type)
+            .all(|eu| eu.is_busy());
+        if waiting.is_some() && all_busy {
+            return CommitStall::EuBusy(eu_type);
+        }
+
+        return CommitStall::Issue;
+    }
+
     fn is_finished(&mut self) -> bool {
         let mut finished = true;
 
@@ -232,81 +826,20 @@ impl CPU {
         return finished;
     }
 
-    #[allow(dead_code)]
-    fn print_dbg(&mut self) {
-        self.fetcher
-            .buffer
-            .iter()
-            .for_each(|val| println!("{:?} | branch taken: {:?}", val.word, val.branch_taken));
-
-        self.reservation_stations
-            .iter()
-            .filter(|rs| !rs.is_empty())
-            .for_each(|rs| {
-                println!("RS {:?}", rs.reserves_for());
-                rs.buffer.iter().for_each(|el| {
-                    println!(
-                        "    {:?} {:?} {:?} {:?} {:?}",
-                        el.rob_index,
-                        el.word.op(),
-                        el.return_op,
-                        el.left_op,
-                        el.right_op
-                    );
-                });
-            });
-        self.execution_units
-            .iter()
-            .filter(|ex| ex.is_busy())
-            .for_each(|ex| {
-                println!("Exe {:?}", ex.flavour);
-                ex.inst.inspect(|el| {
-                    println!(
-                        "    {:?} {:?} {:?} {:?} {:?}",
-                        el.rob_index,
-                        el.word.op(),
-                        el.ret,
-                        el.left,
-                        el.right
-                    );
-                });
-            });
-
-        println!("{:?}", self.rob.buffer);
-        println!("{:?}", self.rat.table);
-
-        // let mut regs = self
-        //     .registers
-        //     .general_registers
-        //     .iter()
-        //     .filter(|(_, v)| **v != 0)
-        //     .collect::<Vec<(&Register, &i32)>>();
-        // regs.sort();
-        // for (reg, value) in regs {
-        //     print!("{:?}: {}  ", reg, value);
-        // }
-        if self.config.print_memory {
-            println!("{:?}", self.memory.to_vec());
+    /// how the program exited and the registers it left behind.
+    pub fn print_end_state(&self) {
+        if let Some(value) = self.stats_tracker.exit_value {
+            println!("Program exited with value {}", value);
+        }
+        if let Some(error) = &self.stats_tracker.exit_error {
+            println!("Program exited with error {}", error);
         }
 
-        println!();
-        println!();
-    }
-
-    #[allow(dead_code)]
-    fn print_end_dbg(&mut self) {
         if self.config.print_memory {
             println!("{:?}", self.memory.to_vec());
         }
 
-        let mut regs = self
-            .registers
-            .general_registers
-            .iter()
-            .filter(|(_, v)| **v != 0)
-            .collect::<Vec<(&Register, &i32)>>();
-        regs.sort();
-        for (reg, value) in regs {
+        for (reg, value) in self.registers.general().filter(|(_, v)| *v != 0) {
             println!(
                 "{:?}: i32({}) f32({})",
                 reg,
@@ -315,22 +848,15 @@ impl CPU {
             );
         }
 
-        let mut regs = self
-            .registers
-            .vector_registers
-            .iter()
-            // .filter(|(_, v)| **v != 0)
-            .collect::<Vec<(&Register, &u128)>>();
-        regs.sort();
-        for (reg, value) in regs {
-            let floats: Vec<f32> = value
-                .to_be_bytes()
-                .chunks_exact(4)
-                .map(|i| [i[0], i[1], i[2], i[3]])
-                .map(|i| f32::from_be_bytes(i))
-                .collect();
-
-            println!("{:?}: u128({}) f32({:?})", reg, value, floats,);
+        for (reg, value) in self.registers.vectors() {
+            println!("{:?}: {} f32({:?})", reg, value, value.floats());
+        }
+
+        if self.registers.float_flags() != 0 {
+            println!(
+                "Floating point exceptions: {}",
+                float::flag_names(self.registers.float_flags())
+            );
         }
     }
 }
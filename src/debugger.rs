@@ -0,0 +1,600 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    assembler::SourceInfo,
+    cpu::CPU,
+    float::{self, RoundingMode},
+    instructions::{Register, Word},
+    pipeline_trace::PipelineStage,
+    register_alias_table::RegisterAliasTable,
+    registers::Registers,
+    stats::{CommittedWrite, Written},
+};
+
+const HELP: &str = "\
+commands:
+  s, step [n]                  run one cycle, or n cycles
+  c, continue                  run until a breakpoint is hit or the program ends
+  u, until fetch|commit <loc>  run until <loc> is fetched or committed
+  b, break fetch|commit <loc>  stop whenever <loc> is fetched or committed
+  w, watch <reg>               stop whenever an instruction writing a register commits,
+                               e.g. $3, $v0, $hi, even if the value doesn't change
+  w, watch mem <addr> [len]    stop whenever a store to any of len bytes (default 4) at
+                               addr commits
+  d, delete <n>                remove breakpoint n
+  i, info                      list breakpoints
+  p, print [what]              show fetch, rs, eu, rob, rat or regs, everything by default
+  x <addr> [n] [i|f|b]         show n ints, floats or bytes of memory from addr
+  checkpoint <file>            save the simulator to file, carry on from it with --restore
+  q, quit                      stop debugging
+<loc> is a pc, a label or label+offset. addresses can be decimal or 0x hex.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Fetch(usize),
+    Commit(usize),
+    Register(Register),
+    Memory(usize, usize), // address, length
+}
+
+/// Steps a `CPU` through a program a cycle at a time from a command prompt.
+pub struct Debugger {
+    cpu: CPU,
+    source: Vec<SourceInfo>,
+    breakpoints: Vec<Breakpoint>,
+    finished: bool,
+}
+impl Debugger {
+    pub fn new(
+        mut cpu: CPU,
+        instructions: Vec<Word>,
+        source: Vec<SourceInfo>,
+    ) -> Result<Self, String> {
+        cpu.load_program(instructions)?;
+        cpu.record_pipeline_events();
+        cpu.record_committed_writes();
+
+        Ok(Self {
+            cpu,
+            source,
+            breakpoints: Vec::new(),
+            finished: false,
+        })
+    }
+
+    pub fn repl(&mut self) {
+        println!(
+            "{} instructions loaded, `help` for commands",
+            self.source.len()
+        );
+
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("(aca:{}) ", self.cpu.stats().cycles);
+            io::stdout().flush().expect("couldn't write prompt");
+
+            let Some(Ok(line)) = lines.next() else {
+                break;
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            if words[0] == "q" || words[0] == "quit" {
+                break;
+            }
+
+            if let Err(e) = self.command(&words) {
+                println!("{}", e);
+            }
+        }
+    }
+
+    fn command(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            ["s" | "step"] => self.run(Some(1), None),
+            ["s" | "step", n] => {
+                let n = n
+                    .parse()
+                    .map_err(|_| format!("{} is not a cycle count", n))?;
+                self.run(Some(n), None);
+            }
+            ["c" | "continue"] => self.run(None, None),
+            ["u" | "until", stage, loc] => {
+                let until = self.code_breakpoint(stage, loc)?;
+                self.run(None, Some(until));
+            }
+            ["b" | "break", stage, loc] => {
+                let breakpoint = self.code_breakpoint(stage, loc)?;
+                self.add_breakpoint(breakpoint);
+            }
+            ["w" | "watch", "mem", addr] => {
+                let addr = parse_addr(addr)?;
+                end_of(addr, 4)?;
+                self.add_breakpoint(Breakpoint::Memory(addr, 4));
+            }
+            ["w" | "watch", "mem", addr, len] => {
+                let len = len
+                    .parse()
+                    .map_err(|_| format!("{} is not a length", len))?;
+                let addr = parse_addr(addr)?;
+                end_of(addr, len)?;
+                self.add_breakpoint(Breakpoint::Memory(addr, len));
+            }
+            ["w" | "watch", reg] => {
+                let reg: Register = reg.parse()?;
+                if !self.cpu.registers().contains(reg) {
+                    return Err(format!("there is no register {}", reg));
+                }
+                self.add_breakpoint(Breakpoint::Register(reg));
+            }
+            ["d" | "delete", n] => {
+                let n: usize = n
+                    .parse()
+                    .map_err(|_| format!("{} is not a breakpoint", n))?;
+                if n >= self.breakpoints.len() {
+                    return Err(format!("there is no breakpoint {}", n));
+                }
+                self.breakpoints.remove(n);
+            }
+            ["i" | "info"] => {
+                for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                    println!("{}: {}", i, self.describe_breakpoint(breakpoint));
+                }
+            }
+            ["p" | "print"] => {
+                for (section, _) in SECTIONS {
+                    print!("{}", self.section(section)?);
+                }
+            }
+            ["p" | "print", section] => print!("{}", self.section(section)?),
+            ["x", addr] => print!("{}", self.examine(parse_addr(addr)?, 1, "i")?),
+            ["x", addr, n] => {
+                let n = n.parse().map_err(|_| format!("{} is not a count", n))?;
+                print!("{}", self.examine(parse_addr(addr)?, n, "i")?);
+            }
+            ["x", addr, n, format] => {
+                let n = n.parse().map_err(|_| format!("{} is not a count", n))?;
+                print!("{}", self.examine(parse_addr(addr)?, n, format)?);
+            }
+            ["checkpoint", path] => {
+                self.cpu
+                    .checkpoint()
+                    .write(path)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                println!("saved cycle {} to {}", self.cpu.stats().cycles, path);
+            }
+            ["h" | "help"] => println!("{}", HELP),
+            _ => return Err(format!("unknown command '{}', try help", words.join(" "))),
+        }
+
+        Ok(())
+    }
+
+    /// Steps until `cycles` have run, a breakpoint or `until` is hit, or the program ends.
+    fn run(&mut self, cycles: Option<u64>, until: Option<Breakpoint>) {
+        let mut ran = 0;
+
+        while cycles.is_none_or(|c| ran < c) {
+            if self.finished {
+                println!("program has finished");
+                return;
+            }
+            if !self.cpu.is_running() {
+                self.finished = true;
+                println!("program has finished");
+                let stats = self.cpu.finish();
+                self.cpu.print_end_state();
+                println!("{}", stats);
+                return;
+            }
+
+            let hits = self.step(until.as_ref());
+            ran += 1;
+            if !hits.is_empty() {
+                for hit in hits {
+                    println!("{}", hit);
+                }
+                break;
+            }
+        }
+
+        println!(
+            "cycle {}, fetching {}",
+            self.cpu.stats().cycles,
+            self.describe_pc(self.cpu.registers().pc())
+        );
+    }
+
+    /// Runs a cycle and reports every breakpoint it hit.
+    fn step(&mut self, until: Option<&Breakpoint>) -> Vec<String> {
+        let events = self.cpu.step();
+        let writes = self.cpu.take_committed_writes();
+        let cycle = self.cpu.stats().cycles - 1;
+        let mut hits = Vec::new();
+
+        for (i, breakpoint) in self.breakpoints.iter().chain(until).enumerate() {
+            let name = if i < self.breakpoints.len() {
+                format!("breakpoint {}", i)
+            } else {
+                String::from("until")
+            };
+
+            match breakpoint {
+                Breakpoint::Fetch(pc) | Breakpoint::Commit(pc) => {
+                    let stage = match breakpoint {
+                        Breakpoint::Fetch(_) => PipelineStage::Fetch,
+                        _ => PipelineStage::Commit,
+                    };
+                    if events.iter().any(|e| e.pc == *pc && e.stage == stage) {
+                        hits.push(format!(
+                            "{}: {} {:?} in cycle {}",
+                            name,
+                            self.describe_pc(*pc),
+                            stage,
+                            cycle
+                        ));
+                    }
+                }
+                Breakpoint::Register(reg) => {
+                    for write in writes
+                        .iter()
+                        .filter(|w| w.written == Written::Register(*reg))
+                    {
+                        hits.push(format!(
+                            "{}: {} wrote {} = {}",
+                            name,
+                            self.describe_pc(write.pc),
+                            reg,
+                            register_value(self.cpu.registers(), *reg)
+                        ));
+                    }
+                }
+                Breakpoint::Memory(addr, len) => {
+                    for write in writes.iter().filter(|w| writes_to(w, *addr, *len)) {
+                        let Written::Memory(start, bytes) = write.written else {
+                            continue;
+                        };
+                        hits.push(format!(
+                            "{}: {} wrote memory {}..{} = {:?}",
+                            name,
+                            self.describe_pc(write.pc),
+                            start,
+                            start + bytes,
+                            self.cpu.memory().get(start..start + bytes)
+                        ));
+                    }
+                }
+            }
+        }
+
+        hits
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        println!(
+            "breakpoint {}: {}",
+            self.breakpoints.len(),
+            self.describe_breakpoint(&breakpoint)
+        );
+        self.breakpoints.push(breakpoint);
+    }
+
+    fn code_breakpoint(&self, stage: &str, loc: &str) -> Result<Breakpoint, String> {
+        let pc = self.parse_location(loc)?;
+        match stage {
+            "fetch" => Ok(Breakpoint::Fetch(pc)),
+            "commit" => Ok(Breakpoint::Commit(pc)),
+            _ => Err(format!("{} should be fetch or commit", stage)),
+        }
+    }
+
+    /// a pc, `label` or `label+offset`.
+    fn parse_location(&self, loc: &str) -> Result<usize, String> {
+        if let Ok(pc) = loc.parse::<usize>() {
+            if pc >= self.source.len() {
+                return Err(format!("pc {} is past the end of the program", pc));
+            }
+            return Ok(pc);
+        }
+
+        let (label, offset) = match loc.split_once('+') {
+            Some((label, offset)) => (
+                label,
+                offset
+                    .parse()
+                    .map_err(|_| format!("{} is not an offset", offset))?,
+            ),
+            None => (loc, 0),
+        };
+
+        self.source
+            .iter()
+            .position(|s| s.label.as_deref() == Some(label) && s.label_offset == offset)
+            .ok_or_else(|| format!("no instruction at {}", loc))
+    }
+
+    fn describe_breakpoint(&self, breakpoint: &Breakpoint) -> String {
+        match breakpoint {
+            Breakpoint::Fetch(pc) => format!("fetch {}", self.describe_pc(*pc)),
+            Breakpoint::Commit(pc) => format!("commit {}", self.describe_pc(*pc)),
+            Breakpoint::Register(reg) => format!("write to {}", reg),
+            Breakpoint::Memory(addr, len) => {
+                format!("write to memory {}..{}", addr, addr.saturating_add(*len))
+            }
+        }
+    }
+
+    fn describe_pc(&self, pc: usize) -> String {
+        match self.source.get(pc) {
+            Some(source) => format!("pc {} ({}: {})", pc, source.location(), source.text),
+            None => format!("pc {} (end of program)", pc),
+        }
+    }
+
+    fn section(&self, section: &str) -> Result<String, String> {
+        let (_, title) = SECTIONS
+            .iter()
+            .find(|(name, _)| *name == section)
+            .ok_or_else(|| {
+                format!(
+                    "can't print {}, try fetch, rs, eu, rob, rat or regs",
+                    section
+                )
+            })?;
+
+        Ok(format!(
+            "{}:\n{}",
+            title,
+            describe_state(&self.cpu, &self.source, section)
+        ))
+    }
+
+    fn examine(&self, addr: usize, n: usize, format: &str) -> Result<String, String> {
+        let size = match format {
+            "i" | "f" => 4,
+            "b" => 1,
+            _ => return Err(format!("{} should be i, f or b", format)),
+        };
+
+        let end = end_of(addr, n.checked_mul(size).ok_or("address out of range")?)?;
+        let memory = self
+            .cpu
+            .memory()
+            .get(addr..end)
+            .ok_or_else(|| format!("memory is only {} bytes", self.cpu.memory().len()))?;
+
+        let mut out = String::new();
+        for (i, chunk) in memory.chunks_exact(size).enumerate() {
+            if i % 8 == 0 {
+                if i != 0 {
+                    out += "\n";
+                }
+                out += &format!("{:>6}:", addr + i * size);
+            }
+            out += &match format {
+                "i" => format!(" {:>11}", i32::from_be_bytes(chunk.try_into().unwrap())),
+                "f" => format!(" {:>11}", f32::from_be_bytes(chunk.try_into().unwrap())),
+                _ => format!(" {:02x}", chunk[0]),
+            };
+        }
+        out += "\n";
+
+        Ok(out)
+    }
+}
+
+/// sections of the machine state that can be shown, with their titles.
+pub const SECTIONS: [(&str, &str); 6] = [
+    ("fetch", "Fetch buffer"),
+    ("rs", "Reservation stations"),
+    ("eu", "Execution units"),
+    ("rob", "ROB, oldest first"),
+    ("rat", "RAT"),
+    ("regs", "Registers"),
+];
+
+/// One of `SECTIONS` of the cpu's state as text, empty for an unknown section.
+pub fn describe_state(cpu: &CPU, source: &[SourceInfo], section: &str) -> String {
+    let mut out = String::new();
+
+    match section {
+        "fetch" => {
+            for fetched in cpu.fetch_buffer() {
+                out += &format!(
+                    "  #{:<5} pc {:<4} {:<24} predicted taken: {}\n",
+                    fetched.seq,
+                    fetched.pc,
+                    text(source, fetched.pc, &fetched.word),
+                    fetched.branch_taken
+                );
+            }
+        }
+        "rs" => {
+            for rs in cpu.reservation_stations() {
+                out += &format!("{:?}:\n", rs.reserves_for());
+                if rs.is_empty() {
+                    out += "  empty\n";
+                }
+                for inst in rs.buffer.iter() {
+                    out += &format!(
+                        "  #{:<5} rob {:<3} {:<24} {:?} {:?} {:?} {:?}\n",
+                        inst.seq,
+                        inst.rob_index,
+                        text(source, inst.pc, &inst.word),
+                        inst.return_op,
+                        inst.left_op,
+                        inst.right_op,
+                        inst.extra_op
+                    );
+                }
+            }
+        }
+        "eu" => {
+            for eu in cpu.execution_units() {
+                match eu.inst {
+                    Some(inst) => {
+                        out += &format!(
+                            "{:<7} #{:<5} rob {:<3} {:<24} {} cycles left\n",
+                            format!("{:?}", eu.flavour),
+                            inst.seq,
+                            inst.rob_index,
+                            text(source, inst.pc, &inst.word),
+                            eu.cycles_left()
+                        );
+                    }
+                    None => out += &format!("{:<7} idle\n", format!("{:?}", eu.flavour)),
+                }
+            }
+        }
+        "rob" => {
+            for inst in cpu.rob().in_order() {
+                out += &format!(
+                    "  [{:<3}] #{:<5} pc {:<4} {:<24} {:?} -> {:?} = {:?}\n",
+                    inst.index,
+                    inst.seq,
+                    inst.pc,
+                    text(source, inst.pc, &cpu.instructions()[inst.pc]),
+                    inst.state,
+                    inst.destination,
+                    inst.value
+                );
+            }
+        }
+        "rat" => out += &format_rat(cpu.rat()),
+        "regs" => out += &format_registers(cpu.registers()),
+        _ => (),
+    }
+
+    out
+}
+
+/// the source text of an instruction, falling back to the decoded word.
+fn text(source: &[SourceInfo], pc: usize, word: &Word) -> String {
+    source
+        .get(pc)
+        .map_or_else(|| format!("{:?}", word), |s| s.text.clone())
+}
+
+fn parse_addr(addr: &str) -> Result<usize, String> {
+    match addr.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => addr.parse(),
+    }
+    .map_err(|_| format!("{} is not an address", addr))
+}
+
+/// where `len` bytes from `addr` end, if that's still an address.
+fn end_of(addr: usize, len: usize) -> Result<usize, String> {
+    addr.checked_add(len)
+        .ok_or_else(|| String::from("address out of range"))
+}
+
+/// whether a committed store overlaps the `len` bytes at `addr`.
+fn writes_to(write: &CommittedWrite, addr: usize, len: usize) -> bool {
+    match write.written {
+        Written::Memory(start, bytes) => {
+            start < addr.saturating_add(len) && addr < start.saturating_add(bytes)
+        }
+        Written::Register(_) => false,
+    }
+}
+
+fn register_value(registers: &Registers, reg: Register) -> String {
+    if reg.is_vector() {
+        registers.get_vector(reg).to_string()
+    } else {
+        registers.get(reg).to_string()
+    }
+}
+
+fn format_rat(rat: &RegisterAliasTable) -> String {
+    let mut out = String::new();
+    for (reg, rob_index) in rat.aliases() {
+        out += &format!("  {} -> rob {}\n", reg, rob_index);
+    }
+    out
+}
+
+/// the pc and every register that isn't zero.
+fn format_registers(registers: &Registers) -> String {
+    let mut out = format!("  $pc: {}\n", registers.pc());
+
+    let regs = registers
+        .general()
+        .filter(|(reg, v)| *v != 0 && *reg != Register::ProgramCounter);
+    for (reg, value) in regs {
+        out += &format!(
+            "  {}: i32({}) f32({:e})\n",
+            reg,
+            value,
+            f32::from_be_bytes(value.to_be_bytes())
+        );
+    }
+
+    for (reg, value) in registers.vectors().filter(|(_, v)| !v.is_zero()) {
+        out += &format!("  {}: {}\n", reg, value);
+    }
+
+    if registers.float_flags() != 0 {
+        out += &format!(
+            "  float flags: {}\n",
+            float::flag_names(registers.float_flags())
+        );
+    }
+    if registers.rounding_mode() != RoundingMode::NearestEven {
+        out += &format!("  rounding: {:?}\n", registers.rounding_mode());
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assembler::assemble_source, cpu::CpuConfig};
+
+    const STORE: &str = "
+.memory
+    data: .int 0, 0
+.instructions
+    main:
+        li $1 7
+        sw $1 $0 data
+";
+
+    fn debugger(source: &str) -> Debugger {
+        let (memory, instructions, source) = assemble_source(source);
+        let mut cpu = CPU::new(CpuConfig::default());
+        cpu.set_memory(memory);
+        Debugger::new(cpu, instructions, source).unwrap()
+    }
+
+    #[test]
+    fn addresses_past_the_end_of_memory_are_errors() {
+        let mut debugger = debugger(STORE);
+        let out_of_range = Err(String::from("address out of range"));
+
+        assert_eq!(
+            debugger.command(&["x", "0xffffffffffffffff", "2"]),
+            out_of_range
+        );
+        assert_eq!(
+            debugger.command(&["w", "mem", "0xffffffffffffffff"]),
+            out_of_range
+        );
+        assert!(debugger.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn watching_the_last_addresses_doesnt_overflow() {
+        let mut debugger = debugger(STORE);
+        debugger
+            .command(&["w", "mem", "0xfffffffffffffff0", "15"])
+            .unwrap();
+        debugger.command(&["c"]).unwrap();
+
+        assert!(debugger.finished);
+    }
+}
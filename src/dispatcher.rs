@@ -1,14 +1,18 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{
     fetcher::Fetcher,
     instructions::{Op, Register, Word},
+    pipeline_trace::PipelineStage,
     register_alias_table::{RegisterAliasTable, Tag},
     registers::Registers,
     reorder_buffer::{Destination, ReorderBuffer, RobInst, RobState, RobValue},
     reservation_station::{ResInst, ResOperand, ReservationStation},
+    stalls::DispatchStall,
     stats::StatsTracker,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dispatcher {
     dispatch_amount: usize,
 }
@@ -17,9 +21,7 @@ impl Dispatcher {
         Self { dispatch_amount }
     }
 
-    pub fn flush(&mut self) {
-        ()
-    }
+    pub fn flush(&mut self) {}
 
     pub fn dispatch(
         &mut self,
@@ -27,13 +29,14 @@ impl Dispatcher {
         registers: &mut Registers,
         rat: &mut RegisterAliasTable,
         rob: &mut ReorderBuffer,
-        reservation_stations: &mut Vec<ReservationStation>,
+        reservation_stations: &mut [ReservationStation],
         stats_tracker: &mut StatsTracker,
     ) {
         for _ in 0..self.dispatch_amount {
             let mut rs: Option<&mut ReservationStation> = None;
 
             if rob.is_full() {
+                stats_tracker.stalls.record_dispatch(DispatchStall::RobFull);
                 return;
             }
 
@@ -43,6 +46,16 @@ impl Dispatcher {
                 rs = reservation_stations
                     .iter_mut()
                     .find(|rs| rs.reserves_for() == word.op().needs_eu_type() && !rs.is_full());
+
+                if rs.is_none() {
+                    stats_tracker
+                        .stalls
+                        .record_dispatch(DispatchStall::RsFull(word.op().needs_eu_type()));
+                }
+            } else {
+                stats_tracker
+                    .stalls
+                    .record_dispatch(DispatchStall::FetchBufferEmpty);
             }
 
             if rs.is_none() {
@@ -68,6 +81,7 @@ impl Dispatcher {
                 let mut ret_op = ResOperand::Value(0);
                 let mut left_op = ResOperand::Value(0);
                 let mut right_op = ResOperand::Value(0);
+                let mut extra_op = ResOperand::Value(0);
 
                 match word.op() {
                     Op::LoadMemory | Op::VLoadMemory | Op::LoadHalfWord | Op::LoadChar => {
@@ -92,6 +106,20 @@ impl Dispatcher {
                             right_op = ResOperand::Value(i);
                         }
                     }
+                    Op::VLoadStrided | Op::VGather => {
+                        if let Word::R(_, ro, rl, rr) = word {
+                            ret_op = ResOperand::Reg(ro);
+                            left_op = make_res_operand(rl);
+                            right_op = make_res_operand(rr);
+                        }
+                    }
+                    Op::VStoreStrided | Op::VScatter => {
+                        if let Word::R(_, ro, rl, rr) = word {
+                            ret_op = make_res_operand(ro);
+                            left_op = make_res_operand(rl);
+                            right_op = make_res_operand(rr);
+                        }
+                    }
                     Op::ReserveMemory => {
                         if let Word::I(_, ro, rl, i) = word {
                             ret_op = ResOperand::Reg(ro);
@@ -108,9 +136,28 @@ impl Dispatcher {
                     | Op::AddImmediate
                     | Op::BitAndImmediate
                     | Op::BitOrImmediate
+                    | Op::BitXorImmediate
                     | Op::Neg
+                    | Op::BitNot
                     | Op::LeftShift
-                    | Op::RightShift => {
+                    | Op::RightShift
+                    | Op::RightShiftLogical
+                    | Op::VLength
+                    | Op::FSqrt
+                    | Op::FAbs
+                    | Op::FNeg
+                    | Op::IntToFloat
+                    | Op::FloatToInt
+                    | Op::FReadFlags
+                    | Op::FClearFlags
+                    | Op::FReadRounding
+                    | Op::FSetRounding
+                    | Op::VLeftShift
+                    | Op::VRightShift
+                    | Op::VBroadcast
+                    | Op::VExtract
+                    | Op::VAbs
+                    | Op::VFAbs => {
                         if let Word::I(_, ro, rl, i) = word {
                             ret_op = ResOperand::Reg(ro);
                             left_op = make_res_operand(rl);
@@ -121,30 +168,88 @@ impl Dispatcher {
                     | Op::Subtract
                     | Op::Multiply
                     | Op::MultiplyNoOverflow
+                    | Op::MultiplyUnsigned
                     | Op::Divide
+                    | Op::DivideUnsigned
+                    | Op::Remainder
+                    | Op::RemainderUnsigned
                     | Op::Compare
+                    | Op::CompareUnsigned
+                    | Op::SetLessThan
+                    | Op::SetLessThanUnsigned
                     | Op::BitAnd
                     | Op::BitOr
+                    | Op::BitXor
+                    | Op::BitNor
+                    | Op::LeftShiftVariable
+                    | Op::RightShiftVariable
+                    | Op::RightShiftLogicalVariable
                     | Op::FAdd
                     | Op::FSubtract
                     | Op::FMultiply
                     | Op::FDivide
                     | Op::FCompare
+                    | Op::FMin
+                    | Op::FMax
                     | Op::VAdd
                     | Op::VSubtract
                     | Op::VMultiply
                     | Op::VDivide
-                    | Op::VLeftShift
-                    | Op::VRightShift
                     | Op::VFAdd
                     | Op::VFSubtract
                     | Op::VFMultiply
                     | Op::VFDivide
-                    | Op::VSum => {
+                    | Op::VSum
+                    | Op::VShuffle
+                    | Op::VMin
+                    | Op::VMax
+                    | Op::VFMin
+                    | Op::VFMax
+                    | Op::VCompareEqual
+                    | Op::VCompareLess
+                    | Op::VCompareLessEqual
+                    | Op::VFCompareEqual
+                    | Op::VFCompareLess
+                    | Op::VFCompareLessEqual
+                    | Op::VAnd
+                    | Op::VOr => {
+                        if let Word::R(_, ro, rl, rr) = word {
+                            ret_op = ResOperand::Reg(ro);
+                            left_op = make_res_operand(rl);
+                            right_op = make_res_operand(rr);
+                        }
+                    }
+                    Op::VBlend
+                    | Op::VAddMasked
+                    | Op::VSubtractMasked
+                    | Op::VMultiplyMasked
+                    | Op::VFAddMasked
+                    | Op::VFSubtractMasked
+                    | Op::VFMultiplyMasked => {
                         if let Word::R(_, ro, rl, rr) = word {
                             ret_op = ResOperand::Reg(ro);
                             left_op = make_res_operand(rl);
                             right_op = make_res_operand(rr);
+                            extra_op = make_res_operand(Register::v(0));
+                        }
+                    }
+                    Op::FMultiplyAdd
+                    | Op::FMultiplySubtract
+                    | Op::VFMultiplyAdd
+                    | Op::VFMultiplySubtract => {
+                        if let Word::R(_, ro, rl, rr) = word {
+                            ret_op = ResOperand::Reg(ro);
+                            left_op = make_res_operand(rl);
+                            right_op = make_res_operand(rr);
+                            extra_op = make_res_operand(ro); // the accumulator
+                        }
+                    }
+                    Op::VInsert => {
+                        if let Word::I(_, ro, rl, i) = word {
+                            ret_op = ResOperand::Reg(ro);
+                            left_op = make_res_operand(rl);
+                            right_op = ResOperand::Value(i);
+                            extra_op = make_res_operand(ro); // the lanes we keep
                         }
                     }
 
@@ -153,7 +258,11 @@ impl Dispatcher {
                     | Op::BranchGreater
                     | Op::BranchGreaterEqual
                     | Op::BranchLess
-                    | Op::BranchLessEqual => {
+                    | Op::BranchLessEqual
+                    | Op::BranchGreaterUnsigned
+                    | Op::BranchGreaterEqualUnsigned
+                    | Op::BranchLessUnsigned
+                    | Op::BranchLessEqualUnsigned => {
                         if let Word::I(_, ro, rl, i) = word {
                             ret_op = make_res_operand(ro);
                             left_op = make_res_operand(rl);
@@ -208,6 +317,9 @@ impl Dispatcher {
                     _speculative: false,
                     taken: fetched_word.branch_taken,
                     pc: fetched_word.pc,
+                    target: word.branch_target(fetched_word.pc),
+                    seq: fetched_word.seq,
+                    float_flags: 0,
                 };
 
                 let rob_index = rob.add_instruction(rob_inst); // add to reorder buffer
@@ -218,7 +330,7 @@ impl Dispatcher {
                         rob.get_mut(rob_index).as_mut().unwrap().destination =
                             Destination::Reg(reg);
                     }
-                } else if word.op() == Op::Divide || word.op() == Op::MultiplyNoOverflow {
+                } else if word.op().writes_high_low() {
                     rat.set(Register::High, rob_index);
                     rat.set(Register::Low, rob_index);
                 }
@@ -226,14 +338,23 @@ impl Dispatcher {
                 let res_inst = ResInst {
                     word,
                     pc: fetched_word.pc,
+                    seq: fetched_word.seq,
                     rob_index,
                     branch_taken: fetched_word.branch_taken,
+                    vector_lanes: registers.vector_lanes(),
+                    rounding: registers.rounding_mode(),
                     return_op: ret_op,
                     left_op,
                     right_op,
+                    extra_op,
                 };
 
                 rs.add_instruction(res_inst); // add to reservation station
+                stats_tracker.pipeline_event(
+                    fetched_word.seq,
+                    fetched_word.pc,
+                    PipelineStage::Dispatch,
+                );
 
                 stats_tracker.instructions_started += 1;
             } else {
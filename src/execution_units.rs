@@ -1,17 +1,21 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BytesMut};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
     branch_prediction::CoreBranchPredictor,
+    float::{self, RoundingMode},
     instructions::{Op, Register, Word},
     reorder_buffer::{Destination, ReorderBuffer, RobState, RobType, RobValue},
     reservation_station::ReservationStation,
+    vector::Vector,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum ExeOperand {
     Reg(Register),
     Value(i32),
-    Vector(u128),
+    Vector(Vector),
 }
 impl ExeOperand {
     pub fn to_reg(&self) -> Register {
@@ -28,26 +32,56 @@ impl ExeOperand {
         }
     }
 
-    pub fn to_vector(&self) -> u128 {
+    pub fn to_vector(&self) -> Vector {
         match self {
             Self::Vector(val) => *val,
             _ => panic!("ExeOperand {:?} is not a vector!", self),
         }
     }
+
+    pub fn to_float(&self) -> f32 {
+        f32::from_bits(self.to_value() as u32)
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct ExeInst {
     pub word: Word,
     pub pc: usize,
+    pub seq: u64,
     pub rob_index: usize,
     pub branch_taken: bool,
+    pub vector_lanes: usize,
+    pub rounding: RoundingMode,
     pub ret: ExeOperand,
     pub left: ExeOperand,
     pub right: ExeOperand,
+    pub extra: ExeOperand,
+}
+impl ExeInst {
+    /// where each lane of a strided or indexed vector load or store is, the base plus the lane
+    /// number times the stride or plus that lane of the offsets.
+    pub fn lane_addresses(&self) -> Vector {
+        let base = self.left.to_value();
+        match self.word.op() {
+            Op::VLoadStrided | Op::VStoreStrided => {
+                let stride = self.right.to_value();
+                let mut addresses = Vector::zero(self.vector_lanes);
+                for (lane, address) in addresses.lanes_mut().iter_mut().enumerate() {
+                    *address = base.wrapping_add(stride.wrapping_mul(lane as i32)) as u32;
+                }
+                addresses
+            }
+            Op::VGather | Op::VScatter => self
+                .right
+                .to_vector()
+                .map(|offset| base.wrapping_add(offset as i32) as u32),
+            op => panic!("{:?} doesn't access memory lane by lane", op),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum EUType {
     ALU,
     Branch,
@@ -56,8 +90,18 @@ pub enum EUType {
     VPU,
     System,
 }
+impl EUType {
+    pub const ALL: [EUType; 6] = [
+        EUType::ALU,
+        EUType::Branch,
+        EUType::Memory,
+        EUType::FPU,
+        EUType::VPU,
+        EUType::System,
+    ];
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionUnit {
     pub flavour: EUType,
     cycles_left: usize,
@@ -76,6 +120,10 @@ impl ExecutionUnit {
         self.cycles_left != 0
     }
 
+    pub fn cycles_left(&self) -> usize {
+        self.cycles_left
+    }
+
     pub fn flush(&mut self) {
         *self = Self::new(self.flavour);
     }
@@ -83,7 +131,7 @@ impl ExecutionUnit {
     /// does the thing
     pub fn start(&mut self, inst: ExeInst, rob: &mut ReorderBuffer) {
         self.inst = Some(inst);
-        self.cycles_left = inst.word.op().cycles_needed();
+        self.cycles_left = inst.word.op().cycles_needed(inst.vector_lanes);
         rob.get_mut(inst.rob_index).as_mut().unwrap().state = RobState::Executing;
     }
 
@@ -92,7 +140,7 @@ impl ExecutionUnit {
         &mut self,
         branch_predictor: &mut CoreBranchPredictor,
         rob: &mut ReorderBuffer,
-        reservation_stations: &mut Vec<ReservationStation>,
+        reservation_stations: &mut [ReservationStation],
         memory: &mut BytesMut,
     ) {
         // cycle
@@ -127,6 +175,8 @@ impl ExecutionUnit {
                             RobValue::Value(value) => {
                                 if !(inst.inst == RobType::Branch && value == -1)
                                     && inst.op != Op::ReserveMemory
+                                    && inst.op != Op::FReadFlags
+                                    && inst.op != Op::FReadRounding
                                 // && inst.op != Op::JumpRegister
                                 {
                                     // println!("{:?}", inst);
@@ -168,6 +218,12 @@ impl ExecutionUnit {
                 Destination::Memory((inst.left.to_value() + inst.right.to_value()) as usize), // start position
                 RobValue::Value(inst.ret.to_value()), // number of bytes
             ),
+            // the status register is only up to date when they commit, so that's when they run
+            Op::FReadFlags | Op::FReadRounding => {
+                (Destination::Reg(inst.ret.to_reg()), RobValue::Value(0))
+            }
+            Op::FClearFlags => (Destination::None, RobValue::Value(0)),
+            Op::FSetRounding => (Destination::None, RobValue::Value(inst.right.to_value())),
             _ => panic!("System command {:?} not implemented!", op),
         };
 
@@ -194,11 +250,11 @@ impl ExecutionUnit {
             dest = Destination::Reg(inst.ret.to_reg());
             let left = inst.left.to_value();
             let right = inst.right.to_value();
-            value = left + right;
+            value = left.wrapping_add(right);
         } else if op == Op::JumpRegister {
             let left = inst.left.to_value();
             let right = inst.right.to_value();
-            value = left + right;
+            value = left.wrapping_add(right);
         } else {
             let left = inst.ret.to_value();
             let right = inst.left.to_value();
@@ -211,6 +267,10 @@ impl ExecutionUnit {
                 Op::BranchGreaterEqual => left >= right,
                 Op::BranchLess => left < right,
                 Op::BranchLessEqual => left <= right,
+                Op::BranchGreaterUnsigned => (left as u32) > (right as u32),
+                Op::BranchGreaterEqualUnsigned => (left as u32) >= (right as u32),
+                Op::BranchLessUnsigned => (left as u32) < (right as u32),
+                Op::BranchLessEqualUnsigned => (left as u32) <= (right as u32),
                 _ => panic!("Branch does not implement this instruction: {:?}", op),
             };
 
@@ -236,9 +296,13 @@ impl ExecutionUnit {
         let left = inst.left.to_value();
         let right = inst.right.to_value();
 
-        if op == Op::Divide && right == 0 {
+        let divides = matches!(
+            op,
+            Op::Divide | Op::DivideUnsigned | Op::Remainder | Op::RemainderUnsigned
+        );
+        if divides && right == 0 {
             if let Some(rob_el) = rob.get_mut(inst.rob_index).as_mut() {
-                rob_el.state = RobState::Errored("tried to divide by 0");
+                rob_el.state = RobState::Errored(String::from("tried to divide by 0"));
                 rob_el.destination = Destination::Reg(dest);
                 rob_el.value = RobValue::Value(0);
             }
@@ -246,20 +310,45 @@ impl ExecutionUnit {
         }
 
         let out = match op {
-            Op::Add | Op::AddImmediate => RobValue::Value(left + right),
-            Op::Subtract | Op::SubtractImmediate => RobValue::Value(left - right),
-            Op::Compare => RobValue::Value((left - right).signum()),
-            Op::Multiply => RobValue::Value((left * right) as i32),
+            Op::Add | Op::AddImmediate => RobValue::Value(left.wrapping_add(right)),
+            Op::Subtract | Op::SubtractImmediate => RobValue::Value(left.wrapping_sub(right)),
+            Op::Compare => RobValue::Value(left.cmp(&right) as i32),
+            Op::Multiply => RobValue::Value(left.wrapping_mul(right)),
             Op::MultiplyNoOverflow => RobValue::Overflow(
                 ((left as i64 * right as i64) >> 32) as i32,
-                (left * right) as i32,
+                left.wrapping_mul(right),
+            ),
+            Op::MultiplyUnsigned => {
+                let product = left as u32 as u64 * right as u32 as u64;
+                RobValue::Overflow((product >> 32) as i32, product as i32)
+            }
+            Op::Divide => RobValue::Overflow(left.wrapping_div(right), left.wrapping_rem(right)),
+            Op::DivideUnsigned => RobValue::Overflow(
+                (left as u32 / right as u32) as i32,
+                (left as u32 % right as u32) as i32,
             ),
-            Op::Divide => RobValue::Overflow(left / right, left % right),
-            Op::LeftShift => RobValue::Value(left << right),
-            Op::RightShift => RobValue::Value(left >> right),
+            Op::Remainder => RobValue::Value(left.wrapping_rem(right)),
+            Op::RemainderUnsigned => RobValue::Value((left as u32 % right as u32) as i32),
+            Op::CompareUnsigned => RobValue::Value((left as u32).cmp(&(right as u32)) as i32),
+            Op::SetLessThan => RobValue::Value((left < right) as i32),
+            Op::SetLessThanUnsigned => RobValue::Value(((left as u32) < (right as u32)) as i32),
+            // shifts only use the bottom 5 bits of the amount
+            Op::LeftShift | Op::LeftShiftVariable => {
+                RobValue::Value(left.wrapping_shl(right as u32))
+            }
+            Op::RightShift | Op::RightShiftVariable => {
+                RobValue::Value(left.wrapping_shr(right as u32))
+            }
+            Op::RightShiftLogical | Op::RightShiftLogicalVariable => {
+                RobValue::Value((left as u32).wrapping_shr(right as u32) as i32)
+            }
             Op::BitAnd | Op::BitAndImmediate => RobValue::Value(left & right),
             Op::BitOr | Op::BitOrImmediate => RobValue::Value(left | right),
-            Op::Neg => RobValue::Value(-left),
+            Op::BitXor | Op::BitXorImmediate => RobValue::Value(left ^ right),
+            Op::BitNor => RobValue::Value(!(left | right)),
+            Op::BitNot => RobValue::Value(!left),
+            Op::Neg => RobValue::Value(left.wrapping_neg()),
+            Op::VLength => RobValue::Value(inst.vector_lanes as i32),
             _ => panic!("ALU does not implement this instruction: {:?}", op),
         };
 
@@ -277,12 +366,35 @@ impl ExecutionUnit {
         let left = f32::from_be_bytes(inst.left.to_value().to_be_bytes());
         let right = f32::from_be_bytes(inst.right.to_value().to_be_bytes());
 
-        let out = match op {
-            Op::FAdd | Op::FAddImmediate => left + right,
-            Op::FSubtract | Op::FSubtractImmediate => left - right,
-            Op::FCompare => (left - right).signum(),
-            Op::FMultiply => (left * right) as f32,
-            Op::FDivide => (left / right) as f32,
+        let mode = inst.rounding;
+
+        let (out, flags) = match op {
+            Op::FAdd | Op::FAddImmediate => float::add(left, right, mode),
+            Op::FSubtract | Op::FSubtractImmediate => float::add(left, -right, mode),
+            Op::FCompare => match left.partial_cmp(&right) {
+                Some(ordering) => (ordering as i32 as f32, 0),
+                None => (f32::NAN, float::INVALID),
+            },
+            Op::FMultiply => float::multiply(left, right, mode),
+            Op::FDivide => float::divide(left, right, mode),
+            Op::FMultiplyAdd => float::multiply_add(left, right, inst.extra.to_float(), mode),
+            Op::FMultiplySubtract => float::multiply_add(-left, right, inst.extra.to_float(), mode),
+            Op::FSqrt => float::sqrt(left, mode),
+            Op::FMin => (float::min(left, right), 0),
+            Op::FMax => (float::max(left, right), 0),
+            Op::FAbs => (left.abs(), 0),
+            Op::FNeg => (-left, 0),
+            Op::IntToFloat => float::from_int(inst.left.to_value(), mode),
+            Op::FloatToInt => {
+                let mode = match inst.right.to_value() {
+                    float::DYNAMIC_ROUNDING => mode,
+                    immediate => {
+                        RoundingMode::from_immediate(immediate).expect("ftoi needs a rounding mode")
+                    }
+                };
+                let (out, flags) = float::to_int(left, mode);
+                (f32::from_be_bytes(out.to_be_bytes()), flags)
+            }
             _ => panic!("FPU does not implement this instruction: {:?}", op),
         };
 
@@ -291,68 +403,128 @@ impl ExecutionUnit {
             rob_el.state = RobState::Finished;
             rob_el.destination = Destination::Reg(dest);
             rob_el.value = RobValue::Value(i32::from_be_bytes(out.to_be_bytes()));
+            rob_el.float_flags = flags;
         }
     }
 
     pub fn vpu(&mut self, rob: &mut ReorderBuffer, inst: ExeInst) {
         let op = inst.word.op();
         let dest = inst.ret.to_reg();
-        let value;
-
-        if op == Op::VSum {
-            let mut b = BytesMut::new();
-            b.put_u128(inst.right.to_vector());
-            let left = inst.left.to_value();
-            value = RobValue::Value(left + b.get_i32() + b.get_i32() + b.get_i32() + b.get_i32());
-        } else {
-            let mut b = BytesMut::new();
-            b.put_u128(inst.left.to_vector());
-            let left = [b.get_u32(), b.get_u32(), b.get_u32(), b.get_u32()];
-
-            b.put_u128(inst.right.to_vector());
-            let right = [b.get_u32(), b.get_u32(), b.get_u32(), b.get_u32()];
+        let mut flags = 0;
 
-            for i in 0..4 {
-                let il = left[i].to_be_bytes();
-                let ir = right[i].to_be_bytes();
-
-                let io = match op {
-                    Op::VAdd => (i32::from_be_bytes(il) + i32::from_be_bytes(ir)).to_be_bytes(),
-                    Op::VSubtract => {
-                        (i32::from_be_bytes(il) - i32::from_be_bytes(ir)).to_be_bytes()
-                    }
-                    Op::VMultiply => {
-                        (i32::from_be_bytes(il) * i32::from_be_bytes(ir)).to_be_bytes()
-                    }
-                    Op::VDivide => (i32::from_be_bytes(il) / i32::from_be_bytes(ir)).to_be_bytes(),
-                    Op::VLeftShift => {
-                        (i32::from_be_bytes(il) << i32::from_be_bytes(ir)).to_be_bytes()
-                    }
-                    Op::VRightShift => {
-                        (i32::from_be_bytes(il) >> i32::from_be_bytes(ir)).to_be_bytes()
-                    }
-                    Op::VFAdd => (f32::from_be_bytes(il) + f32::from_be_bytes(ir)).to_be_bytes(),
-                    Op::VFSubtract => {
-                        (f32::from_be_bytes(il) - f32::from_be_bytes(ir)).to_be_bytes()
-                    }
-                    Op::VFMultiply => {
-                        (f32::from_be_bytes(il) * f32::from_be_bytes(ir)).to_be_bytes()
+        let value = match op {
+            Op::VSum => {
+                let left = inst.left.to_value();
+                let lanes = inst.right.to_vector();
+                RobValue::Value(
+                    lanes
+                        .lanes()
+                        .iter()
+                        .fold(left, |sum, lane| sum.wrapping_add(*lane as i32)),
+                )
+            }
+            Op::VBroadcast => RobValue::Vector(Vector::splat(
+                inst.left.to_value() as u32,
+                inst.vector_lanes,
+            )),
+            Op::VExtract | Op::VInsert => {
+                let lane = inst.right.to_value();
+                if lane < 0 || lane as usize >= inst.vector_lanes {
+                    if let Some(rob_el) = rob.get_mut(inst.rob_index).as_mut() {
+                        rob_el.state = RobState::Errored(format!(
+                            "lane {} is out of range for {} lane vectors",
+                            lane, inst.vector_lanes
+                        ));
+                        rob_el.destination = Destination::Reg(dest);
+                        rob_el.value = RobValue::Value(0);
                     }
-                    Op::VFDivide => (f32::from_be_bytes(il) / f32::from_be_bytes(ir)).to_be_bytes(),
-                    _ => panic!("VPU does not implement this instruction: {:?}", op),
-                };
+                    return;
+                }
 
-                b.put(&io[..]);
+                if op == Op::VExtract {
+                    RobValue::Value(inst.left.to_vector().lanes()[lane as usize] as i32)
+                } else {
+                    let mut vector = inst.extra.to_vector();
+                    vector.lanes_mut()[lane as usize] = inst.left.to_value() as u32;
+                    RobValue::Vector(vector)
+                }
+            }
+            Op::VShuffle => {
+                let left = inst.left.to_vector();
+                let lanes = left.lanes();
+                RobValue::Vector(
+                    inst.right
+                        .to_vector()
+                        .map(|index| lanes[index as usize % lanes.len()]),
+                )
+            }
+            Op::VLeftShift | Op::VRightShift => {
+                let shift = inst.right.to_value() as u32;
+                RobValue::Vector(inst.left.to_vector().map(|lane| match op {
+                    Op::VLeftShift => (lane as i32).wrapping_shl(shift) as u32,
+                    _ => (lane as i32).wrapping_shr(shift) as u32,
+                }))
             }
+            Op::VAbs => RobValue::Vector(
+                inst.left
+                    .to_vector()
+                    .map(|lane| (lane as i32).wrapping_abs() as u32),
+            ),
+            Op::VFAbs => RobValue::Vector(inst.left.to_vector().map(|lane| lane & 0x7fff_ffff)),
+            Op::VFMultiplyAdd | Op::VFMultiplySubtract => {
+                let (left, right) = (inst.left.to_vector(), inst.right.to_vector());
+                let mut out = inst.extra.to_vector(); // the accumulator
+                for ((acc, l), r) in out
+                    .lanes_mut()
+                    .iter_mut()
+                    .zip(left.floats())
+                    .zip(right.floats())
+                {
+                    let l = if op == Op::VFMultiplySubtract { -l } else { l };
+                    let (lane, lane_flags) =
+                        float::multiply_add(l, r, f32::from_bits(*acc), inst.rounding);
+                    *acc = lane.to_bits();
+                    flags |= lane_flags;
+                }
+                RobValue::Vector(out)
+            }
+            Op::VBlend => {
+                let mask = inst.extra.to_vector();
+                RobValue::Vector(inst.right.to_vector().select(&inst.left.to_vector(), &mask))
+            }
+            _ => {
+                let mut out = inst.left.to_vector();
+                let right = inst.right.to_vector();
+                let mask = op.is_masked().then(|| inst.extra.to_vector());
+
+                if op == Op::VDivide && right.lanes().contains(&0) {
+                    if let Some(rob_el) = rob.get_mut(inst.rob_index).as_mut() {
+                        rob_el.state = RobState::Errored(String::from("tried to divide by 0"));
+                        rob_el.destination = Destination::Reg(dest);
+                        rob_el.value = RobValue::Vector(out);
+                    }
+                    return;
+                }
 
-            value = RobValue::Vector(b.get_u128());
-        }
+                for (i, (lane, r)) in out.lanes_mut().iter_mut().zip(right.lanes()).enumerate() {
+                    // masked lanes keep the left operand and can't raise anything
+                    if mask.is_some_and(|mask| mask.lanes()[i] == 0) {
+                        continue;
+                    }
+                    let (value, lane_flags) = vector_lane(op, *lane, *r, inst.rounding);
+                    *lane = value;
+                    flags |= lane_flags;
+                }
+                RobValue::Vector(out)
+            }
+        };
 
         // update the reorder buffer to say this instruction is now finished
         if let Some(rob_el) = rob.get_mut(inst.rob_index).as_mut() {
             rob_el.state = RobState::Finished;
             rob_el.destination = Destination::Reg(dest);
             rob_el.value = value;
+            rob_el.float_flags = flags;
         }
     }
 
@@ -365,11 +537,14 @@ impl ExecutionUnit {
                 let dest = inst.ret.to_reg();
                 let left = inst.left.to_value();
                 let right = inst.right.to_value();
-                (Destination::Reg(dest), RobValue::Value(left + right))
+                (
+                    Destination::Reg(dest),
+                    RobValue::Value(left.wrapping_add(right)),
+                )
             }
             Op::LoadMemory | Op::LoadHalfWord | Op::LoadChar | Op::VLoadMemory => {
                 let dest = inst.ret.to_reg();
-                let addr = (inst.left.to_value() + inst.right.to_value()) as usize;
+                let addr = inst.left.to_value().wrapping_add(inst.right.to_value()) as usize;
 
                 let value = if memory.len() < addr {
                     RobValue::Value(0)
@@ -377,22 +552,59 @@ impl ExecutionUnit {
                     RobValue::Value((&memory[addr..(addr + 4)]).get_i32())
                 } else if op == Op::LoadHalfWord && addr + 2 <= memory.len() {
                     RobValue::Value((&memory[addr..(addr + 2)]).get_u16() as i32)
-                } else if op == Op::LoadChar && addr + 1 <= memory.len() {
+                } else if op == Op::LoadChar && addr < memory.len() {
                     RobValue::Value((&memory[addr..addr + 1]).get_u8() as i32)
-                } else if op == Op::VLoadMemory && addr + 16 <= memory.len() {
-                    RobValue::Vector((&memory[addr..(addr + 16)]).get_u128())
+                } else if op == Op::VLoadMemory && addr + inst.vector_lanes * 4 <= memory.len() {
+                    RobValue::Vector(Vector::read(&memory[addr..], inst.vector_lanes))
+                } else if op == Op::VLoadMemory {
+                    RobValue::Vector(Vector::zero(inst.vector_lanes))
                 } else {
                     RobValue::Value(0)
                 };
 
                 (Destination::Reg(dest), value)
             }
+            Op::VLoadStrided | Op::VGather => {
+                // lanes outside memory read zero like lv does
+                let mut value = Vector::zero(inst.vector_lanes);
+                for (lane, address) in value
+                    .lanes_mut()
+                    .iter_mut()
+                    .zip(inst.lane_addresses().lanes())
+                {
+                    let address = *address as usize;
+                    if address + 4 <= memory.len() {
+                        *lane = (&memory[address..(address + 4)]).get_u32();
+                    }
+                }
+                (Destination::Reg(inst.ret.to_reg()), RobValue::Vector(value))
+            }
+            Op::VStoreStrided | Op::VScatter => {
+                let addresses = inst.lane_addresses();
+                if let Some(address) = addresses
+                    .lanes()
+                    .iter()
+                    .find(|address| **address as usize + 4 > memory.len())
+                {
+                    if let Some(rob_el) = rob.get_mut(inst.rob_index).as_mut() {
+                        rob_el.state = RobState::Errored(format!(
+                            "vector store to {} is outside memory",
+                            *address as i32
+                        ));
+                    }
+                    return;
+                }
+                (
+                    Destination::Lanes(addresses),
+                    RobValue::Vector(inst.ret.to_vector()),
+                )
+            }
             Op::VStoreMemory => {
                 let value = inst.ret.to_vector();
                 let left = inst.left.to_value();
                 let right = inst.right.to_value();
                 (
-                    Destination::Memory((left + right) as usize),
+                    Destination::Memory(left.wrapping_add(right) as usize),
                     RobValue::Vector(value),
                 )
             }
@@ -401,7 +613,7 @@ impl ExecutionUnit {
                 let left = inst.left.to_value();
                 let right = inst.right.to_value();
                 (
-                    Destination::Memory((left + right) as usize),
+                    Destination::Memory(left.wrapping_add(right) as usize),
                     RobValue::Value(value),
                 )
             }
@@ -421,3 +633,36 @@ impl ExecutionUnit {
         }
     }
 }
+
+/// One lane of a lane-wise vector op and the float exceptions it raised, masked ops do the
+/// same as their unmasked versions.
+fn vector_lane(op: Op, left: u32, right: u32, mode: RoundingMode) -> (u32, u32) {
+    let (il, ir) = (left as i32, right as i32);
+    let (fl, fr) = (f32::from_bits(left), f32::from_bits(right));
+    let mask = |set: bool| if set { u32::MAX } else { 0 };
+    let float = |(out, flags): (f32, u32)| (out.to_bits(), flags);
+
+    match op {
+        Op::VAdd | Op::VAddMasked => (il.wrapping_add(ir) as u32, 0),
+        Op::VSubtract | Op::VSubtractMasked => (il.wrapping_sub(ir) as u32, 0),
+        Op::VMultiply | Op::VMultiplyMasked => (il.wrapping_mul(ir) as u32, 0),
+        Op::VDivide => (il.wrapping_div(ir) as u32, 0),
+        Op::VFAdd | Op::VFAddMasked => float(float::add(fl, fr, mode)),
+        Op::VFSubtract | Op::VFSubtractMasked => float(float::add(fl, -fr, mode)),
+        Op::VFMultiply | Op::VFMultiplyMasked => float(float::multiply(fl, fr, mode)),
+        Op::VFDivide => float(float::divide(fl, fr, mode)),
+        Op::VMin => (il.min(ir) as u32, 0),
+        Op::VMax => (il.max(ir) as u32, 0),
+        Op::VFMin => (float::min(fl, fr).to_bits(), 0),
+        Op::VFMax => (float::max(fl, fr).to_bits(), 0),
+        Op::VCompareEqual => (mask(il == ir), 0),
+        Op::VCompareLess => (mask(il < ir), 0),
+        Op::VCompareLessEqual => (mask(il <= ir), 0),
+        Op::VFCompareEqual => (mask(fl == fr), 0),
+        Op::VFCompareLess => (mask(fl < fr), float::compare(fl, fr)),
+        Op::VFCompareLessEqual => (mask(fl <= fr), float::compare(fl, fr)),
+        Op::VAnd => (left & right, 0),
+        Op::VOr => (left | right, 0),
+        _ => panic!("VPU does not implement this instruction: {:?}", op),
+    }
+}
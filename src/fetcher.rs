@@ -1,26 +1,31 @@
 use std::collections::VecDeque;
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     branch_prediction::{BranchPredictor, CoreBranchPredictor},
     instructions::{Op, Register, Word},
+    pipeline_trace::PipelineStage,
     registers::Registers,
     stats::StatsTracker,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FetchedWord {
     pub word: Word,
     pub branch_taken: bool,
     pub pc: usize,
+    pub seq: u64, // unique id, in fetch order
 }
 
 /// Fetches instructions from the instruction memeory every cycle. Contains the instruction
 /// buffer.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Fetcher {
     fetch_amount: usize,
     pub buffer: VecDeque<FetchedWord>,
     buffer_capacity: usize,
+    next_seq: u64,
 }
 impl Fetcher {
     /// Creates a new Fetcher that can fetch at least `fetch_amount` per cycle and holds `buffer_capacity` instructions in a buffer.
@@ -29,16 +34,19 @@ impl Fetcher {
             fetch_amount,
             buffer: VecDeque::new(),
             buffer_capacity,
+            next_seq: 0,
         }
     }
 
     pub fn flush(&mut self) {
+        let next_seq = self.next_seq;
         *self = Self::new(self.fetch_amount, self.buffer_capacity);
+        self.next_seq = next_seq;
     }
 
-    fn fetch_one(
+    pub fn fetch_one(
         &mut self,
-        instructions: &Vec<Word>,
+        instructions: &[Word],
         registers: &mut Registers,
         branch_predictor: &mut CoreBranchPredictor,
         stats_tracker: &mut StatsTracker,
@@ -79,14 +87,17 @@ impl Fetcher {
             word,
             pc,
             branch_taken,
+            seq: self.next_seq,
         };
+        self.next_seq += 1;
 
+        stats_tracker.pipeline_event(fetched_word.seq, pc, PipelineStage::Fetch);
         self.buffer.push_back(fetched_word);
     }
 
     pub fn fetch(
         &mut self,
-        instructions: &Vec<Word>,
+        instructions: &[Word],
         registers: &mut Registers,
         branch_predictor: &mut CoreBranchPredictor,
         stats_tracker: &mut StatsTracker,
@@ -95,6 +106,10 @@ impl Fetcher {
             .fetch_amount
             .min(self.buffer_capacity - self.buffer.len());
 
+        if num_to_fetch < self.fetch_amount {
+            stats_tracker.stalls.fetch_buffer_full_cycles += 1;
+        }
+
         for _ in 0..num_to_fetch {
             self.fetch_one(instructions, registers, branch_predictor, stats_tracker);
         }
@@ -0,0 +1,410 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+// exception flags, in the bits risc-v keeps them in
+pub const INEXACT: u32 = 1;
+pub const UNDERFLOW: u32 = 2;
+pub const OVERFLOW: u32 = 4;
+pub const DIVIDE_BY_ZERO: u32 = 8;
+pub const INVALID: u32 = 16;
+
+const FLAG_NAMES: [(u32, &str); 5] = [
+    (INVALID, "invalid"),
+    (DIVIDE_BY_ZERO, "divide-by-zero"),
+    (OVERFLOW, "overflow"),
+    (UNDERFLOW, "underflow"),
+    (INEXACT, "inexact"),
+];
+
+/// `ftoi`'s immediate when it rounds however `fsrm` last said to.
+pub const DYNAMIC_ROUNDING: i32 = 4;
+
+/// How results that can't be represented exactly get rounded, set with `fsrm` and used by
+/// everything on the FPU and VPU, or picked directly by `ftoi`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    NearestEven,
+    TowardZero,
+    Down,
+    Up,
+}
+impl RoundingMode {
+    pub fn from_immediate(immediate: i32) -> Option<Self> {
+        match immediate {
+            0 => Some(Self::NearestEven),
+            1 => Some(Self::TowardZero),
+            2 => Some(Self::Down),
+            3 => Some(Self::Up),
+            _ => None,
+        }
+    }
+
+    pub fn to_immediate(self) -> i32 {
+        match self {
+            Self::NearestEven => 0,
+            Self::TowardZero => 1,
+            Self::Down => 2,
+            Self::Up => 3,
+        }
+    }
+
+    /// the names risc-v uses, `rne`, `rtz`, `rdn` and `rup`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "rne" => Some(Self::NearestEven),
+            "rtz" => Some(Self::TowardZero),
+            "rdn" => Some(Self::Down),
+            "rup" => Some(Self::Up),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NearestEven => "rne",
+            Self::TowardZero => "rtz",
+            Self::Down => "rdn",
+            Self::Up => "rup",
+        }
+    }
+
+    /// to a whole number.
+    pub fn round(self, value: f32) -> f32 {
+        match self {
+            Self::NearestEven => value.round_ties_even(),
+            Self::TowardZero => value.trunc(),
+            Self::Down => value.floor(),
+            Self::Up => value.ceil(),
+        }
+    }
+}
+
+/// Rounds a result that went to the nearest float, `error` being which side of it the exact
+/// result was, and works out which exceptions that raised.
+fn round(nearest: f32, error: Ordering, mode: RoundingMode) -> (f32, u32) {
+    if error == Ordering::Equal {
+        return (nearest, 0);
+    }
+
+    let out = match mode {
+        RoundingMode::NearestEven => nearest,
+        RoundingMode::TowardZero if nearest > 0.0 && error == Ordering::Less => nearest.next_down(),
+        RoundingMode::TowardZero if nearest < 0.0 && error == Ordering::Greater => {
+            nearest.next_up()
+        }
+        RoundingMode::Down if error == Ordering::Less => nearest.next_down(),
+        RoundingMode::Up if error == Ordering::Greater => nearest.next_up(),
+        _ => nearest,
+    };
+
+    let mut flags = INEXACT;
+    if nearest.is_infinite() || out.is_infinite() {
+        flags |= OVERFLOW;
+    }
+    if out == 0.0 || out.is_subnormal() {
+        flags |= UNDERFLOW;
+    }
+    (out, flags)
+}
+
+/// which side of `nearest` `exact` is, or equal when it overflowed to infinity.
+fn error(exact: f64, nearest: f32) -> Ordering {
+    if nearest.is_infinite() {
+        return if nearest > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        };
+    }
+    exact
+        .partial_cmp(&(nearest as f64))
+        .unwrap_or(Ordering::Equal)
+}
+
+/// NaN out of operands that weren't NaN is the invalid exception, anything infinite going in
+/// comes out exactly. NaNs come out as `f32::NAN` like risc-v's canonical NaN, which NaN the
+/// host picked depends on how the operation got compiled.
+fn special(out: f32, operands: &[f32]) -> Option<(f32, u32)> {
+    if out.is_nan() {
+        let invalid = operands.iter().all(|operand| !operand.is_nan());
+        Some((f32::NAN, if invalid { INVALID } else { 0 }))
+    } else if operands.iter().any(|operand| !operand.is_finite()) {
+        Some((out, 0))
+    } else {
+        None
+    }
+}
+
+/// `a + b`, and `a - b` as `add(a, -b)`.
+pub fn add(a: f32, b: f32, mode: RoundingMode) -> (f32, u32) {
+    let nearest = a + b;
+    if let Some(out) = special(nearest, &[a, b]) {
+        return out;
+    }
+
+    // the f64 sum and what it rounded off, which together are exact
+    let (a, b) = (a as f64, b as f64);
+    let sum = a + b;
+    let b_part = sum - a;
+    let lost = (a - (sum - b_part)) + (b - b_part);
+    let error = match nearest.is_infinite() {
+        true => error(0.0, nearest),
+        false => ((sum - nearest as f64) + lost)
+            .partial_cmp(&0.0)
+            .unwrap_or(Ordering::Equal),
+    };
+    round(nearest, error, mode)
+}
+
+pub fn multiply(a: f32, b: f32, mode: RoundingMode) -> (f32, u32) {
+    let nearest = a * b;
+    if let Some(out) = special(nearest, &[a, b]) {
+        return out;
+    }
+    round(nearest, error(a as f64 * b as f64, nearest), mode) // exact in f64
+}
+
+pub fn divide(a: f32, b: f32, mode: RoundingMode) -> (f32, u32) {
+    let nearest = a / b;
+    if b == 0.0 && a != 0.0 && a.is_finite() {
+        return (nearest, DIVIDE_BY_ZERO);
+    }
+    if let Some(out) = special(nearest, &[a, b]) {
+        return out;
+    }
+
+    // a - nearest * b is exact, and has the error's sign when b is positive
+    let remainder = a as f64 - nearest as f64 * b as f64;
+    let error = match nearest.is_infinite() {
+        true => error(0.0, nearest),
+        false => (remainder * b as f64)
+            .partial_cmp(&0.0)
+            .unwrap_or(Ordering::Equal),
+    };
+    round(nearest, error, mode)
+}
+
+pub fn sqrt(a: f32, mode: RoundingMode) -> (f32, u32) {
+    let nearest = a.sqrt();
+    if let Some(out) = special(nearest, &[a]) {
+        return out;
+    }
+
+    let remainder = a as f64 - nearest as f64 * nearest as f64;
+    round(
+        nearest,
+        remainder.partial_cmp(&0.0).unwrap_or(Ordering::Equal),
+        mode,
+    )
+}
+
+/// `a * b + c` rounded once.
+pub fn multiply_add(a: f32, b: f32, c: f32, mode: RoundingMode) -> (f32, u32) {
+    let nearest = a.mul_add(b, c);
+    if let Some(out) = special(nearest, &[a, b, c]) {
+        return out;
+    }
+
+    let (product, c) = (a as f64 * b as f64, c as f64);
+    let sum = product + c;
+    let c_part = sum - product;
+    let lost = (product - (sum - c_part)) + (c - c_part);
+    let error = match nearest.is_infinite() {
+        true => error(0.0, nearest),
+        false => ((sum - nearest as f64) + lost)
+            .partial_cmp(&0.0)
+            .unwrap_or(Ordering::Equal),
+    };
+    round(nearest, error, mode)
+}
+
+pub fn from_int(value: i32, mode: RoundingMode) -> (f32, u32) {
+    let nearest = value as f32;
+    round(nearest, error(value as f64, nearest), mode)
+}
+
+/// saturating when it doesn't fit and NaN is 0, both invalid.
+pub fn to_int(value: f32, mode: RoundingMode) -> (i32, u32) {
+    let rounded = mode.round(value);
+    if value.is_nan() || rounded < i32::MIN as f32 || rounded >= -(i32::MIN as f32) {
+        (rounded as i32, INVALID)
+    } else if rounded != value {
+        (rounded as i32, INEXACT)
+    } else {
+        (rounded as i32, 0)
+    }
+}
+
+/// the smaller of the two ignoring a NaN, -0 being smaller than 0.
+pub fn min(a: f32, b: f32) -> f32 {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => f32::NAN,
+        (true, false) => b,
+        (false, true) => a,
+        _ if a == b && a.is_sign_negative() => a,
+        _ if a == b => b,
+        _ => a.min(b),
+    }
+}
+
+/// the bigger of the two ignoring a NaN, 0 being bigger than -0.
+pub fn max(a: f32, b: f32) -> f32 {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => f32::NAN,
+        (true, false) => b,
+        (false, true) => a,
+        _ if a == b && a.is_sign_positive() => a,
+        _ if a == b => b,
+        _ => a.max(b),
+    }
+}
+
+/// NaN can't be ordered so ordered compares are invalid with one.
+pub fn compare(a: f32, b: f32) -> u32 {
+    if a.is_nan() || b.is_nan() {
+        INVALID
+    } else {
+        0
+    }
+}
+
+/// `--fp-traps`, a comma separated list of exceptions.
+pub fn parse_flags(s: &str) -> Result<u32, String> {
+    s.split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            FLAG_NAMES
+                .iter()
+                .find(|(_, flag)| *flag == name)
+                .map(|(bit, _)| *bit)
+                .ok_or_else(|| {
+                    format!(
+                        "{} isn't one of invalid, divide-by-zero, overflow, underflow or inexact",
+                        name
+                    )
+                })
+        })
+        .try_fold(0, |flags, bit| bit.map(|bit| flags | bit))
+}
+
+/// the names of the exceptions in `flags`, most serious first.
+pub fn flag_names(flags: u32) -> String {
+    FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [RoundingMode; 4] = [
+        RoundingMode::NearestEven,
+        RoundingMode::TowardZero,
+        RoundingMode::Down,
+        RoundingMode::Up,
+    ];
+
+    /// `op` in every mode, in the order of `MODES`.
+    fn each_mode(op: impl Fn(RoundingMode) -> (f32, u32)) -> Vec<(f32, u32)> {
+        MODES.into_iter().map(op).collect()
+    }
+
+    #[test]
+    fn just_past_the_biggest_float() {
+        let (max, inf) = (f32::MAX, f32::INFINITY);
+        assert_eq!(
+            each_mode(|mode| add(max, 1.0, mode)),
+            [
+                (max, INEXACT),
+                (max, INEXACT),
+                (max, INEXACT),
+                (inf, OVERFLOW | INEXACT),
+            ]
+        );
+        assert_eq!(
+            each_mode(|mode| add(-max, -1.0, mode)),
+            [
+                (-max, INEXACT),
+                (-max, INEXACT),
+                (-inf, OVERFLOW | INEXACT),
+                (-max, INEXACT),
+            ]
+        );
+    }
+
+    #[test]
+    fn far_past_the_biggest_float() {
+        let (max, inf) = (f32::MAX, f32::INFINITY);
+        assert_eq!(
+            each_mode(|mode| multiply(max, 2.0, mode)),
+            [
+                (inf, OVERFLOW | INEXACT),
+                (max, OVERFLOW | INEXACT),
+                (max, OVERFLOW | INEXACT),
+                (inf, OVERFLOW | INEXACT),
+            ]
+        );
+        assert_eq!(
+            each_mode(|mode| multiply(-max, 2.0, mode)),
+            [
+                (-inf, OVERFLOW | INEXACT),
+                (-max, OVERFLOW | INEXACT),
+                (-inf, OVERFLOW | INEXACT),
+                (-max, OVERFLOW | INEXACT),
+            ]
+        );
+    }
+
+    #[test]
+    fn half_the_smallest_subnormal() {
+        let tiny = f32::from_bits(1);
+        assert_eq!(
+            each_mode(|mode| multiply(tiny, 0.5, mode)),
+            [
+                (0.0, UNDERFLOW | INEXACT),
+                (0.0, UNDERFLOW | INEXACT),
+                (0.0, UNDERFLOW | INEXACT),
+                (tiny, UNDERFLOW | INEXACT),
+            ]
+        );
+        assert_eq!(
+            each_mode(|mode| multiply(-tiny, 0.5, mode)),
+            [
+                (-0.0, UNDERFLOW | INEXACT),
+                (-0.0, UNDERFLOW | INEXACT),
+                (-tiny, UNDERFLOW | INEXACT),
+                (-0.0, UNDERFLOW | INEXACT),
+            ]
+        );
+    }
+
+    #[test]
+    fn exact_subnormals_dont_underflow() {
+        let (tiny, two) = (f32::from_bits(1), f32::from_bits(2));
+        assert_eq!(each_mode(|mode| multiply(two, 0.5, mode)), [(tiny, 0); 4]);
+    }
+
+    #[test]
+    fn nans_come_out_canonical() {
+        let (nan, negative_nan) = (f32::from_bits(0x7fc0_1234), f32::from_bits(0xffff_ffff));
+        for mode in MODES {
+            assert_eq!(
+                add(negative_nan, -nan, mode).0.to_bits(),
+                f32::NAN.to_bits()
+            );
+            assert_eq!(
+                add(f32::INFINITY, f32::NEG_INFINITY, mode).0.to_bits(),
+                f32::NAN.to_bits()
+            );
+        }
+        assert_eq!(min(nan, negative_nan).to_bits(), f32::NAN.to_bits());
+        assert_eq!(max(negative_nan, 1.0), 1.0);
+        assert_eq!(min(0.0, -0.0).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(max(-0.0, 0.0).to_bits(), 0.0f32.to_bits());
+    }
+}
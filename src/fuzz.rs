@@ -0,0 +1,775 @@
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+};
+
+use bytes::BytesMut;
+use clap::Parser;
+
+use crate::{
+    assembler::assemble_source, cpu::CpuConfig, cpu::CPU, interpreter::Interpreter,
+    registers::Registers, Cli,
+};
+
+const DATA_WORDS: usize = 32; // small so loads and stores overlap a lot, room for 512 bit vectors
+const INT_REGISTERS: u32 = 12; // $1 to $12
+const FLOAT_REGISTERS: [u32; 4] = [13, 14, 15, 16];
+const STRIDE: u32 = 19; // scratch for strides and offset masks
+const ADDRESS: u32 = 20; // scratch for addresses and divisors
+const COUNTERS: [u32; 2] = [21, 22]; // one per level of loop nesting
+const LINK: u32 = 23;
+const DIVISOR: u32 = 2; // $v2, scratch for vector divisors
+
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+const MAX_CYCLES_WITHOUT_COMMIT: u64 = 10_000;
+
+const PREDICTORS: &[&str] = &[
+    "always-take",
+    "never-take",
+    "bimodal",
+    "one-bit-saturating",
+    "local",
+    "local:hist=2,bits=1",
+    "gshare",
+    "gshare:hist=4",
+    "loop+gshare",
+    "loop:conf=1+bimodal",
+];
+
+/// splitmix64, plenty for picking instructions and every run can be repeated from its seed.
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// from 0 up to but not including `n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// from `low` up to and including `high`.
+    pub fn between(&mut self, low: i32, high: i32) -> i32 {
+        low + self.below((high - low) as usize + 1) as i32
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// A random acasm program that always terminates: loops count down a register nothing else
+/// writes, every other branch jumps forwards and functions don't call anything.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub memory: Vec<String>,
+    pub instructions: Vec<String>,
+}
+impl Program {
+    pub fn generate(rng: &mut Rng, length: usize) -> Self {
+        let data: Vec<String> = (0..DATA_WORDS)
+            .map(|_| random_int(rng).to_string())
+            .collect();
+        let mut generator = Generator {
+            rng,
+            labels: 0,
+            functions: 0,
+        };
+        let functions = generator.rng.below(3);
+        generator.functions = functions;
+
+        let mut instructions = vec![String::from("main:")];
+        for r in 1..=INT_REGISTERS {
+            instructions.push(format!("li ${} {}", r, random_int(generator.rng)));
+        }
+        for r in FLOAT_REGISTERS {
+            instructions.push(format!("fli ${} {}", r, random_float(generator.rng)));
+        }
+        instructions.push(String::from("lv $v0 $0 data"));
+        instructions.push(String::from("lv $v1 $0 data"));
+
+        generator.block(&mut instructions, length, 0, true);
+        instructions.push(format!("exit ${}", generator.int()));
+
+        for function in 0..functions {
+            instructions.push(format!("func_{}:", function));
+            let length = generator.rng.below(6) + 1;
+            generator.block(&mut instructions, length, COUNTERS.len(), false);
+            instructions.push(format!("jr ${}", LINK));
+        }
+
+        Self {
+            memory: vec![format!("data: .int {}", data.join(", "))],
+            instructions,
+        }
+    }
+
+    pub fn source(&self) -> String {
+        let mut source = String::from(".memory\n");
+        for line in self.memory.iter() {
+            source += &format!("    {}\n", line);
+        }
+        source += "\n.instructions\n";
+        for line in self.instructions.iter() {
+            match line.ends_with(':') {
+                true => source += &format!("    {}\n", line),
+                false => source += &format!("        {}\n", line),
+            }
+        }
+        source
+    }
+}
+
+fn random_int(rng: &mut Rng) -> i32 {
+    match rng.below(4) {
+        0 => rng.between(-4, 4),
+        1 => rng.between(-1000, 1000),
+        2 => rng.next() as i32,
+        _ => *rng.pick(&[i32::MIN, i32::MAX, -1, 0, 1, 4, 16, 60]),
+    }
+}
+
+fn random_float(rng: &mut Rng) -> f32 {
+    match rng.below(3) {
+        0 => rng.between(-8, 8) as f32,
+        1 => rng.between(-100_000, 100_000) as f32 / 128.0,
+        _ => *rng.pick(&[0.0, -0.0, 0.1, 1e30, -1e-30]),
+    }
+}
+
+struct Generator<'a> {
+    rng: &'a mut Rng,
+    labels: usize,
+    functions: usize,
+}
+impl Generator<'_> {
+    fn int(&mut self) -> u32 {
+        self.rng.between(1, INT_REGISTERS as i32) as u32
+    }
+
+    fn float(&mut self) -> u32 {
+        *self.rng.pick(&FLOAT_REGISTERS)
+    }
+
+    fn vector(&mut self) -> u32 {
+        self.rng.below(2) as u32
+    }
+
+    fn label(&mut self, name: &str) -> String {
+        self.labels += 1;
+        format!("{}_{}", name, self.labels)
+    }
+
+    /// `statements` things to do, counting everything inside loops and branches.
+    fn block(&mut self, out: &mut Vec<String>, statements: usize, depth: usize, calls: bool) {
+        let mut left = statements;
+        while left > 0 {
+            let inner = self.rng.below(left.min(6)) + 1;
+            match self.rng.below(100) {
+                0..=39 => self.alu(out),
+                40..=59 => self.memory(out),
+                60..=69 => self.fpu(out),
+                70..=77 => self.vpu(out),
+                78..=87 => {
+                    let skip = self.label("skip");
+                    let branch = self.rng.pick(&[
+                        "be", "bne", "bg", "bge", "bl", "ble", "bgu", "bgeu", "blu", "bleu",
+                    ]);
+                    out.push(format!(
+                        "{} ${} ${} {}",
+                        branch,
+                        self.int(),
+                        self.int(),
+                        skip
+                    ));
+                    self.block(out, inner, depth, calls);
+                    out.push(format!("{}:", skip));
+                    left -= inner - 1;
+                }
+                88..=94 if depth < COUNTERS.len() => {
+                    let counter = COUNTERS[depth];
+                    let body = self.label("loop");
+                    out.push(format!("li ${} {}", counter, self.rng.between(1, 5)));
+                    out.push(format!("{}:", body));
+                    self.block(out, inner, depth + 1, calls);
+                    out.push(format!("subi ${} ${} 1", counter, counter));
+                    out.push(format!("bg ${} $0 {}", counter, body));
+                    left -= inner - 1;
+                }
+                95..=99 if calls && self.functions > 0 => {
+                    let function = self.rng.below(self.functions);
+                    out.push(format!("jal ${} func_{}", LINK, function));
+                }
+                _ => self.alu(out),
+            }
+            left -= 1;
+        }
+    }
+
+    fn alu(&mut self, out: &mut Vec<String>) {
+        let (d, l, r) = (self.int(), self.int(), self.int());
+        match self.rng.below(9) {
+            0..=2 => {
+                let op = self.rng.pick(&[
+                    "add", "sub", "mult", "and", "or", "xor", "nor", "cmp", "cmpu", "slt", "sltu",
+                    "lsftv", "rsftv", "rsftlv",
+                ]);
+                out.push(format!("{} ${} ${} ${}", op, d, l, r));
+            }
+            3 | 4 => {
+                let op = self.rng.pick(&["addi", "subi", "andi", "ori", "xori"]);
+                out.push(format!(
+                    "{} ${} ${} {}",
+                    op,
+                    d,
+                    l,
+                    self.rng.between(-100, 100)
+                ));
+            }
+            5 => {
+                let op = self.rng.pick(&["lsft", "rsft", "rsftl"]);
+                out.push(format!("{} ${} ${} {}", op, d, l, self.rng.between(0, 31)));
+            }
+            6 => {
+                // never divide by 0, that ends the program
+                out.push(format!("ori ${} ${} 1", ADDRESS, r));
+                match self.rng.below(3) {
+                    0 => {
+                        let op = self.rng.pick(&["div", "divu"]);
+                        out.push(format!("{} ${} ${}", op, l, ADDRESS));
+                        out.push(format!("{} ${}", self.rng.pick(&["mfhi", "mflo"]), d));
+                    }
+                    _ => {
+                        let op = self.rng.pick(&["rem", "remu"]);
+                        out.push(format!("{} ${} ${} ${}", op, d, l, ADDRESS));
+                    }
+                }
+            }
+            7 => {
+                let op = self.rng.pick(&["multno", "multu"]);
+                out.push(format!("{} ${} ${}", op, l, r));
+                out.push(format!("{} ${}", self.rng.pick(&["mfhi", "mflo"]), d));
+            }
+            _ => match self.rng.below(4) {
+                0 => out.push(format!("neg ${} ${}", d, l)),
+                1 => out.push(format!("not ${} ${}", d, l)),
+                2 => out.push(format!("mv ${} ${}", d, l)),
+                _ => out.push(format!("li ${} {}", d, random_int(self.rng))),
+            },
+        }
+    }
+
+    /// every access lands somewhere in `data`.
+    fn memory(&mut self, out: &mut Vec<String>) {
+        let (r, a) = (self.int(), self.int());
+        let (mask, access) = match self.rng.below(10) {
+            0 => (124, format!("lw ${} ${} data", r, ADDRESS)),
+            1 => (124, format!("lw ${} ${} data", self.float(), ADDRESS)),
+            2 | 3 => (124, format!("sw ${} ${} data", r, ADDRESS)),
+            4 => (127, format!("lc ${} ${} data", r, ADDRESS)),
+            5 => (127, format!("sc ${} ${} data", r, ADDRESS)),
+            6 => (64, format!("lv $v{} ${} data", self.vector(), ADDRESS)),
+            7 => (64, format!("sv $v{} ${} data", self.vector(), ADDRESS)),
+            8 => {
+                // a stride of 0 or 4 so 16 lanes stay inside the data
+                out.push(format!("andi ${} ${} 4", STRIDE, self.int()));
+                out.push(format!("andi ${} ${} 60", ADDRESS, a));
+                out.push(format!("addi ${} ${} data", ADDRESS, ADDRESS));
+                let op = self.rng.pick(&["lvs", "svs"]);
+                out.push(format!(
+                    "{} $v{} ${} ${}",
+                    op,
+                    self.vector(),
+                    ADDRESS,
+                    STRIDE
+                ));
+                return;
+            }
+            _ => {
+                // offsets from a random vector, made word aligned and small enough
+                let offsets = self.vector();
+                out.push(format!("li ${} 60", STRIDE));
+                out.push(format!("vbcast $v{} ${}", offsets, STRIDE));
+                out.push(format!(
+                    "vand $v{} $v{} $v{}",
+                    offsets,
+                    offsets,
+                    self.vector()
+                ));
+                out.push(format!("andi ${} ${} 60", ADDRESS, a));
+                out.push(format!("addi ${} ${} data", ADDRESS, ADDRESS));
+                let op = self.rng.pick(&["lvx", "svx"]);
+                out.push(format!(
+                    "{} $v{} ${} $v{}",
+                    op,
+                    self.vector(),
+                    ADDRESS,
+                    offsets
+                ));
+                return;
+            }
+        };
+        out.push(format!("andi ${} ${} {}", ADDRESS, a, mask));
+        out.push(access);
+    }
+
+    fn fpu(&mut self, out: &mut Vec<String>) {
+        let (d, l, r) = (self.float(), self.float(), self.float());
+        match self.rng.below(8) {
+            0 | 1 => {
+                let op = self.rng.pick(&["fadd", "fsub", "fmult", "fdiv"]);
+                out.push(format!("{} ${} ${} ${}", op, d, l, r));
+            }
+            2 => {
+                let op = self.rng.pick(&["faddi", "fsubi"]);
+                out.push(format!("{} ${} ${} {}", op, d, l, random_float(self.rng)));
+            }
+            3 => {
+                let op = self.rng.pick(&["fma", "fms", "fmin", "fmax"]);
+                out.push(format!("{} ${} ${} ${}", op, d, l, r));
+            }
+            4 => {
+                let op = self.rng.pick(&["fsqrt", "fabs", "fneg"]);
+                out.push(format!("{} ${} ${}", op, d, l));
+            }
+            5 => match self.rng.below(2) {
+                0 => out.push(format!("itof ${} ${}", d, self.int())),
+                _ => {
+                    let mode = self.rng.pick(&["rne", "rtz", "rdn", "rup", "dyn"]);
+                    out.push(format!("ftoi ${} ${} {}", self.int(), l, mode));
+                }
+            },
+            6 => match self.rng.below(4) {
+                0 => out.push(format!("frflags ${}", self.int())),
+                1 => out.push("fclrflags".to_string()),
+                2 => out.push(format!("frrm ${}", self.int())),
+                _ => {
+                    let mode = self.rng.pick(&["rne", "rtz", "rdn", "rup"]);
+                    out.push(format!("fsrm {}", mode));
+                }
+            },
+            _ => out.push(format!("fcmp ${} ${} ${}", self.int(), l, r)),
+        }
+    }
+
+    fn vpu(&mut self, out: &mut Vec<String>) {
+        let (d, l, r) = (self.vector(), self.vector(), self.vector());
+        match self.rng.below(9) {
+            0..=2 => {
+                let op = self.rng.pick(&[
+                    "vadd", "vsub", "vmult", "vfadd", "vfsub", "vfmult", "vfdiv", "vfma", "vfms",
+                ]);
+                out.push(format!("{} $v{} $v{} $v{}", op, d, l, r));
+            }
+            3 => {
+                let op = self.rng.pick(&[
+                    "vshuf", "vmin", "vmax", "vfmin", "vfmax", "vcmpeq", "vcmplt", "vcmple",
+                    "vfcmpeq", "vfcmplt", "vfcmple", "vand", "vor",
+                ]);
+                out.push(format!("{} $v{} $v{} $v{}", op, d, l, r));
+            }
+            4 => {
+                let op = self.rng.pick(&[
+                    "vblend", "vaddm", "vsubm", "vmultm", "vfaddm", "vfsubm", "vfmultm",
+                ]);
+                out.push(format!("{} $v{} $v{} $v{}", op, d, l, r));
+            }
+            5 => match self.rng.below(3) {
+                0 => out.push(format!("vbcast $v{} ${}", d, self.int())),
+                1 => out.push(format!(
+                    "vext ${} $v{} {}",
+                    self.int(),
+                    l,
+                    self.rng.below(4)
+                )),
+                _ => out.push(format!(
+                    "vins $v{} ${} {}",
+                    d,
+                    self.int(),
+                    self.rng.below(4)
+                )),
+            },
+            6 => {
+                let op = self.rng.pick(&["vabs", "vfabs"]);
+                out.push(format!("{} $v{} $v{}", op, d, l));
+            }
+            7 => {
+                // mostly with every lane made odd, a 0 lane ends the program
+                if self.rng.below(4) != 0 {
+                    out.push(format!("li ${} 1", ADDRESS));
+                    out.push(format!("vbcast $v{} ${}", DIVISOR, ADDRESS));
+                    out.push(format!("vor $v{} $v{} $v{}", DIVISOR, r, DIVISOR));
+                    out.push(format!("vdiv $v{} $v{} $v{}", d, l, DIVISOR));
+                } else {
+                    out.push(format!("vdiv $v{} $v{} $v{}", d, l, r));
+                }
+            }
+            _ => {
+                let (d, l) = (self.int(), self.int());
+                out.push(format!("vsum ${} ${} $v{}", d, l, r));
+            }
+        }
+    }
+}
+
+/// A random configuration as the flags a normal run takes, so failures can be repeated.
+pub fn random_flags(rng: &mut Rng) -> String {
+    let mut flags = format!(
+        "--rob-size {} --rob-max-retire {} --fetch-amount {} --fetch-buffer-capacity {} \
+         --dispatch-amount {}",
+        rng.pick(&[2, 3, 4, 8, 16, 32, 64]),
+        rng.between(1, 8),
+        rng.between(1, 8),
+        rng.between(1, 8),
+        rng.between(1, 8)
+    );
+    for rs in ["alu", "fpu", "vpu", "lsu", "branch"] {
+        flags += &format!(" --rs-{}-size {}", rs, rng.between(1, 6));
+    }
+    for eu in ["alu", "fpu", "vpu", "lsu", "branch"] {
+        flags += &format!(" --eu-{}-num {}", eu, rng.between(1, 3));
+    }
+    flags += &format!(" --vector-width {}", rng.pick(&[128, 256, 512]));
+    if rng.below(4) == 0 {
+        flags += " --fp-traps divide-by-zero,overflow";
+    }
+    flags + &format!(" -b {}", rng.pick(PREDICTORS))
+}
+
+fn config(flags: &str) -> CpuConfig {
+    let cli = Cli::parse_from(
+        ["aca", "fuzz.acasm"]
+            .into_iter()
+            .chain(flags.split_whitespace()),
+    );
+    cli.args.unwrap().into()
+}
+
+/// The architectural state a program ends in.
+#[derive(Debug, PartialEq)]
+pub struct FinalState {
+    pub registers: Registers,
+    pub memory: BytesMut,
+    pub exit_value: Option<i32>,
+    pub exit_error: Option<String>,
+    pub instructions: u64,
+}
+impl FinalState {
+    fn new(cpu: &mut CPU) -> Self {
+        let stats = cpu.finish();
+
+        Self {
+            registers: cpu.registers().clone(),
+            memory: cpu.memory().clone(),
+            exit_value: stats.exit_value,
+            exit_error: stats.exit_error,
+            instructions: stats.instructions_commited + stats.instructions_fast_forwarded,
+        }
+    }
+
+    fn interpreted(interpreter: Interpreter) -> Self {
+        Self {
+            registers: interpreter.registers().clone(),
+            memory: interpreter.memory().clone(),
+            exit_value: interpreter.exit_value,
+            exit_error: interpreter.exit_error,
+            instructions: interpreter.executed,
+        }
+    }
+
+    /// how `other` ended up somewhere else.
+    fn differences(&self, other: &FinalState) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        for (reg, value) in self.registers.general() {
+            let other = other.registers.get(reg);
+            if value != other {
+                differences.push(format!("{} is {}, expected {}", reg, other, value));
+            }
+        }
+        for (reg, value) in self.registers.vectors() {
+            let other = other.registers.get_vector(reg);
+            if value != other {
+                differences.push(format!("{} is {}, expected {}", reg, other, value));
+            }
+        }
+        if self.registers.float_flags() != other.registers.float_flags() {
+            differences.push(format!(
+                "float flags are {}, expected {}",
+                other.registers.float_flags(),
+                self.registers.float_flags()
+            ));
+        }
+        if self.registers.rounding_mode() != other.registers.rounding_mode() {
+            differences.push(format!(
+                "rounding mode is {:?}, expected {:?}",
+                other.registers.rounding_mode(),
+                self.registers.rounding_mode()
+            ));
+        }
+        for (addr, (value, other)) in self.memory.iter().zip(other.memory.iter()).enumerate() {
+            if value != other {
+                differences.push(format!("memory[{}] is {}, expected {}", addr, other, value));
+            }
+        }
+        if self.exit_value != other.exit_value {
+            differences.push(format!(
+                "exited with {:?}, expected {:?}",
+                other.exit_value, self.exit_value
+            ));
+        }
+        if self.exit_error != other.exit_error {
+            differences.push(format!(
+                "stopped with error {:?}, expected {:?}",
+                other.exit_error, self.exit_error
+            ));
+        }
+        if self.instructions != other.instructions {
+            differences.push(format!(
+                "committed {} instructions, expected {}",
+                other.instructions, self.instructions
+            ));
+        }
+        differences
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => String::from("panicked"),
+        },
+    }
+}
+
+/// Runs the program on the reference interpreter, on the same configuration so vectors are as
+/// wide and the same exceptions trap. None if it doesn't assemble, crashes or doesn't finish,
+/// which only happens to minimised programs.
+pub fn run_sequential(source: &str, flags: &str) -> Option<FinalState> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let (memory, instructions, _) = assemble_source(source);
+        let config = config(flags);
+        config.check_program(&instructions).ok()?;
+        let registers = Registers::new(config.vector_registers, config.vector_lanes());
+        let mut interpreter = Interpreter::new(instructions, memory, registers, config.float_traps);
+
+        match interpreter.run(MAX_INSTRUCTIONS) {
+            true => Some(FinalState::interpreted(interpreter)),
+            false => None,
+        }
+    }))
+    .ok()
+    .flatten()
+}
+
+/// Runs the program on the out of order core and says how it went wrong, if it did.
+pub fn check_out_of_order(source: &str, flags: &str, expected: &FinalState) -> Option<String> {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let (memory, instructions, _) = assemble_source(source);
+        let mut cpu = CPU::new(config(flags));
+        cpu.set_memory(memory);
+        cpu.load_program(instructions)?;
+
+        let mut last_commit = (0, 0); // (cycle, instructions committed)
+        while cpu.is_running() {
+            cpu.step();
+
+            let stats = cpu.stats();
+            if stats.instructions_commited != last_commit.1 {
+                last_commit = (stats.cycles, stats.instructions_commited);
+            }
+            if stats.cycles - last_commit.0 >= MAX_CYCLES_WITHOUT_COMMIT {
+                return Err(format!("nothing committed after cycle {}", last_commit.0));
+            }
+            if stats.instructions_commited > expected.instructions {
+                return Err(format!(
+                    "still running after committing {} instructions",
+                    stats.instructions_commited
+                ));
+            }
+        }
+        Ok(FinalState::new(&mut cpu))
+    }));
+
+    let state = match outcome {
+        Ok(Ok(state)) => state,
+        Ok(Err(hung)) => return Some(hung),
+        Err(payload) => return Some(format!("panicked: {}", panic_message(payload))),
+    };
+    let differences = expected.differences(&state);
+    match differences.is_empty() {
+        true => None,
+        false => Some(differences.join("\n")),
+    }
+}
+
+/// Throws away instructions, biggest chunks first, for as long as `fails` still holds.
+pub fn minimise(mut lines: Vec<String>, fails: impl Fn(&[String]) -> bool) -> Vec<String> {
+    let mut chunk = lines.len() / 2;
+    while chunk > 0 {
+        let mut removed = false;
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start + chunk).min(lines.len());
+            let candidate = [&lines[..start], &lines[end..]].concat();
+            if fails(&candidate) {
+                lines = candidate;
+                removed = true;
+            } else {
+                start += chunk;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    lines
+}
+
+pub struct Failure {
+    pub seed: u64,
+    pub flags: String,
+    pub problem: String,
+    pub program: Program, // minimised
+}
+
+/// Generates the program for `seed` and checks it on a random configuration.
+pub fn fuzz_one(seed: u64, length: usize) -> Option<Failure> {
+    let mut rng = Rng::new(seed);
+    let program = Program::generate(&mut rng, length);
+    let flags = random_flags(&mut rng);
+
+    let Some(expected) = run_sequential(&program.source(), &flags) else {
+        return Some(Failure {
+            seed,
+            flags,
+            problem: String::from("doesn't finish on the reference interpreter"),
+            program,
+        });
+    };
+    let problem = check_out_of_order(&program.source(), &flags, &expected)?;
+
+    let fails = |instructions: &[String]| {
+        let candidate = Program {
+            memory: program.memory.clone(),
+            instructions: instructions.to_vec(),
+        };
+        let source = candidate.source();
+        match run_sequential(&source, &flags) {
+            Some(expected) => check_out_of_order(&source, &flags, &expected).is_some(),
+            None => false,
+        }
+    };
+    let instructions = minimise(program.instructions.clone(), fails);
+    let program = Program {
+        memory: program.memory,
+        instructions,
+    };
+    let problem = run_sequential(&program.source(), &flags)
+        .and_then(|expected| check_out_of_order(&program.source(), &flags, &expected))
+        .unwrap_or(problem);
+
+    Some(Failure {
+        seed,
+        flags,
+        problem,
+        program,
+    })
+}
+
+/// Checks `runs` programs starting from `seed`, writing each failure to `out` minimised.
+/// Returns how many failed.
+pub fn fuzz(seed: u64, runs: u64, length: usize, out: &str) -> u64 {
+    // the out of order core panicking is a failure like any other, not worth a backtrace
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failures = 0;
+    for seed in seed..seed.saturating_add(runs) {
+        let Some(failure) = fuzz_one(seed, length) else {
+            continue;
+        };
+        failures += 1;
+
+        fs::create_dir_all(out).expect("couldn't create the fuzz output directory");
+        let path = format!("{}/seed_{}.acasm", out, failure.seed);
+        let mut source = format!("// run with: aca {} {}\n", path, failure.flags);
+        for line in failure.problem.lines() {
+            source += &format!("// {}\n", line);
+        }
+        source += &failure.program.source();
+        fs::write(&path, source).expect("couldn't write failing program");
+
+        println!(
+            "seed {} failed with `{}`, minimised to {} lines in {}:",
+            failure.seed,
+            failure.flags,
+            failure.program.instructions.len(),
+            path
+        );
+        for line in failure.problem.lines() {
+            println!(" - {}", line);
+        }
+    }
+
+    panic::set_hook(hook);
+    println!("{} programs, {} failed", runs, failures);
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_programs_finish() {
+        for seed in 0..50 {
+            let program = Program::generate(&mut Rng::new(seed), 40);
+            assert!(
+                run_sequential(&program.source(), "").is_some(),
+                "seed {} doesn't finish:\n{}",
+                seed,
+                program.source()
+            );
+        }
+    }
+
+    #[test]
+    fn out_of_order_matches_sequential() {
+        // the first few found a full rob letting loads past older stores, and deadlocking
+        let seeds = [16696, 17075, 17789, 19073, 19940]
+            .into_iter()
+            .chain(0..100);
+        for seed in seeds {
+            if let Some(failure) = fuzz_one(seed, 40) {
+                panic!(
+                    "seed {} failed with `{}`:\n{}\n{}",
+                    seed,
+                    failure.flags,
+                    failure.problem,
+                    failure.program.source()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn minimise_keeps_what_fails() {
+        let lines: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let fails = |lines: &[String]| {
+            lines.contains(&String::from("17")) && lines.contains(&String::from("60"))
+        };
+
+        assert_eq!(minimise(lines, fails), vec!["17", "60"]);
+    }
+}
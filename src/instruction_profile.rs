@@ -0,0 +1,247 @@
+use std::{collections::HashMap, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assembler::SourceInfo,
+    instructions::{Op, Word},
+    pipeline_trace::{PipelineEvent, PipelineStage},
+};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct InstructionRecord {
+    pub committed: u64,
+    pub squashed: u64,
+    pub dispatch_to_issue: u64, // summed over every committed execution
+    pub issue_to_commit: u64,
+}
+impl InstructionRecord {
+    pub fn add(&mut self, other: &InstructionRecord) {
+        self.committed += other.committed;
+        self.squashed += other.squashed;
+        self.dispatch_to_issue += other.dispatch_to_issue;
+        self.issue_to_commit += other.issue_to_commit;
+    }
+
+    pub fn mean_dispatch_to_issue(&self) -> f64 {
+        self.dispatch_to_issue as f64 / self.committed as f64
+    }
+
+    pub fn mean_issue_to_commit(&self) -> f64 {
+        self.issue_to_commit as f64 / self.committed as f64
+    }
+}
+
+/// Straight line code from `start` up to and including `end`.
+#[derive(Debug, Clone, Copy)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Splits the program before every branch target and after every instruction that can change
+/// the pc.
+pub fn basic_blocks(instructions: &[Word]) -> Vec<BasicBlock> {
+    let mut leaders = vec![false; instructions.len() + 1];
+    leaders[0] = true;
+
+    for (pc, word) in instructions.iter().enumerate() {
+        if let Some(target) = word.branch_target(pc) {
+            if target < leaders.len() {
+                leaders[target] = true;
+            }
+        }
+        let ends_block = match word.op() {
+            Op::JumpRegister | Op::Exit => true,
+            _ => word.branch_target(pc).is_some(),
+        };
+        if ends_block {
+            leaders[pc + 1] = true;
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for (pc, leader) in leaders.into_iter().enumerate().skip(1) {
+        if leader {
+            blocks.push(BasicBlock { start, end: pc - 1 });
+            start = pc;
+        }
+    }
+    blocks
+}
+
+/// How every static instruction went through the pipeline, built from pipeline events.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstructionProfile {
+    records: HashMap<usize, InstructionRecord>,
+    in_flight: HashMap<u64, (u64, u64)>, // seq -> (dispatch cycle, issue cycle)
+}
+impl InstructionProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, cycle: u64, event: &PipelineEvent) {
+        match event.stage {
+            PipelineStage::Dispatch => {
+                self.in_flight.insert(event.seq, (cycle, cycle));
+            }
+            PipelineStage::Issue => {
+                if let Some(times) = self.in_flight.get_mut(&event.seq) {
+                    times.1 = cycle;
+                }
+            }
+            PipelineStage::Commit => {
+                let record = self.records.entry(event.pc).or_default();
+                record.committed += 1;
+                if let Some((dispatch, issue)) = self.in_flight.remove(&event.seq) {
+                    record.dispatch_to_issue += issue - dispatch;
+                    record.issue_to_commit += cycle - issue;
+                }
+            }
+            PipelineStage::Squash => {
+                self.records.entry(event.pc).or_default().squashed += 1;
+                self.in_flight.remove(&event.seq);
+            }
+            PipelineStage::Fetch | PipelineStage::Complete => (),
+        }
+    }
+
+    pub fn get(&self, pc: usize) -> InstructionRecord {
+        self.records.get(&pc).copied().unwrap_or_default()
+    }
+
+    pub fn block(&self, block: &BasicBlock) -> InstructionRecord {
+        let mut record = InstructionRecord::default();
+        for pc in block.start..=block.end {
+            record.add(&self.get(pc));
+        }
+        record
+    }
+
+    pub fn report(&self, instructions: &[Word], source: &[SourceInfo]) -> String {
+        let mut out = String::from("Instruction profile:\n");
+        out += &format!(
+            "{:>6} {:>6}  {:<20} {:<28} {:>10} {:>9} {:>11} {:>11}\n",
+            "PC",
+            "Line",
+            "Location",
+            "Source",
+            "Committed",
+            "Squashed",
+            "Disp->Issue",
+            "Issue->Comm"
+        );
+        for pc in 0..instructions.len() {
+            let record = self.get(pc);
+            let (line, location, text) = describe(source, pc);
+            out += &format!(
+                "{:>6} {:>6}  {:<20} {:<28} {:>10} {:>9} {:>11.2} {:>11.2}\n",
+                pc,
+                line,
+                location,
+                text,
+                record.committed,
+                record.squashed,
+                record.mean_dispatch_to_issue(),
+                record.mean_issue_to_commit()
+            );
+        }
+
+        out += "\nBasic block profile:\n";
+        out += &format!(
+            "{:>11}  {:<20} {:>10} {:>10} {:>9} {:>11} {:>11}\n",
+            "PCs", "Location", "Executed", "Committed", "Squashed", "Disp->Issue", "Issue->Comm"
+        );
+        for block in basic_blocks(instructions) {
+            let record = self.block(&block);
+            let (_, location, _) = describe(source, block.start);
+            out += &format!(
+                "{:>11}  {:<20} {:>10} {:>10} {:>9} {:>11.2} {:>11.2}\n",
+                format!("{}-{}", block.start, block.end),
+                location,
+                self.get(block.start).committed,
+                record.committed,
+                record.squashed,
+                record.mean_dispatch_to_issue(),
+                record.mean_issue_to_commit()
+            );
+        }
+
+        out
+    }
+
+    /// one row per static instruction, with the basic block it starts at so blocks can be
+    /// grouped by.
+    pub fn write_csv(
+        &self,
+        path: &str,
+        instructions: &[Word],
+        source: &[SourceInfo],
+    ) -> io::Result<()> {
+        let mut out = String::from(
+            "pc,line,location,source,block,committed,squashed,mean_dispatch_to_issue,mean_issue_to_commit\n",
+        );
+
+        for block in basic_blocks(instructions) {
+            for pc in block.start..=block.end {
+                let record = self.get(pc);
+                let (line, location, text) = describe(source, pc);
+                out += &format!(
+                    "{},{},{},\"{}\",{},{},{},{:.4},{:.4}\n",
+                    pc,
+                    line,
+                    location,
+                    text.replace('"', "\"\""),
+                    block.start,
+                    record.committed,
+                    record.squashed,
+                    record.mean_dispatch_to_issue(),
+                    record.mean_issue_to_commit()
+                );
+            }
+        }
+
+        fs::write(path, out)
+    }
+}
+
+fn describe(source: &[SourceInfo], pc: usize) -> (String, String, String) {
+    source.get(pc).map_or_else(
+        || (String::new(), String::new(), String::new()),
+        |s| (s.line.to_string(), s.location(), s.text.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_source;
+
+    #[test]
+    fn basic_blocks_split_at_targets_and_after_branches() {
+        let (_, instructions, _) = assemble_source(
+            "
+.instructions
+    main:
+        li $1 0
+        li $2 5
+    loop:
+        addi $1 $1 1
+        bl $1 $2 loop
+        j end
+        jr $2
+        li $3 1
+    end:
+        exit $1
+",
+        );
+
+        let blocks: Vec<(usize, usize)> = basic_blocks(&instructions)
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect();
+        assert_eq!(blocks, vec![(0, 1), (2, 3), (4, 4), (5, 5), (6, 6), (7, 7)]);
+    }
+}
@@ -1,6 +1,15 @@
-use crate::{execution_units::EUType, reorder_buffer::RobType};
+use core::fmt::{self, Display};
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    execution_units::EUType,
+    float::{RoundingMode, DYNAMIC_ROUNDING},
+    reorder_buffer::RobType,
+};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Register {
     ProgramCounter,
     High,
@@ -23,9 +32,63 @@ impl Register {
             _ => false,
         }
     }
+
+    /// where the register lives in its register file, general registers after $pc, $hi and
+    /// $lo and vector registers in their own.
+    pub fn index(&self) -> usize {
+        match self {
+            Self::ProgramCounter => 0,
+            Self::High => 1,
+            Self::Low => 2,
+            Self::General(r) => 3 + *r as usize,
+            Self::Vector(r) => *r as usize,
+        }
+    }
+
+    /// the general register at `index` in the register file.
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            0 => Self::ProgramCounter,
+            1 => Self::High,
+            2 => Self::Low,
+            _ => Self::General(index as u32 - 3),
+        }
+    }
+}
+impl Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ProgramCounter => write!(f, "$pc"),
+            Self::High => write!(f, "$hi"),
+            Self::Low => write!(f, "$lo"),
+            Self::General(r) => write!(f, "${}", r),
+            Self::Vector(r) => write!(f, "$v{}", r),
+        }
+    }
+}
+impl FromStr for Register {
+    type Err = String;
+
+    /// the same names as Display: `$3`, `$v0`, `$pc`, `$hi` and `$lo`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s
+            .strip_prefix('$')
+            .ok_or_else(|| format!("{} is not a register", s))?;
+        let bad_register = |_| format!("{} is not a register", s);
+
+        return match name {
+            "pc" => Ok(Self::ProgramCounter),
+            "hi" => Ok(Self::High),
+            "lo" => Ok(Self::Low),
+            _ => match name.strip_prefix('v') {
+                Some(r) => r.parse().map(Self::Vector).map_err(bad_register),
+                None => name.parse().map(Self::General).map_err(bad_register),
+            },
+        };
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Op {
     LoadImmediate,
     LoadMemory,
@@ -48,12 +111,31 @@ pub enum Op {
     Neg,
     LeftShift,
     RightShift,
+    BitXor,
+    BitXorImmediate,
+    BitNor,
+    BitNot,
+    RightShiftLogical,
+    LeftShiftVariable,
+    RightShiftVariable,
+    RightShiftLogicalVariable,
+    SetLessThan,
+    SetLessThanUnsigned,
+    CompareUnsigned,
+    MultiplyUnsigned,
+    DivideUnsigned,
+    Remainder,
+    RemainderUnsigned,
     BranchEqual,
     BranchNotEqual,
     BranchGreater,
     BranchGreaterEqual,
     BranchLess,
     BranchLessEqual,
+    BranchGreaterUnsigned,
+    BranchGreaterEqualUnsigned,
+    BranchLessUnsigned,
+    BranchLessEqualUnsigned,
     Jump,
     JumpRegister,
     JumpAndLink,
@@ -65,8 +147,25 @@ pub enum Op {
     FMultiply,
     FDivide,
     FCompare,
+    FMultiplyAdd,
+    FMultiplySubtract,
+    FSqrt,
+    FMin,
+    FMax,
+    FAbs,
+    FNeg,
+    IntToFloat,
+    FloatToInt,
+    FReadFlags,
+    FClearFlags,
+    FReadRounding,
+    FSetRounding,
     VLoadMemory,
     VStoreMemory,
+    VLoadStrided,
+    VStoreStrided,
+    VGather,
+    VScatter,
     VLeftShift,
     VRightShift,
     VAdd,
@@ -78,6 +177,34 @@ pub enum Op {
     VFMultiply,
     VFDivide,
     VSum,
+    VLength,
+    VBroadcast,
+    VExtract,
+    VInsert,
+    VShuffle,
+    VMin,
+    VMax,
+    VFMin,
+    VFMax,
+    VAbs,
+    VFAbs,
+    VCompareEqual,
+    VCompareLess,
+    VCompareLessEqual,
+    VFCompareEqual,
+    VFCompareLess,
+    VFCompareLessEqual,
+    VAnd,
+    VOr,
+    VBlend,
+    VAddMasked,
+    VSubtractMasked,
+    VMultiplyMasked,
+    VFAddMasked,
+    VFSubtractMasked,
+    VFMultiplyMasked,
+    VFMultiplyAdd,
+    VFMultiplySubtract,
     MoveFromHigh,
     MoveFromLow,
     ReserveMemory,
@@ -92,7 +219,11 @@ impl Op {
             | Op::BranchGreater
             | Op::BranchGreaterEqual
             | Op::BranchLess
-            | Op::BranchLessEqual => true,
+            | Op::BranchLessEqual
+            | Op::BranchGreaterUnsigned
+            | Op::BranchGreaterEqualUnsigned
+            | Op::BranchLessUnsigned
+            | Op::BranchLessEqualUnsigned => true,
             _ => false,
         }
     }
@@ -120,12 +251,31 @@ impl Op {
             Op::Neg => RobType::Register,
             Op::LeftShift => RobType::Register,
             Op::RightShift => RobType::Register,
+            Op::BitXor => RobType::Register,
+            Op::BitXorImmediate => RobType::Register,
+            Op::BitNor => RobType::Register,
+            Op::BitNot => RobType::Register,
+            Op::RightShiftLogical => RobType::Register,
+            Op::LeftShiftVariable => RobType::Register,
+            Op::RightShiftVariable => RobType::Register,
+            Op::RightShiftLogicalVariable => RobType::Register,
+            Op::SetLessThan => RobType::Register,
+            Op::SetLessThanUnsigned => RobType::Register,
+            Op::CompareUnsigned => RobType::Register,
+            Op::MultiplyUnsigned => RobType::Register,
+            Op::DivideUnsigned => RobType::Register,
+            Op::Remainder => RobType::Register,
+            Op::RemainderUnsigned => RobType::Register,
             Op::BranchEqual => RobType::Branch,
             Op::BranchNotEqual => RobType::Branch,
             Op::BranchGreater => RobType::Branch,
             Op::BranchGreaterEqual => RobType::Branch,
             Op::BranchLess => RobType::Branch,
             Op::BranchLessEqual => RobType::Branch,
+            Op::BranchGreaterUnsigned => RobType::Branch,
+            Op::BranchGreaterEqualUnsigned => RobType::Branch,
+            Op::BranchLessUnsigned => RobType::Branch,
+            Op::BranchLessEqualUnsigned => RobType::Branch,
             Op::Jump => RobType::Branch,
             Op::JumpRegister => RobType::Branch,
             Op::JumpAndLink => RobType::Register,
@@ -137,8 +287,25 @@ impl Op {
             Op::FMultiply => RobType::Register,
             Op::FDivide => RobType::Register,
             Op::FCompare => RobType::Register,
+            Op::FMultiplyAdd => RobType::Register,
+            Op::FMultiplySubtract => RobType::Register,
+            Op::FSqrt => RobType::Register,
+            Op::FMin => RobType::Register,
+            Op::FMax => RobType::Register,
+            Op::FAbs => RobType::Register,
+            Op::FNeg => RobType::Register,
+            Op::IntToFloat => RobType::Register,
+            Op::FloatToInt => RobType::Register,
+            Op::FReadFlags => RobType::Register,
+            Op::FClearFlags => RobType::System,
+            Op::FReadRounding => RobType::Register,
+            Op::FSetRounding => RobType::System,
             Op::VLoadMemory => RobType::LoadMemory,
             Op::VStoreMemory => RobType::StoreMemory,
+            Op::VLoadStrided => RobType::LoadMemory,
+            Op::VStoreStrided => RobType::StoreMemory,
+            Op::VGather => RobType::LoadMemory,
+            Op::VScatter => RobType::StoreMemory,
             Op::VLeftShift => RobType::Register,
             Op::VRightShift => RobType::Register,
             Op::VAdd => RobType::Register,
@@ -150,6 +317,34 @@ impl Op {
             Op::VFMultiply => RobType::Register,
             Op::VFDivide => RobType::Register,
             Op::VSum => RobType::Register,
+            Op::VLength => RobType::Register,
+            Op::VBroadcast => RobType::Register,
+            Op::VExtract => RobType::Register,
+            Op::VInsert => RobType::Register,
+            Op::VShuffle => RobType::Register,
+            Op::VMin => RobType::Register,
+            Op::VMax => RobType::Register,
+            Op::VFMin => RobType::Register,
+            Op::VFMax => RobType::Register,
+            Op::VAbs => RobType::Register,
+            Op::VFAbs => RobType::Register,
+            Op::VCompareEqual => RobType::Register,
+            Op::VCompareLess => RobType::Register,
+            Op::VCompareLessEqual => RobType::Register,
+            Op::VFCompareEqual => RobType::Register,
+            Op::VFCompareLess => RobType::Register,
+            Op::VFCompareLessEqual => RobType::Register,
+            Op::VAnd => RobType::Register,
+            Op::VOr => RobType::Register,
+            Op::VBlend => RobType::Register,
+            Op::VAddMasked => RobType::Register,
+            Op::VSubtractMasked => RobType::Register,
+            Op::VMultiplyMasked => RobType::Register,
+            Op::VFAddMasked => RobType::Register,
+            Op::VFSubtractMasked => RobType::Register,
+            Op::VFMultiplyMasked => RobType::Register,
+            Op::VFMultiplyAdd => RobType::Register,
+            Op::VFMultiplySubtract => RobType::Register,
             Op::MoveFromHigh => RobType::Register,
             Op::MoveFromLow => RobType::Register,
             Op::Exit => RobType::Branch,
@@ -181,12 +376,31 @@ impl Op {
             Op::Neg => EUType::ALU,
             Op::LeftShift => EUType::ALU,
             Op::RightShift => EUType::ALU,
+            Op::BitXor => EUType::ALU,
+            Op::BitXorImmediate => EUType::ALU,
+            Op::BitNor => EUType::ALU,
+            Op::BitNot => EUType::ALU,
+            Op::RightShiftLogical => EUType::ALU,
+            Op::LeftShiftVariable => EUType::ALU,
+            Op::RightShiftVariable => EUType::ALU,
+            Op::RightShiftLogicalVariable => EUType::ALU,
+            Op::SetLessThan => EUType::ALU,
+            Op::SetLessThanUnsigned => EUType::ALU,
+            Op::CompareUnsigned => EUType::ALU,
+            Op::MultiplyUnsigned => EUType::ALU,
+            Op::DivideUnsigned => EUType::ALU,
+            Op::Remainder => EUType::ALU,
+            Op::RemainderUnsigned => EUType::ALU,
             Op::BranchEqual => EUType::Branch,
             Op::BranchNotEqual => EUType::Branch,
             Op::BranchGreater => EUType::Branch,
             Op::BranchGreaterEqual => EUType::Branch,
             Op::BranchLess => EUType::Branch,
             Op::BranchLessEqual => EUType::Branch,
+            Op::BranchGreaterUnsigned => EUType::Branch,
+            Op::BranchGreaterEqualUnsigned => EUType::Branch,
+            Op::BranchLessUnsigned => EUType::Branch,
+            Op::BranchLessEqualUnsigned => EUType::Branch,
             Op::Jump => EUType::Branch,
             Op::JumpRegister => EUType::Branch,
             Op::JumpAndLink => EUType::Branch,
@@ -198,8 +412,25 @@ impl Op {
             Op::FMultiply => EUType::FPU,
             Op::FDivide => EUType::FPU,
             Op::FCompare => EUType::FPU,
+            Op::FMultiplyAdd => EUType::FPU,
+            Op::FMultiplySubtract => EUType::FPU,
+            Op::FSqrt => EUType::FPU,
+            Op::FMin => EUType::FPU,
+            Op::FMax => EUType::FPU,
+            Op::FAbs => EUType::FPU,
+            Op::FNeg => EUType::FPU,
+            Op::IntToFloat => EUType::FPU,
+            Op::FloatToInt => EUType::FPU,
+            Op::FReadFlags => EUType::System,
+            Op::FClearFlags => EUType::System,
+            Op::FReadRounding => EUType::System,
+            Op::FSetRounding => EUType::System,
             Op::VLoadMemory => EUType::Memory,
             Op::VStoreMemory => EUType::Memory,
+            Op::VLoadStrided => EUType::Memory,
+            Op::VStoreStrided => EUType::Memory,
+            Op::VGather => EUType::Memory,
+            Op::VScatter => EUType::Memory,
             Op::VLeftShift => EUType::VPU,
             Op::VRightShift => EUType::VPU,
             Op::VAdd => EUType::VPU,
@@ -211,6 +442,34 @@ impl Op {
             Op::VFMultiply => EUType::VPU,
             Op::VFDivide => EUType::VPU,
             Op::VSum => EUType::VPU,
+            Op::VLength => EUType::ALU,
+            Op::VBroadcast => EUType::VPU,
+            Op::VExtract => EUType::VPU,
+            Op::VInsert => EUType::VPU,
+            Op::VShuffle => EUType::VPU,
+            Op::VMin => EUType::VPU,
+            Op::VMax => EUType::VPU,
+            Op::VFMin => EUType::VPU,
+            Op::VFMax => EUType::VPU,
+            Op::VAbs => EUType::VPU,
+            Op::VFAbs => EUType::VPU,
+            Op::VCompareEqual => EUType::VPU,
+            Op::VCompareLess => EUType::VPU,
+            Op::VCompareLessEqual => EUType::VPU,
+            Op::VFCompareEqual => EUType::VPU,
+            Op::VFCompareLess => EUType::VPU,
+            Op::VFCompareLessEqual => EUType::VPU,
+            Op::VAnd => EUType::VPU,
+            Op::VOr => EUType::VPU,
+            Op::VBlend => EUType::VPU,
+            Op::VAddMasked => EUType::VPU,
+            Op::VSubtractMasked => EUType::VPU,
+            Op::VMultiplyMasked => EUType::VPU,
+            Op::VFAddMasked => EUType::VPU,
+            Op::VFSubtractMasked => EUType::VPU,
+            Op::VFMultiplyMasked => EUType::VPU,
+            Op::VFMultiplyAdd => EUType::VPU,
+            Op::VFMultiplySubtract => EUType::VPU,
             Op::MoveFromHigh => EUType::Memory,
             Op::MoveFromLow => EUType::Memory,
             Op::Exit => EUType::System,
@@ -219,7 +478,9 @@ impl Op {
         }
     }
 
-    pub fn cycles_needed(&self) -> usize {
+    /// How long the op takes to execute on vectors of `vector_lanes` lanes. Strided and indexed
+    /// accesses go to memory a lane at a time, gather and scatter also read the offsets.
+    pub fn cycles_needed(&self, vector_lanes: usize) -> usize {
         match self {
             Op::LoadImmediate => 1,
             Op::LoadMemory => 2,
@@ -242,12 +503,31 @@ impl Op {
             Op::Neg => 1,
             Op::LeftShift => 1,
             Op::RightShift => 1,
+            Op::BitXor => 1,
+            Op::BitXorImmediate => 1,
+            Op::BitNor => 1,
+            Op::BitNot => 1,
+            Op::RightShiftLogical => 1,
+            Op::LeftShiftVariable => 1,
+            Op::RightShiftVariable => 1,
+            Op::RightShiftLogicalVariable => 1,
+            Op::SetLessThan => 1,
+            Op::SetLessThanUnsigned => 1,
+            Op::CompareUnsigned => 1,
+            Op::MultiplyUnsigned => 3,
+            Op::DivideUnsigned => 5,
+            Op::Remainder => 5,
+            Op::RemainderUnsigned => 5,
             Op::BranchEqual => 2,
             Op::BranchNotEqual => 2,
             Op::BranchGreater => 2,
             Op::BranchGreaterEqual => 2,
             Op::BranchLess => 2,
             Op::BranchLessEqual => 2,
+            Op::BranchGreaterUnsigned => 2,
+            Op::BranchGreaterEqualUnsigned => 2,
+            Op::BranchLessUnsigned => 2,
+            Op::BranchLessEqualUnsigned => 2,
             Op::Jump => 1,
             Op::JumpRegister => 1,
             Op::JumpAndLink => 1,
@@ -259,8 +539,25 @@ impl Op {
             Op::FMultiply => 4,
             Op::FDivide => 6,
             Op::FCompare => 2,
+            Op::FMultiplyAdd => 5,
+            Op::FMultiplySubtract => 5,
+            Op::FSqrt => 8,
+            Op::FMin => 2,
+            Op::FMax => 2,
+            Op::FAbs => 1,
+            Op::FNeg => 1,
+            Op::IntToFloat => 2,
+            Op::FloatToInt => 2,
+            Op::FReadFlags => 1,
+            Op::FClearFlags => 1,
+            Op::FReadRounding => 1,
+            Op::FSetRounding => 1,
             Op::VLoadMemory => 4,
             Op::VStoreMemory => 4,
+            Op::VLoadStrided => 4 + vector_lanes,
+            Op::VStoreStrided => 4 + vector_lanes,
+            Op::VGather => 4 + 2 * vector_lanes,
+            Op::VScatter => 4 + 2 * vector_lanes,
             Op::VLeftShift => 3,
             Op::VRightShift => 3,
             Op::VAdd => 3,
@@ -272,6 +569,34 @@ impl Op {
             Op::VFMultiply => 7,
             Op::VFDivide => 7,
             Op::VSum => 2,
+            Op::VLength => 1,
+            Op::VBroadcast => 2,
+            Op::VExtract => 2,
+            Op::VInsert => 2,
+            Op::VShuffle => 3,
+            Op::VMin => 3,
+            Op::VMax => 3,
+            Op::VFMin => 3,
+            Op::VFMax => 3,
+            Op::VAbs => 2,
+            Op::VFAbs => 2,
+            Op::VCompareEqual => 2,
+            Op::VCompareLess => 2,
+            Op::VCompareLessEqual => 2,
+            Op::VFCompareEqual => 3,
+            Op::VFCompareLess => 3,
+            Op::VFCompareLessEqual => 3,
+            Op::VAnd => 1,
+            Op::VOr => 1,
+            Op::VBlend => 2,
+            Op::VAddMasked => 3,
+            Op::VSubtractMasked => 3,
+            Op::VMultiplyMasked => 6,
+            Op::VFAddMasked => 3,
+            Op::VFSubtractMasked => 6,
+            Op::VFMultiplyMasked => 7,
+            Op::VFMultiplyAdd => 8,
+            Op::VFMultiplySubtract => 8,
             Op::MoveFromHigh => 1,
             Op::MoveFromLow => 1,
             Op::Exit => 1,
@@ -280,8 +605,30 @@ impl Op {
         }
     }
 
+    /// lanes where the mask in $v0 isn't set keep the left operand.
+    pub fn is_masked(&self) -> bool {
+        match self {
+            Op::VAddMasked
+            | Op::VSubtractMasked
+            | Op::VMultiplyMasked
+            | Op::VFAddMasked
+            | Op::VFSubtractMasked
+            | Op::VFMultiplyMasked => true,
+            _ => false,
+        }
+    }
+
+    /// multiplies into $hi and $lo instead of a destination, and divides with the quotient in
+    /// $hi and remainder in $lo.
+    pub fn writes_high_low(&self) -> bool {
+        match self {
+            Op::MultiplyNoOverflow | Op::MultiplyUnsigned | Op::Divide | Op::DivideUnsigned => true,
+            _ => false,
+        }
+    }
+
     pub fn updates_rat(&self) -> bool {
-        if *self == Op::MultiplyNoOverflow || *self == Op::Divide {
+        if self.writes_high_low() {
             return false;
         }
 
@@ -295,7 +642,7 @@ impl Op {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Word {
     R(Op, Register, Register, Register), // op, ro, rl, rr
     I(Op, Register, Register, i32),      // op, ro, rl, immediate
@@ -312,6 +659,32 @@ impl Word {
         }
     }
 
+    /// every register the instruction names, and $v0 if it's masked.
+    pub fn registers(&self) -> Vec<Register> {
+        let mut registers = match *self {
+            Word::R(_, ro, rl, rr) => vec![ro, rl, rr],
+            Word::I(_, ro, rl, _) => vec![ro, rl],
+            Word::JI(_, _) => vec![],
+            Word::JR(_, r) => vec![r],
+        };
+        if self.op().is_masked() {
+            registers.push(Register::v(0));
+        }
+        registers
+    }
+
+    /// Where this instruction sends the pc if it is a branch or jump with a known target.
+    pub fn branch_target(&self, pc: usize) -> Option<usize> {
+        match self {
+            Word::I(op, _, _, immediate) if op.is_predictable_branch() => {
+                Some((pc as i32 + immediate) as usize)
+            }
+            Word::I(Op::JumpAndLink, _, _, absolute) => Some(*absolute as usize),
+            Word::JI(Op::Jump, absolute) => Some(*absolute as usize),
+            _ => None,
+        }
+    }
+
     pub fn load_immediate(ro: u32, immediate: i32) -> Word {
         Word::I(
             Op::LoadImmediate,
@@ -411,6 +784,44 @@ impl Word {
         Word::R(Op::Divide, Register::High, Register::g(rl), Register::g(rr))
     }
 
+    pub fn multiply_unsigned(rl: u32, rr: u32) -> Word {
+        // sig = HIGH, insig = LOW
+        Word::R(
+            Op::MultiplyUnsigned,
+            Register::High,
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn divide_unsigned(rl: u32, rr: u32) -> Word {
+        // quotent = HIGH, remainder = LOW
+        Word::R(
+            Op::DivideUnsigned,
+            Register::High,
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn remainder(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::Remainder,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn remainder_unsigned(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::RemainderUnsigned,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
     pub fn bit_and(ro: u32, rl: u32, rr: u32) -> Word {
         Word::R(
             Op::BitAnd,
@@ -442,6 +853,37 @@ impl Word {
         )
     }
 
+    pub fn bit_xor(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::BitXor,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn bit_xor_immediate(ro: u32, rl: u32, immediate: i32) -> Word {
+        Word::I(
+            Op::BitXorImmediate,
+            Register::g(ro),
+            Register::g(rl),
+            immediate,
+        )
+    }
+
+    pub fn bit_nor(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::BitNor,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn bit_not(ro: u32, rl: u32) -> Word {
+        Word::I(Op::BitNot, Register::g(ro), Register::g(rl), 0)
+    }
+
     pub fn neg(ro: u32, rl: u32) -> Word {
         Word::I(Op::Neg, Register::g(ro), Register::g(rl), 0)
     }
@@ -454,6 +896,60 @@ impl Word {
         Word::I(Op::RightShift, Register::g(ro), Register::g(rl), immediate)
     }
 
+    pub fn right_shift_logical(ro: u32, rl: u32, immediate: i32) -> Word {
+        Word::I(
+            Op::RightShiftLogical,
+            Register::g(ro),
+            Register::g(rl),
+            immediate,
+        )
+    }
+
+    pub fn left_shift_variable(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::LeftShiftVariable,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn right_shift_variable(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::RightShiftVariable,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn right_shift_logical_variable(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::RightShiftLogicalVariable,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn set_less_than(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::SetLessThan,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn set_less_than_unsigned(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::SetLessThanUnsigned,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
     pub fn compare(ro: u32, rl: u32, rr: u32) -> Word {
         Word::R(
             Op::Compare,
@@ -463,6 +959,15 @@ impl Word {
         )
     }
 
+    pub fn compare_unsigned(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::CompareUnsigned,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
     pub fn branch_equal(rr: u32, rl: u32, relative: i32) -> Word {
         Word::I(Op::BranchEqual, Register::g(rr), Register::g(rl), relative)
     }
@@ -507,6 +1012,42 @@ impl Word {
         )
     }
 
+    pub fn branch_less_unsigned(rr: u32, rl: u32, relative: i32) -> Word {
+        Word::I(
+            Op::BranchLessUnsigned,
+            Register::g(rr),
+            Register::g(rl),
+            relative,
+        )
+    }
+
+    pub fn branch_less_equal_unsigned(rr: u32, rl: u32, relative: i32) -> Word {
+        Word::I(
+            Op::BranchLessEqualUnsigned,
+            Register::g(rr),
+            Register::g(rl),
+            relative,
+        )
+    }
+
+    pub fn branch_greater_unsigned(rr: u32, rl: u32, relative: i32) -> Word {
+        Word::I(
+            Op::BranchGreaterUnsigned,
+            Register::g(rr),
+            Register::g(rl),
+            relative,
+        )
+    }
+
+    pub fn branch_greater_equal_unsigned(rr: u32, rl: u32, relative: i32) -> Word {
+        Word::I(
+            Op::BranchGreaterEqualUnsigned,
+            Register::g(rr),
+            Register::g(rl),
+            relative,
+        )
+    }
+
     pub fn jump_immediate(absolute: i32) -> Word {
         Word::JI(Op::Jump, absolute)
     }
@@ -586,6 +1127,82 @@ impl Word {
         )
     }
 
+    /// `ro` plus `rl` times `rr`, rounded once.
+    pub fn fmultiply_add(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::FMultiplyAdd,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    /// `ro` minus `rl` times `rr`, rounded once.
+    pub fn fmultiply_subtract(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::FMultiplySubtract,
+            Register::g(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn fsqrt(ro: u32, rl: u32) -> Word {
+        Word::I(Op::FSqrt, Register::g(ro), Register::g(rl), 0)
+    }
+
+    pub fn fmin(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::FMin, Register::g(ro), Register::g(rl), Register::g(rr))
+    }
+
+    pub fn fmax(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::FMax, Register::g(ro), Register::g(rl), Register::g(rr))
+    }
+
+    pub fn fabs(ro: u32, rl: u32) -> Word {
+        Word::I(Op::FAbs, Register::g(ro), Register::g(rl), 0)
+    }
+
+    pub fn fneg(ro: u32, rl: u32) -> Word {
+        Word::I(Op::FNeg, Register::g(ro), Register::g(rl), 0)
+    }
+
+    pub fn int_to_float(ro: u32, rl: u32) -> Word {
+        Word::I(Op::IntToFloat, Register::g(ro), Register::g(rl), 0)
+    }
+
+    /// rounded with `mode`, or however `fsrm` last said to without one.
+    pub fn float_to_int(ro: u32, rl: u32, mode: Option<RoundingMode>) -> Word {
+        Word::I(
+            Op::FloatToInt,
+            Register::g(ro),
+            Register::g(rl),
+            mode.map_or(DYNAMIC_ROUNDING, RoundingMode::to_immediate),
+        )
+    }
+
+    /// the exceptions raised by everything older, read when it commits.
+    pub fn fread_flags(ro: u32) -> Word {
+        Word::I(Op::FReadFlags, Register::g(ro), Register::g(0), 0)
+    }
+
+    pub fn fclear_flags() -> Word {
+        Word::I(Op::FClearFlags, Register::g(0), Register::g(0), 0)
+    }
+
+    pub fn fread_rounding(ro: u32) -> Word {
+        Word::I(Op::FReadRounding, Register::g(ro), Register::g(0), 0)
+    }
+
+    pub fn fset_rounding(mode: RoundingMode) -> Word {
+        Word::I(
+            Op::FSetRounding,
+            Register::g(0),
+            Register::g(0),
+            mode.to_immediate(),
+        )
+    }
+
     pub fn v_load_memory(ro: u32, rl: u32, offset: i32) -> Word {
         Word::I(Op::VLoadMemory, Register::v(ro), Register::g(rl), offset)
     }
@@ -594,6 +1211,42 @@ impl Word {
         Word::I(Op::VStoreMemory, Register::v(ro), Register::g(rl), offset)
     }
 
+    pub fn v_load_strided(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VLoadStrided,
+            Register::v(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn v_store_strided(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VStoreStrided,
+            Register::v(ro),
+            Register::g(rl),
+            Register::g(rr),
+        )
+    }
+
+    pub fn v_gather(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VGather,
+            Register::v(ro),
+            Register::g(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_scatter(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VScatter,
+            Register::v(ro),
+            Register::g(rl),
+            Register::v(rr),
+        )
+    }
+
     pub fn v_left_shift(ro: u32, rl: u32, immediate: i32) -> Word {
         Word::I(Op::VLeftShift, Register::v(ro), Register::v(rl), immediate)
     }
@@ -668,12 +1321,210 @@ impl Word {
         Word::R(Op::VSum, Register::g(ro), Register::g(rl), Register::v(rr))
     }
 
+    /// every lane of `ro` set to `rl`.
+    pub fn v_broadcast(ro: u32, rl: u32) -> Word {
+        Word::I(Op::VBroadcast, Register::v(ro), Register::g(rl), 0)
+    }
+
+    pub fn v_extract(ro: u32, rl: u32, lane: i32) -> Word {
+        Word::I(Op::VExtract, Register::g(ro), Register::v(rl), lane)
+    }
+
+    /// `rl` written into one lane of `ro`, the others are kept.
+    pub fn v_insert(ro: u32, rl: u32, lane: i32) -> Word {
+        Word::I(Op::VInsert, Register::v(ro), Register::g(rl), lane)
+    }
+
+    /// lane i of `ro` is the lane of `rl` picked by lane i of `rr`, modulo the lane count.
+    pub fn v_shuffle(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VShuffle,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_min(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::VMin, Register::v(ro), Register::v(rl), Register::v(rr))
+    }
+
+    pub fn v_max(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::VMax, Register::v(ro), Register::v(rl), Register::v(rr))
+    }
+
+    pub fn v_fmin(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::VFMin, Register::v(ro), Register::v(rl), Register::v(rr))
+    }
+
+    pub fn v_fmax(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::VFMax, Register::v(ro), Register::v(rl), Register::v(rr))
+    }
+
+    pub fn v_abs(ro: u32, rl: u32) -> Word {
+        Word::I(Op::VAbs, Register::v(ro), Register::v(rl), 0)
+    }
+
+    pub fn v_fabs(ro: u32, rl: u32) -> Word {
+        Word::I(Op::VFAbs, Register::v(ro), Register::v(rl), 0)
+    }
+
+    pub fn v_compare_equal(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VCompareEqual,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_compare_less(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VCompareLess,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_compare_less_equal(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VCompareLessEqual,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fcompare_equal(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFCompareEqual,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fcompare_less(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFCompareLess,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fcompare_less_equal(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFCompareLessEqual,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_and(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::VAnd, Register::v(ro), Register::v(rl), Register::v(rr))
+    }
+
+    pub fn v_or(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(Op::VOr, Register::v(ro), Register::v(rl), Register::v(rr))
+    }
+
+    /// lanes of `rr` where the mask in $v0 is set and of `rl` where it isn't.
+    pub fn v_blend(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VBlend,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    /// only lanes where the mask in $v0 is set are added, the rest are copied from `rl`.
+    pub fn v_add_masked(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VAddMasked,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_subtract_masked(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VSubtractMasked,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_multiply_masked(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VMultiplyMasked,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fadd_masked(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFAddMasked,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fsubtract_masked(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFSubtractMasked,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fmultiply_masked(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFMultiplyMasked,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fmultiply_add(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFMultiplyAdd,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    pub fn v_fmultiply_subtract(ro: u32, rl: u32, rr: u32) -> Word {
+        Word::R(
+            Op::VFMultiplySubtract,
+            Register::v(ro),
+            Register::v(rl),
+            Register::v(rr),
+        )
+    }
+
+    /// how many lanes vectors have, so loops can step through memory by the vector width.
+    pub fn v_length(ro: u32) -> Word {
+        Word::I(Op::VLength, Register::g(ro), Register::g(0), 0)
+    }
+
     pub fn move_from_high(ro: u32) -> Word {
         Word::I(Op::MoveFromHigh, Register::g(ro), Register::High, 0)
     }
 
     pub fn move_from_low(ro: u32) -> Word {
-        Word::I(Op::MoveFromLow, Register::g(ro), Register::High, 0)
+        Word::I(Op::MoveFromLow, Register::g(ro), Register::Low, 0)
     }
 
     pub fn exit(ri: u32) -> Word {
@@ -0,0 +1,553 @@
+use bytes::BytesMut;
+
+use crate::{
+    float::{self, RoundingMode},
+    instructions::{Op, Register, Word},
+    registers::Registers,
+    vector::Vector,
+};
+
+/// Why the program stopped before running off the end of its instructions.
+enum Stop {
+    Exit(i32),
+    Error(String),
+}
+
+/// Runs acasm one instruction at a time straight from what each instruction is documented to
+/// do, with no fetcher, reservation stations, execution units or ROB. The fuzzer checks the out
+/// of order core against it, so nothing in here shares code with the pipeline.
+pub struct Interpreter {
+    instructions: Vec<Word>,
+    registers: Registers,
+    memory: BytesMut,
+    float_traps: u32,
+    pub exit_value: Option<i32>,
+    pub exit_error: Option<String>,
+    pub executed: u64,
+}
+impl Interpreter {
+    pub fn new(
+        instructions: Vec<Word>,
+        memory: BytesMut,
+        registers: Registers,
+        float_traps: u32,
+    ) -> Self {
+        Self {
+            instructions,
+            registers,
+            memory,
+            float_traps,
+            exit_value: None,
+            exit_error: None,
+            executed: 0,
+        }
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &BytesMut {
+        &self.memory
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.registers.pc() < self.instructions.len()
+    }
+
+    /// Runs until the program ends or `limit` instructions have run, false if it hasn't ended.
+    pub fn run(&mut self, limit: u64) -> bool {
+        while self.is_running() && self.executed < limit {
+            self.step();
+        }
+        !self.is_running()
+    }
+
+    pub fn step(&mut self) {
+        let pc = self.registers.pc();
+        let word = self.instructions[pc];
+
+        // `j` is followed by the fetcher and never committed, so like the core it isn't counted
+        if word.op() != Op::Jump {
+            self.executed += 1;
+        }
+
+        let next = match self.execute(word, pc) {
+            Ok(next) => next,
+            Err(Stop::Exit(value)) => {
+                self.exit_value = Some(value);
+                i32::MAX
+            }
+            Err(Stop::Error(error)) => {
+                self.exit_error = Some(error);
+                i32::MAX
+            }
+        };
+        self.registers.set(Register::ProgramCounter, next);
+    }
+
+    fn int(&self, reg: Register) -> i32 {
+        self.registers.get(reg)
+    }
+
+    fn float(&self, reg: Register) -> f32 {
+        f32::from_bits(self.registers.get(reg) as u32)
+    }
+
+    fn vector(&self, reg: Register) -> Vector {
+        self.registers.get_vector(reg)
+    }
+
+    fn lanes(&self) -> usize {
+        self.registers.vector_lanes()
+    }
+
+    /// Adds the exceptions an instruction raised to the status register, unless one of them
+    /// traps, which stops the program before it writes anything.
+    fn raise(&mut self, flags: u32, pc: usize) -> Result<(), Stop> {
+        let trapped = flags & self.float_traps;
+        if trapped != 0 {
+            return Err(Stop::Error(format!(
+                "floating point exception ({}) at pc {}",
+                float::flag_names(trapped),
+                pc
+            )));
+        }
+        self.registers.raise_float_flags(flags);
+        Ok(())
+    }
+
+    /// `bytes` bytes from `addr`, or None if any of them are outside memory.
+    fn read(&self, addr: usize, bytes: usize) -> Option<&[u8]> {
+        self.memory.get(addr..addr.checked_add(bytes)?)
+    }
+
+    fn write(&mut self, addr: usize, bytes: &[u8]) {
+        let end = addr.saturating_add(bytes.len());
+        self.memory
+            .get_mut(addr..end)
+            .unwrap_or_else(|| panic!("store to {} is outside memory", addr as i32))
+            .copy_from_slice(bytes);
+    }
+
+    /// where lane i of a strided or indexed access is.
+    fn lane_addresses(&self, op: Op, base: Register, by: Register) -> Vec<u32> {
+        let base = self.int(base);
+        match op {
+            Op::VLoadStrided | Op::VStoreStrided => (0..self.lanes() as i32)
+                .map(|lane| base.wrapping_add(self.int(by).wrapping_mul(lane)) as u32)
+                .collect(),
+            _ => self
+                .vector(by)
+                .lanes()
+                .iter()
+                .map(|offset| base.wrapping_add(*offset as i32) as u32)
+                .collect(),
+        }
+    }
+
+    /// Does `word`, returning where the pc goes next.
+    fn execute(&mut self, word: Word, pc: usize) -> Result<i32, Stop> {
+        let next = pc as i32 + 1;
+        let (op, ro, rl, rr, immediate) = match word {
+            Word::R(op, ro, rl, rr) => (op, ro, rl, rr, 0),
+            Word::I(op, ro, rl, immediate) => (op, ro, rl, Register::g(0), immediate),
+            Word::JI(op, target) => (op, Register::g(0), Register::g(0), Register::g(0), target),
+            Word::JR(op, r) => (op, Register::g(0), r, Register::g(0), 0),
+        };
+        // the second operand of a scalar instruction, a register or the immediate
+        let right = match word {
+            Word::R(..) if !rr.is_vector() => self.int(rr),
+            _ => immediate,
+        };
+        let address = self.int(rl).wrapping_add(immediate) as usize;
+        let mode = self.registers.rounding_mode();
+
+        match op {
+            Op::Jump => return Ok(immediate),
+            Op::JumpRegister => return Ok(self.int(rl)),
+            Op::JumpAndLink => {
+                self.registers.set(ro, next);
+                return Ok(immediate);
+            }
+            Op::BranchEqual
+            | Op::BranchNotEqual
+            | Op::BranchGreater
+            | Op::BranchGreaterEqual
+            | Op::BranchLess
+            | Op::BranchLessEqual
+            | Op::BranchGreaterUnsigned
+            | Op::BranchGreaterEqualUnsigned
+            | Op::BranchLessUnsigned
+            | Op::BranchLessEqualUnsigned => {
+                let (a, b) = (self.int(ro), self.int(rl));
+                let taken = match op {
+                    Op::BranchEqual => a == b,
+                    Op::BranchNotEqual => a != b,
+                    Op::BranchGreater => a > b,
+                    Op::BranchGreaterEqual => a >= b,
+                    Op::BranchLess => a < b,
+                    Op::BranchLessEqual => a <= b,
+                    Op::BranchGreaterUnsigned => a as u32 > b as u32,
+                    Op::BranchGreaterEqualUnsigned => a as u32 >= b as u32,
+                    Op::BranchLessUnsigned => (a as u32) < b as u32,
+                    _ => a as u32 <= b as u32,
+                };
+                return Ok(if taken { pc as i32 + immediate } else { next });
+            }
+            Op::Exit => return Err(Stop::Exit(self.int(rl))),
+
+            Op::LoadImmediate | Op::FLoadImmediate => self.registers.set(ro, immediate),
+            Op::LoadMemory => {
+                let value = self.read(address, 4).map_or(0, |bytes| {
+                    i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                });
+                self.registers.set(ro, value);
+            }
+            Op::LoadHalfWord => {
+                let value = self
+                    .read(address, 2)
+                    .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as i32);
+                self.registers.set(ro, value);
+            }
+            Op::LoadChar => {
+                let value = self.read(address, 1).map_or(0, |bytes| bytes[0] as i32);
+                self.registers.set(ro, value);
+            }
+            Op::StoreMemory => self.write(address, &self.int(ro).to_be_bytes()),
+            Op::StoreChar => self.write(address, &[self.int(ro) as u8]),
+            Op::VLoadMemory => {
+                let lanes = self.lanes();
+                let value = self
+                    .read(address, lanes * 4)
+                    .map_or(Vector::zero(lanes), |bytes| Vector::read(bytes, lanes));
+                self.registers.set_vector(ro, value);
+            }
+            Op::VStoreMemory => {
+                let mut bytes = vec![0; self.lanes() * 4];
+                self.vector(ro).write(&mut bytes);
+                self.write(address, &bytes);
+            }
+            Op::VLoadStrided | Op::VGather => {
+                let mut value = Vector::zero(self.lanes());
+                for (lane, addr) in value
+                    .lanes_mut()
+                    .iter_mut()
+                    .zip(self.lane_addresses(op, rl, rr))
+                {
+                    if let Some(bytes) = self.read(addr as usize, 4) {
+                        *lane = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    }
+                }
+                self.registers.set_vector(ro, value);
+            }
+            Op::VStoreStrided | Op::VScatter => {
+                let addresses = self.lane_addresses(op, rl, rr);
+                if let Some(addr) = addresses
+                    .iter()
+                    .find(|addr| self.read(**addr as usize, 4).is_none())
+                {
+                    return Err(Stop::Error(format!(
+                        "vector store to {} is outside memory",
+                        *addr as i32
+                    )));
+                }
+                // later lanes win when two share an address
+                for (addr, lane) in addresses.iter().zip(self.vector(ro).lanes()) {
+                    self.write(*addr as usize, &lane.to_be_bytes());
+                }
+            }
+            Op::ReserveMemory => {
+                self.registers.set(ro, self.memory.len() as i32);
+                let bytes = self.memory.len() + (self.int(rl) + immediate) as usize;
+                self.memory.resize(bytes, 0);
+            }
+            Op::Save => panic!("the interpreter doesn't save images"),
+
+            Op::Divide | Op::DivideUnsigned | Op::Remainder | Op::RemainderUnsigned
+                if right == 0 =>
+            {
+                return Err(Stop::Error(String::from("tried to divide by 0")));
+            }
+            Op::MultiplyNoOverflow => {
+                let product = self.int(rl) as i64 * right as i64;
+                self.registers.set(Register::High, (product >> 32) as i32);
+                self.registers.set(Register::Low, product as i32);
+            }
+            Op::MultiplyUnsigned => {
+                let product = self.int(rl) as u32 as u64 * right as u32 as u64;
+                self.registers.set(Register::High, (product >> 32) as i32);
+                self.registers.set(Register::Low, product as i32);
+            }
+            Op::Divide => {
+                let left = self.int(rl);
+                self.registers.set(Register::High, left.wrapping_div(right));
+                self.registers.set(Register::Low, left.wrapping_rem(right));
+            }
+            Op::DivideUnsigned => {
+                let (left, right) = (self.int(rl) as u32, right as u32);
+                self.registers.set(Register::High, (left / right) as i32);
+                self.registers.set(Register::Low, (left % right) as i32);
+            }
+            Op::Add
+            | Op::AddImmediate
+            | Op::Subtract
+            | Op::SubtractImmediate
+            | Op::Multiply
+            | Op::Remainder
+            | Op::RemainderUnsigned
+            | Op::Compare
+            | Op::CompareUnsigned
+            | Op::SetLessThan
+            | Op::SetLessThanUnsigned
+            | Op::LeftShift
+            | Op::LeftShiftVariable
+            | Op::RightShift
+            | Op::RightShiftVariable
+            | Op::RightShiftLogical
+            | Op::RightShiftLogicalVariable
+            | Op::BitAnd
+            | Op::BitAndImmediate
+            | Op::BitOr
+            | Op::BitOrImmediate
+            | Op::BitXor
+            | Op::BitXorImmediate
+            | Op::BitNor
+            | Op::BitNot
+            | Op::Neg
+            | Op::MoveFromHigh
+            | Op::MoveFromLow
+            | Op::VLength => {
+                let value = integer(op, self.int(rl), right, self.lanes());
+                self.registers.set(ro, value);
+            }
+
+            Op::FReadFlags => self.registers.set(ro, self.registers.float_flags() as i32),
+            Op::FClearFlags => self.registers.clear_float_flags(),
+            Op::FReadRounding => self.registers.set(ro, mode.to_immediate()),
+            Op::FSetRounding => self.registers.set_rounding_mode(
+                RoundingMode::from_immediate(immediate).expect("fsrm needs a rounding mode"),
+            ),
+            Op::FloatToInt => {
+                let mode = match immediate {
+                    float::DYNAMIC_ROUNDING => mode,
+                    immediate => {
+                        RoundingMode::from_immediate(immediate).expect("ftoi needs a rounding mode")
+                    }
+                };
+                let (value, flags) = float::to_int(self.float(rl), mode);
+                self.raise(flags, pc)?;
+                self.registers.set(ro, value);
+            }
+            Op::FAdd
+            | Op::FAddImmediate
+            | Op::FSubtract
+            | Op::FSubtractImmediate
+            | Op::FMultiply
+            | Op::FDivide
+            | Op::FCompare
+            | Op::FMultiplyAdd
+            | Op::FMultiplySubtract
+            | Op::FSqrt
+            | Op::FMin
+            | Op::FMax
+            | Op::FAbs
+            | Op::FNeg
+            | Op::IntToFloat => {
+                let (left, right) = (self.float(rl), f32::from_bits(right as u32));
+                let (value, flags) = match op {
+                    Op::FAdd | Op::FAddImmediate => float::add(left, right, mode),
+                    Op::FSubtract | Op::FSubtractImmediate => float::add(left, -right, mode),
+                    Op::FMultiply => float::multiply(left, right, mode),
+                    Op::FDivide => float::divide(left, right, mode),
+                    Op::FCompare if left < right => (-1.0, 0),
+                    Op::FCompare if left > right => (1.0, 0),
+                    Op::FCompare if left == right => (0.0, 0),
+                    Op::FCompare => (f32::NAN, float::INVALID),
+                    Op::FMultiplyAdd => float::multiply_add(left, right, self.float(ro), mode),
+                    Op::FMultiplySubtract => {
+                        float::multiply_add(-left, right, self.float(ro), mode)
+                    }
+                    Op::FSqrt => float::sqrt(left, mode),
+                    Op::FMin => (float::min(left, right), 0),
+                    Op::FMax => (float::max(left, right), 0),
+                    Op::FAbs => (left.abs(), 0),
+                    Op::FNeg => (-left, 0),
+                    _ => float::from_int(self.int(rl), mode),
+                };
+                self.raise(flags, pc)?;
+                self.registers.set(ro, value.to_bits() as i32);
+            }
+
+            Op::VSum => {
+                let sum = self
+                    .vector(rr)
+                    .lanes()
+                    .iter()
+                    .fold(self.int(rl), |sum, lane| sum.wrapping_add(*lane as i32));
+                self.registers.set(ro, sum);
+            }
+            Op::VBroadcast => {
+                let value = Vector::splat(self.int(rl) as u32, self.lanes());
+                self.registers.set_vector(ro, value);
+            }
+            Op::VExtract | Op::VInsert => {
+                if immediate < 0 || immediate as usize >= self.lanes() {
+                    return Err(Stop::Error(format!(
+                        "lane {} is out of range for {} lane vectors",
+                        immediate,
+                        self.lanes()
+                    )));
+                }
+                let lane = immediate as usize;
+                if op == Op::VExtract {
+                    self.registers.set(ro, self.vector(rl).lanes()[lane] as i32);
+                } else {
+                    let mut value = self.vector(ro);
+                    value.lanes_mut()[lane] = self.int(rl) as u32;
+                    self.registers.set_vector(ro, value);
+                }
+            }
+            Op::VShuffle => {
+                let (left, indices) = (self.vector(rl), self.vector(rr));
+                let mut value = indices;
+                for (lane, index) in value.lanes_mut().iter_mut().zip(indices.lanes()) {
+                    *lane = left.lanes()[*index as usize % left.lanes().len()];
+                }
+                self.registers.set_vector(ro, value);
+            }
+            Op::VLeftShift | Op::VRightShift | Op::VAbs | Op::VFAbs => {
+                let mut value = self.vector(rl);
+                for lane in value.lanes_mut() {
+                    *lane = match op {
+                        Op::VLeftShift => (*lane as i32).wrapping_shl(immediate as u32) as u32,
+                        Op::VRightShift => (*lane as i32).wrapping_shr(immediate as u32) as u32,
+                        Op::VAbs => (*lane as i32).wrapping_abs() as u32,
+                        _ => f32::from_bits(*lane).abs().to_bits(),
+                    };
+                }
+                self.registers.set_vector(ro, value);
+            }
+            Op::VBlend => {
+                let (left, right, mask) = (
+                    self.vector(rl),
+                    self.vector(rr),
+                    self.vector(Register::v(0)),
+                );
+                let mut value = left;
+                for (i, lane) in value.lanes_mut().iter_mut().enumerate() {
+                    if mask.lanes()[i] != 0 {
+                        *lane = right.lanes()[i];
+                    }
+                }
+                self.registers.set_vector(ro, value);
+            }
+            _ => {
+                let (left, right) = (self.vector(rl), self.vector(rr));
+                let accumulator = self.vector(ro);
+                let mask = self.vector(Register::v(0));
+                if op == Op::VDivide && right.lanes().contains(&0) {
+                    return Err(Stop::Error(String::from("tried to divide by 0")));
+                }
+
+                let mut value = left;
+                let mut flags = 0;
+                for (i, lane) in value.lanes_mut().iter_mut().enumerate() {
+                    if op.is_masked() && mask.lanes()[i] == 0 {
+                        continue;
+                    }
+                    let (out, lane_flags) = match op {
+                        Op::VFMultiplyAdd | Op::VFMultiplySubtract => {
+                            let left = f32::from_bits(left.lanes()[i]);
+                            let left = if op == Op::VFMultiplyAdd { left } else { -left };
+                            let (out, flags) = float::multiply_add(
+                                left,
+                                f32::from_bits(right.lanes()[i]),
+                                f32::from_bits(accumulator.lanes()[i]),
+                                mode,
+                            );
+                            (out.to_bits(), flags)
+                        }
+                        _ => lane_wise(op, left.lanes()[i], right.lanes()[i], mode),
+                    };
+                    *lane = out;
+                    flags |= lane_flags;
+                }
+                self.raise(flags, pc)?;
+                self.registers.set_vector(ro, value);
+            }
+        }
+        Ok(next)
+    }
+}
+
+/// A scalar integer instruction on `left` and its register or immediate `right`.
+fn integer(op: Op, left: i32, right: i32, lanes: usize) -> i32 {
+    let (unsigned_left, unsigned_right) = (left as u32, right as u32);
+    match op {
+        Op::Add | Op::AddImmediate => left.wrapping_add(right),
+        Op::Subtract | Op::SubtractImmediate => left.wrapping_sub(right),
+        Op::Multiply => left.wrapping_mul(right),
+        Op::Remainder => left.wrapping_rem(right),
+        Op::RemainderUnsigned => (unsigned_left % unsigned_right) as i32,
+        Op::Compare => left.cmp(&right) as i32,
+        Op::CompareUnsigned => unsigned_left.cmp(&unsigned_right) as i32,
+        Op::SetLessThan => (left < right) as i32,
+        Op::SetLessThanUnsigned => (unsigned_left < unsigned_right) as i32,
+        // shift amounts are taken modulo 32
+        Op::LeftShift | Op::LeftShiftVariable => left << (right & 31),
+        Op::RightShift | Op::RightShiftVariable => left >> (right & 31),
+        Op::RightShiftLogical | Op::RightShiftLogicalVariable => {
+            (unsigned_left >> (right & 31)) as i32
+        }
+        Op::BitAnd | Op::BitAndImmediate => left & right,
+        Op::BitOr | Op::BitOrImmediate => left | right,
+        Op::BitXor | Op::BitXorImmediate => left ^ right,
+        Op::BitNor => !(left | right),
+        Op::BitNot => !left,
+        Op::Neg => left.wrapping_neg(),
+        Op::MoveFromHigh | Op::MoveFromLow => left,
+        Op::VLength => lanes as i32,
+        _ => unreachable!("{:?} isn't a scalar integer instruction", op),
+    }
+}
+
+/// One lane of an element-wise vector instruction and the float exceptions it raised.
+fn lane_wise(op: Op, left: u32, right: u32, mode: RoundingMode) -> (u32, u32) {
+    let (a, b) = (left as i32, right as i32);
+    let (x, y) = (f32::from_bits(left), f32::from_bits(right));
+    let all_or_none = |true_lane: bool| (if true_lane { u32::MAX } else { 0 }, 0);
+    let ordered = |true_lane: bool| {
+        let flags = if x.is_nan() || y.is_nan() {
+            float::INVALID
+        } else {
+            0
+        };
+        (if true_lane { u32::MAX } else { 0 }, flags)
+    };
+    let bits = |(out, flags): (f32, u32)| (out.to_bits(), flags);
+
+    match op {
+        Op::VAdd | Op::VAddMasked => (a.wrapping_add(b) as u32, 0),
+        Op::VSubtract | Op::VSubtractMasked => (a.wrapping_sub(b) as u32, 0),
+        Op::VMultiply | Op::VMultiplyMasked => (a.wrapping_mul(b) as u32, 0),
+        Op::VDivide => (a.wrapping_div(b) as u32, 0),
+        Op::VMin => (a.min(b) as u32, 0),
+        Op::VMax => (a.max(b) as u32, 0),
+        Op::VAnd => (left & right, 0),
+        Op::VOr => (left | right, 0),
+        Op::VCompareEqual => all_or_none(a == b),
+        Op::VCompareLess => all_or_none(a < b),
+        Op::VCompareLessEqual => all_or_none(a <= b),
+        Op::VFAdd | Op::VFAddMasked => bits(float::add(x, y, mode)),
+        Op::VFSubtract | Op::VFSubtractMasked => bits(float::add(x, -y, mode)),
+        Op::VFMultiply | Op::VFMultiplyMasked => bits(float::multiply(x, y, mode)),
+        Op::VFDivide => bits(float::divide(x, y, mode)),
+        Op::VFMin => (float::min(x, y).to_bits(), 0),
+        Op::VFMax => (float::max(x, y).to_bits(), 0),
+        Op::VFCompareEqual => all_or_none(x == y),
+        Op::VFCompareLess => ordered(x < y),
+        Op::VFCompareLessEqual => ordered(x <= y),
+        _ => unreachable!("{:?} isn't an element-wise vector instruction", op),
+    }
+}
@@ -1,80 +1,547 @@
+#![allow(
+    clippy::needless_return,
+    clippy::match_like_matches_macro,
+    clippy::upper_case_acronyms,
+    clippy::too_many_arguments,
+    clippy::wrong_self_convention
+)]
+
 mod assembler;
+mod bench;
 mod branch_prediction;
+mod branch_profile;
+mod branch_trace;
+mod checkpoint;
 mod commiter;
 mod cpu;
+mod debugger;
 mod dispatcher;
 mod execution_units;
 mod fetcher;
+mod float;
+mod fuzz;
+mod instruction_profile;
 mod instructions;
+mod interpreter;
+mod occupancy;
+mod pipeline_trace;
 // mod memory;
 mod register_alias_table;
 mod registers;
 mod reorder_buffer;
 mod reservation_station;
+mod sampling;
+#[cfg(test)]
+mod script_tests;
+mod smarts;
+mod stalls;
 mod stats;
+mod stats_report;
+mod vcd;
+mod vector;
+mod visualiser;
 
 use assembler::assemble_file;
 use branch_prediction::BranchPredictionMode;
-use cpu::CPU;
+use branch_trace::BranchTrace;
+use checkpoint::{Checkpoint, CheckpointAt};
+use cpu::{CpuConfig, CPU};
+use debugger::Debugger;
+use instructions::Word;
+use pipeline_trace::{PipelineTrace, PipelineTraceFormat};
+use sampling::{SampleUnit, Sampler};
+use smarts::Smarts;
+use stats_report::StatsFormat;
+use vcd::VcdWriter;
+use visualiser::Visualiser;
+
+use std::ffi::OsString;
 
-use clap::Parser;
+use bytes::BytesMut;
+use clap::{
+    error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand,
+};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+}
+impl Cli {
+    /// Parses `args` like `parse_from`, noting which flags were given rather than left at their
+    /// defaults.
+    fn parse_noting_given<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command().get_matches_from(args);
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+        let matches = matches
+            .subcommand()
+            .map_or(&matches, |(_, matches)| matches);
+        if let Some(args) = cli.args_mut() {
+            args.given = matches
+                .ids()
+                .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+                .map(|id| id.to_string())
+                .collect();
+        }
+        cli
+    }
+
+    /// the simulator's arguments, whichever command they were given to.
+    fn args_mut(&mut self) -> Option<&mut Args> {
+        match &mut self.command {
+            Some(Command::Debug { args }) | Some(Command::Tui { args }) => Some(args),
+            Some(_) => None,
+            None => self.args.as_mut(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Replay a branch trace recorded with --branch-trace through branch predictors
+    Replay {
+        trace_filename: String,
+
+        /// predictor to evaluate, can be given more than once to compare them
+        #[arg(short, long, default_value = "bimodal")]
+        branch_predictor_mode: Vec<BranchPredictionMode>,
+    },
+    /// Step through a program cycle by cycle with breakpoints and a view of the pipeline
+    Debug {
+        #[command(flatten)]
+        args: Box<Args>,
+    },
+    /// Full screen view of the pipeline you can step forwards and backwards through
+    Tui {
+        #[command(flatten)]
+        args: Box<Args>,
+    },
+    /// Run random programs on random configurations and compare the results with running
+    /// them on a reference interpreter one instruction at a time
+    Fuzz {
+        /// seed of the first program, each run after it uses the next one
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(long, default_value_t = 1000)]
+        runs: u64,
+
+        /// statements in each program, counting those inside loops and branches
+        #[arg(long, default_value_t = 40)]
+        length: usize,
+
+        /// where minimised failing programs are written
+        #[arg(long, default_value = "fuzz_failures")]
+        out: String,
+    },
+    /// Time how many cycles per second the simulator gets through each program with the
+    /// default configuration
+    Bench {
+        #[arg(required = true)]
+        acasm_filenames: Vec<String>,
+
+        /// runs of each program, the quickest is reported
+        #[arg(long, default_value_t = 3)]
+        repeat: usize,
+    },
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
     pub acasm_filename: String,
 
     #[arg(short, long, default_value_t = false)]
     pub print_memory: bool,
 
+    /// how to print the stats at the end of the run
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    pub stats_format: StatsFormat,
+
     #[arg(short, long, default_value_t = 32)]
     pub rob_size: usize,
 
-    #[arg(short, long, default_value_t = 8)]
+    #[arg(long, default_value_t = 8)]
     pub rob_max_retire: usize,
 
     #[arg(short, long, default_value_t = 8)]
     pub fetch_amount: usize,
 
-    #[arg(short, long, default_value_t = 8)]
+    #[arg(long, default_value_t = 8)]
     pub fetch_buffer_capacity: usize,
 
     #[arg(short, long, default_value_t = 8)]
     pub dispatch_amount: usize,
 
-    #[arg(short, long, default_value_t = 6)]
+    #[arg(long, default_value_t = 6)]
     pub rs_alu_size: usize,
-    #[arg(short, long, default_value_t = 4)]
+    #[arg(long, default_value_t = 4)]
     pub rs_fpu_size: usize,
-    #[arg(short, long, default_value_t = 2)]
+    #[arg(long, default_value_t = 2)]
     pub rs_vpu_size: usize,
-    #[arg(short, long, default_value_t = 2)]
+    #[arg(long, default_value_t = 2)]
     pub rs_lsu_size: usize,
-    #[arg(short, long, default_value_t = 2)]
+    #[arg(long, default_value_t = 2)]
     pub rs_branch_size: usize,
 
     #[arg(short, long, default_value_t = 3)]
     pub eu_alu_num: usize,
-    #[arg(short, long, default_value_t = 2)]
+    #[arg(long, default_value_t = 2)]
     pub eu_fpu_num: usize,
-    #[arg(short, long, default_value_t = 1)]
+    #[arg(long, default_value_t = 1)]
     pub eu_vpu_num: usize,
-    #[arg(short, long, default_value_t = 1)]
+    #[arg(long, default_value_t = 1)]
     pub eu_lsu_num: usize,
-    #[arg(short, long, default_value_t = 1)]
+    #[arg(long, default_value_t = 1)]
     pub eu_branch_num: usize,
 
-    #[arg(short, long)]
-    pub branch_predictor_mode: Option<BranchPredictionMode>,
+    /// predictor spec, e.g. `bimodal:bits=2,index=10`, `local:hist=8,bits=3` or `gshare:hist=12`.
+    /// options are hist, bits, index, tag and init.
+    #[arg(short, long, default_value = "bimodal")]
+    pub branch_predictor_mode: BranchPredictionMode,
+
+    /// how many vector registers there are, $v0 upwards
+    #[arg(long, default_value_t = 8, value_parser = vector::parse_registers)]
+    pub vector_registers: usize,
+    /// bits in each vector register, 128, 256 or 512. vector instructions work on this many
+    /// 32 bit lanes at once
+    #[arg(long, default_value_t = 128, value_parser = vector::parse_width)]
+    pub vector_width: usize,
+
+    /// stop with an error when a float operation raises any of these comma separated
+    /// exceptions: invalid, divide-by-zero, overflow, underflow, inexact
+    #[arg(long, default_value = "", value_parser = float::parse_flags)]
+    pub fp_traps: u32,
+
+    /// print how every branch was predicted, worst first
+    #[arg(long, default_value_t = false)]
+    pub branch_profile: bool,
+    /// write the branch profile to this csv file
+    #[arg(long)]
+    pub branch_profile_csv: Option<String>,
+    /// record every committed branch to this file for the replay command
+    #[arg(long)]
+    pub branch_trace: Option<String>,
+
+    /// print how many times every instruction and basic block committed, was squashed and
+    /// how long it waited to issue and commit
+    #[arg(long, default_value_t = false)]
+    pub profile: bool,
+    /// write the instruction profile to this csv file
+    #[arg(long)]
+    pub profile_csv: Option<String>,
+
+    /// print a CPI stack and what each stage stalled on
+    #[arg(long, default_value_t = false)]
+    pub cpi_stack: bool,
+
+    /// print how full the buffers got and how busy the execution units were
+    #[arg(long, default_value_t = false)]
+    pub occupancy: bool,
+    /// write the occupancy histograms to this csv file
+    #[arg(long)]
+    pub occupancy_csv: Option<String>,
+
+    /// write how the program performed over time to this csv file, one row per interval
+    #[arg(long)]
+    pub sample_csv: Option<String>,
+    /// how long each sampling interval is, in --sample-unit
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub sample_every: u64,
+    #[arg(long, value_enum, default_value_t = SampleUnit::Cycles)]
+    pub sample_unit: SampleUnit,
+
+    /// write the lifecycle of every instruction to this file
+    #[arg(long)]
+    pub pipeline_trace: Option<String>,
+    #[arg(long, value_enum, default_value_t = PipelineTraceFormat::Konata)]
+    pub pipeline_trace_format: PipelineTraceFormat,
+
+    /// write the state of the pipeline every cycle to this vcd file, for GTKWave
+    #[arg(long)]
+    pub vcd: Option<String>,
+
+    /// save the whole simulator to this file at --checkpoint-cycle or --checkpoint-instruction
+    #[arg(long, requires = "checkpoint_at")]
+    pub checkpoint: Option<String>,
+    /// take the checkpoint at the start of this cycle
+    #[arg(long, group = "checkpoint_at", requires = "checkpoint")]
+    pub checkpoint_cycle: Option<u64>,
+    /// take the checkpoint once this many instructions have committed
+    #[arg(long, group = "checkpoint_at", requires = "checkpoint")]
+    pub checkpoint_instruction: Option<u64>,
+    /// carry on from a checkpoint of the same program instead of starting from scratch
+    #[arg(long)]
+    pub restore: Option<String>,
+
+    /// run this many instructions functionally, without timing them, before simulating in detail
+    #[arg(long)]
+    pub fast_forward: Option<u64>,
+    /// stop after simulating this many instructions in detail, or how many instructions each
+    /// --smarts-period sample measures (default 1000)
+    #[arg(long)]
+    pub detailed: Option<u64>,
+    /// simulate a sample in detail every this many instructions and fast-forward the rest,
+    /// estimating the CPI of the whole program
+    #[arg(long)]
+    pub smarts_period: Option<u64>,
+    /// instructions simulated in detail before each sample to refill the pipeline
+    #[arg(long, default_value_t = 2000)]
+    pub smarts_warmup: u64,
+
+    /// ids of the arguments given on the command line, filled in by `Cli::parse_noting_given`
+    #[arg(skip)]
+    pub given: Vec<String>,
 }
 
 fn main() {
-    let args = Args::parse();
-    let (memory, instructions) = assemble_file(&args.acasm_filename);
+    let cli = Cli::parse_noting_given(std::env::args_os());
+
+    match cli.command {
+        Some(Command::Replay {
+            trace_filename,
+            branch_predictor_mode,
+        }) => replay(&trace_filename, branch_predictor_mode),
+        Some(Command::Debug { args }) => debug(*args),
+        Some(Command::Tui { args }) => tui(*args),
+        Some(Command::Fuzz {
+            seed,
+            runs,
+            length,
+            out,
+        }) => {
+            if fuzz::fuzz(seed, runs, length, &out) > 0 {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Bench {
+            acasm_filenames,
+            repeat,
+        }) => {
+            print!(
+                "{}",
+                bench::report(&bench::bench(
+                    &acasm_filenames,
+                    CpuConfig::default(),
+                    repeat
+                ))
+            );
+        }
+        None => run(cli.args.expect("clap requires the run arguments")),
+    }
+}
+
+/// Stops with `message` the way clap reports a bad argument.
+fn invalid_args(message: String) -> ! {
+    Cli::command()
+        .error(ErrorKind::ValueValidation, message)
+        .exit()
+}
+
+fn replay(trace_filename: &str, modes: Vec<BranchPredictionMode>) {
+    let trace = BranchTrace::read(trace_filename).unwrap_or_else(|e| invalid_args(e));
+
+    println!(
+        "Replaying {} branches over {} instructions",
+        trace.records.len(),
+        trace.instructions
+    );
+    for mode in modes {
+        println!("{}", trace.replay(mode));
+    }
+}
+
+/// A fresh cpu with the program's memory, or one carrying on from --restore, which will
+/// write a checkpoint if asked.
+fn build_cpu(args: Args, memory: BytesMut, instructions: &[Word]) -> CPU {
+    let checkpoint_at = match (args.checkpoint_cycle, args.checkpoint_instruction) {
+        (Some(cycle), _) => Some(CheckpointAt::Cycle(cycle)),
+        (None, Some(instructions)) => Some(CheckpointAt::Instruction(instructions)),
+        (None, None) => None,
+    };
+    let checkpoint_path = args.checkpoint.clone();
+    let restore = args.restore.clone();
+    let given = args.given.clone();
+
+    let mut simulator = match restore {
+        Some(path) => {
+            let checkpoint = Checkpoint::read(&path).unwrap_or_else(|e| invalid_args(e));
+            if checkpoint.instructions != instructions {
+                invalid_args(format!("{} is a checkpoint of a different program", path));
+            }
+            let config = CpuConfig::from(args);
+            config
+                .check_restore(&checkpoint.config, &given)
+                .unwrap_or_else(|e| invalid_args(format!("{}: {}", path, e)));
+            CPU::restore(checkpoint, config)
+        }
+        None => {
+            let mut simulator = CPU::new(args.into());
+            simulator.set_memory(memory);
+            simulator
+        }
+    };
+
+    if let Some(path) = checkpoint_path {
+        let at =
+            checkpoint_at.expect("clap requires --checkpoint-cycle or --checkpoint-instruction");
+        simulator.set_checkpoint(at, path);
+    }
+    simulator
+}
+
+fn debug(args: Args) {
+    let (memory, instructions, source) = assemble_file(&args.acasm_filename);
+
+    let simulator = build_cpu(args, memory, &instructions);
+    Debugger::new(simulator, instructions, source)
+        .unwrap_or_else(|e| invalid_args(e))
+        .repl();
+}
+
+fn tui(args: Args) {
+    if args.checkpoint.is_some() {
+        invalid_args(String::from(
+            "the tui can't write checkpoints, use the debug command's checkpoint instead",
+        ));
+    }
+    let (memory, instructions, source) = assemble_file(&args.acasm_filename);
+    let fast_forward = args.fast_forward;
+
+    let mut simulator = build_cpu(args, memory, &instructions);
+    simulator
+        .load_program(instructions)
+        .unwrap_or_else(|e| invalid_args(e));
+    if let Some(instructions) = fast_forward {
+        simulator.fast_forward(instructions);
+    }
+
+    Visualiser::new(simulator, source)
+        .show()
+        .expect("couldn't draw to the terminal");
+}
+
+fn run(args: Args) {
+    let (memory, instructions, source) = assemble_file(&args.acasm_filename);
+    let stats_format = args.stats_format;
+    let branch_profile = args.branch_profile;
+    let cpi_stack = args.cpi_stack;
+    let profile = args.profile;
+    let profile_csv = args.profile_csv.clone();
+    let occupancy = args.occupancy;
+    let occupancy_csv = args.occupancy_csv.clone();
+    let branch_profile_csv = args.branch_profile_csv.clone();
+    let branch_trace = args.branch_trace.clone();
+    let pipeline_trace = args.pipeline_trace.clone().map(|path| {
+        let labels = source.iter().map(|s| s.text.clone()).collect();
+        PipelineTrace::create(&path, args.pipeline_trace_format, labels)
+            .expect("couldn't create pipeline trace")
+    });
+    let fast_forward = args.fast_forward;
+    let detailed = args.detailed;
+    let smarts = args.smarts_period.map(|period| {
+        Smarts::new(period, args.smarts_warmup, detailed.unwrap_or(1000))
+            .unwrap_or_else(|e| invalid_args(e))
+    });
+    let vcd = args.vcd.clone();
+    let sampler = args.sample_csv.as_ref().map(|path| {
+        Sampler::create(path, args.sample_every, args.sample_unit)
+            .expect("couldn't create sample csv")
+    });
+
+    let mut simulator = build_cpu(args, memory, &instructions);
+    if let Some(sampler) = sampler {
+        simulator.set_sampler(sampler);
+    }
+    if let Some(trace) = pipeline_trace {
+        simulator.set_pipeline_trace(trace);
+    }
+    if let Some(path) = vcd {
+        let vcd = VcdWriter::create(&path, &simulator).expect("couldn't create vcd");
+        simulator.set_vcd(vcd);
+    }
+    simulator
+        .load_program(instructions.clone())
+        .unwrap_or_else(|e| invalid_args(e));
+    if let Some(instructions) = fast_forward {
+        simulator.fast_forward(instructions);
+    }
+    let estimate = match smarts {
+        Some(smarts) => Some(smarts.run(&mut simulator)),
+        None => {
+            simulator.run_detailed(detailed.unwrap_or(u64::MAX));
+            None
+        }
+    };
+    let stats = simulator.finish();
 
-    let mut simulator = CPU::new(args.into());
-    simulator.set_memory(memory);
-    let stats = simulator.run_program(instructions);
+    match stats_format {
+        StatsFormat::Text => {
+            simulator.print_end_state();
+            println!("{}", stats);
+        }
+        StatsFormat::Json => println!(
+            "{}",
+            stats_report::to_json(&stats_report::report(&simulator, &stats))
+        ),
+        StatsFormat::Csv => print!(
+            "{}",
+            stats_report::to_csv(&stats_report::report(&simulator, &stats))
+        ),
+    }
 
-    println!("{}", stats);
+    // stdout belongs to the json or csv, so the text reports go to stderr alongside them
+    let print_report = |report: String| match stats_format {
+        StatsFormat::Text => println!("{}", report),
+        StatsFormat::Json | StatsFormat::Csv => eprintln!("{}", report),
+    };
+    if let Some(estimate) = estimate {
+        print_report(estimate.to_string());
+    }
+    if cpi_stack {
+        print_report(
+            stats
+                .stalls
+                .report(stats.cycles, stats.instructions_commited),
+        );
+    }
+    if occupancy {
+        print_report(stats.occupancy.report());
+    }
+    if let Some(path) = occupancy_csv {
+        stats
+            .occupancy
+            .write_csv(&path)
+            .expect("couldn't write occupancy csv");
+    }
+    if let Some(instruction_profile) = &stats.instruction_profile {
+        if profile {
+            print_report(instruction_profile.report(&instructions, &source));
+        }
+        if let Some(path) = profile_csv {
+            instruction_profile
+                .write_csv(&path, &instructions, &source)
+                .expect("couldn't write instruction profile csv");
+        }
+    }
+    if branch_profile {
+        print_report(stats.branch_profile.report(&source));
+    }
+    if let Some(path) = branch_profile_csv {
+        stats
+            .branch_profile
+            .write_csv(&path, &source)
+            .expect("couldn't write branch profile csv");
+    }
+    if let (Some(path), Some(trace)) = (branch_trace, &stats.branch_trace) {
+        trace.write(&path).expect("couldn't write branch trace");
+    }
 }
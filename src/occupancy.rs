@@ -0,0 +1,158 @@
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+/// How many cycles a structure spent holding each number of entries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Histogram {
+    pub name: String,
+    pub capacity: usize,
+    pub cycles: Vec<u64>, // cycles[n] is how many cycles it held n entries
+}
+impl Histogram {
+    pub fn new(name: String, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity,
+            cycles: vec![0; capacity + 1],
+        }
+    }
+
+    pub fn record(&mut self, occupancy: usize) {
+        if occupancy >= self.cycles.len() {
+            self.cycles.resize(occupancy + 1, 0);
+        }
+        self.cycles[occupancy] += 1;
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.cycles.iter().sum()
+    }
+
+    pub fn mean(&self) -> f64 {
+        let total: u64 = self
+            .cycles
+            .iter()
+            .enumerate()
+            .map(|(n, cycles)| n as u64 * cycles)
+            .sum();
+        total as f64 / self.samples() as f64
+    }
+
+    pub fn max(&self) -> usize {
+        self.cycles.iter().rposition(|c| *c != 0).unwrap_or(0)
+    }
+
+    /// percentage of cycles spent completely full.
+    pub fn full_rate(&self) -> f64 {
+        let full: u64 = self.cycles[self.capacity.min(self.cycles.len() - 1)..]
+            .iter()
+            .sum();
+        100.0 * full as f64 / self.samples() as f64
+    }
+}
+
+/// Per-cycle occupancy of the fetch buffer, rob and every reservation station, and how many
+/// execution units of each type were busy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Occupancy {
+    pub histograms: Vec<Histogram>,
+}
+impl Occupancy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds a structure to track, samples are given to `record` in the order they were added.
+    pub fn track(&mut self, name: String, capacity: usize) {
+        self.histograms.push(Histogram::new(name, capacity));
+    }
+
+    pub fn record(&mut self, occupancies: impl IntoIterator<Item = usize>) {
+        for (histogram, occupancy) in self.histograms.iter_mut().zip(occupancies) {
+            histogram.record(occupancy);
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut out = String::from("Occupancy:\n");
+        out += &format!(
+            "  {:<16} {:>8} {:>8} {:>6} {:>7}\n",
+            "Structure", "Capacity", "Mean", "Max", "Full%"
+        );
+        for histogram in &self.histograms {
+            out += &format!(
+                "  {:<16} {:>8} {:>8.2} {:>6} {:>7.2}\n",
+                histogram.name,
+                histogram.capacity,
+                histogram.mean(),
+                histogram.max(),
+                histogram.full_rate()
+            );
+        }
+
+        for histogram in &self.histograms {
+            out += &format!("{} histogram:\n", histogram.name);
+            let most = *histogram.cycles.iter().max().unwrap_or(&0);
+            for (n, cycles) in histogram.cycles.iter().enumerate() {
+                let bar = (40 * cycles).checked_div(most).unwrap_or(0);
+                out += &format!("  {:>4} {:>8} {}\n", n, cycles, "#".repeat(bar as usize));
+            }
+        }
+
+        out
+    }
+
+    /// one `structure,capacity,occupancy,cycles` row for every bucket of every histogram.
+    pub fn write_csv(&self, path: &str) -> io::Result<()> {
+        let mut out = String::from("structure,capacity,occupancy,cycles\n");
+        for histogram in &self.histograms {
+            for (n, cycles) in histogram.cycles.iter().enumerate() {
+                out += &format!(
+                    "{},{},{},{}\n",
+                    histogram.name, histogram.capacity, n, cycles
+                );
+            }
+        }
+
+        fs::write(path, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_count_cycles_per_occupancy() {
+        let mut occupancy = Occupancy::new();
+        occupancy.track(String::from("ROB"), 4);
+        occupancy.track(String::from("ALU busy"), 1);
+        for sample in [[0, 0], [2, 1], [4, 1], [4, 0], [2, 1], [1, 1]] {
+            occupancy.record(sample);
+        }
+
+        let rob = &occupancy.histograms[0];
+        assert_eq!(rob.cycles, vec![1, 1, 2, 0, 2]);
+        assert_eq!(rob.samples(), 6);
+        assert_eq!(rob.max(), 4);
+        assert_eq!(rob.mean(), 13.0 / 6.0);
+        assert_eq!(rob.full_rate(), 100.0 * 2.0 / 6.0);
+
+        let alu = &occupancy.histograms[1];
+        assert_eq!(alu.cycles, vec![2, 4]);
+        assert_eq!(alu.full_rate(), 100.0 * 4.0 / 6.0);
+    }
+
+    #[test]
+    fn occupancy_past_the_capacity_grows_the_histogram() {
+        let mut histogram = Histogram::new(String::from("fetch buffer"), 2);
+        histogram.record(1);
+        histogram.record(3);
+
+        assert_eq!(histogram.cycles, vec![0, 1, 0, 1]);
+        assert_eq!(histogram.max(), 3);
+        // anything at or over capacity counts as full
+        assert_eq!(histogram.full_rate(), 50.0);
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use clap::ValueEnum;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PipelineTraceFormat {
+    /// Kanata log for the Konata viewer
+    Konata,
+    /// gem5's O3PipeView, for util/o3-pipeview.py
+    O3PipeView,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PipelineStage {
+    Fetch,
+    Dispatch, // into a reservation station
+    Issue,    // into an execution unit
+    Complete,
+    Commit,
+    Squash,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PipelineEvent {
+    pub seq: u64,
+    pub pc: usize,
+    pub stage: PipelineStage,
+}
+
+fn label(labels: &[String], pc: usize) -> &str {
+    labels.get(pc).map_or("", |l| l.as_str())
+}
+
+/// cycles each stage of an instruction was reached, 0 if it never got there.
+#[derive(Debug, Default)]
+struct Timeline {
+    pc: usize,
+    fetch: u64,
+    dispatch: u64,
+    issue: u64,
+    complete: u64,
+}
+
+/// Streams the lifecycle of every instruction to a file a pipeline viewer can open.
+pub struct PipelineTrace {
+    format: PipelineTraceFormat,
+    out: BufWriter<File>,
+    labels: Vec<String>, // pc -> text shown for the instruction
+    in_flight: HashMap<u64, Timeline>,
+    cycle: u64,
+    retired: u64,
+}
+impl PipelineTrace {
+    pub fn create(
+        path: &str,
+        format: PipelineTraceFormat,
+        labels: Vec<String>,
+    ) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if format == PipelineTraceFormat::Konata {
+            writeln!(out, "Kanata\t0004")?;
+            writeln!(out, "C=\t0")?;
+        }
+
+        Ok(Self {
+            format,
+            out,
+            labels,
+            in_flight: HashMap::new(),
+            cycle: 0,
+            retired: 0,
+        })
+    }
+
+    /// Events have to be recorded in cycle order.
+    pub fn record(&mut self, cycle: u64, event: PipelineEvent) -> io::Result<()> {
+        // cycles are 1 indexed in the trace so 0 can mean a stage was never reached
+        let cycle = cycle + 1;
+
+        match self.format {
+            PipelineTraceFormat::Konata => self.record_konata(cycle, event),
+            PipelineTraceFormat::O3PipeView => self.record_o3(cycle, event),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn record_konata(&mut self, cycle: u64, event: PipelineEvent) -> io::Result<()> {
+        if cycle > self.cycle {
+            writeln!(self.out, "C\t{}", cycle - self.cycle)?;
+            self.cycle = cycle;
+        }
+
+        let id = event.seq;
+        let stage_name = |stage| match stage {
+            PipelineStage::Fetch => "F",
+            PipelineStage::Dispatch => "Ds",
+            PipelineStage::Issue => "Is",
+            _ => "Cm",
+        };
+
+        // end whatever stage it was in before
+        let previous = match event.stage {
+            PipelineStage::Fetch => None,
+            PipelineStage::Dispatch => Some(PipelineStage::Fetch),
+            PipelineStage::Issue => Some(PipelineStage::Dispatch),
+            PipelineStage::Complete => Some(PipelineStage::Issue),
+            PipelineStage::Commit => Some(PipelineStage::Complete),
+            PipelineStage::Squash => self.in_flight.get(&id).map(|t| {
+                if t.complete != 0 {
+                    PipelineStage::Complete
+                } else if t.issue != 0 {
+                    PipelineStage::Issue
+                } else if t.dispatch != 0 {
+                    PipelineStage::Dispatch
+                } else {
+                    PipelineStage::Fetch
+                }
+            }),
+        };
+        if let Some(previous) = previous {
+            writeln!(self.out, "E\t{}\t0\t{}", id, stage_name(previous))?;
+        }
+
+        match event.stage {
+            PipelineStage::Fetch => {
+                writeln!(self.out, "I\t{}\t{}\t0", id, id)?;
+                writeln!(
+                    self.out,
+                    "L\t{}\t0\t{}: {}",
+                    id,
+                    event.pc,
+                    label(&self.labels, event.pc)
+                )?;
+                writeln!(self.out, "S\t{}\t0\t{}", id, stage_name(event.stage))?;
+                self.in_flight.insert(id, Timeline::default());
+            }
+            PipelineStage::Dispatch | PipelineStage::Issue | PipelineStage::Complete => {
+                writeln!(self.out, "S\t{}\t0\t{}", id, stage_name(event.stage))?;
+                if let Some(timeline) = self.in_flight.get_mut(&id) {
+                    match event.stage {
+                        PipelineStage::Dispatch => timeline.dispatch = cycle,
+                        PipelineStage::Issue => timeline.issue = cycle,
+                        _ => timeline.complete = cycle,
+                    }
+                }
+            }
+            PipelineStage::Commit => {
+                writeln!(self.out, "R\t{}\t{}\t0", id, self.retired)?;
+                self.retired += 1;
+                self.in_flight.remove(&id);
+            }
+            PipelineStage::Squash => {
+                writeln!(self.out, "R\t{}\t{}\t1", id, id)?;
+                self.in_flight.remove(&id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_o3(&mut self, cycle: u64, event: PipelineEvent) -> io::Result<()> {
+        // o3-pipeview.py expects ticks, 1000 per cycle by default
+        let tick = cycle * 1000;
+
+        match event.stage {
+            PipelineStage::Fetch => {
+                self.in_flight.insert(
+                    event.seq,
+                    Timeline {
+                        pc: event.pc,
+                        fetch: tick,
+                        ..Default::default()
+                    },
+                );
+            }
+            PipelineStage::Dispatch | PipelineStage::Issue | PipelineStage::Complete => {
+                if let Some(timeline) = self.in_flight.get_mut(&event.seq) {
+                    match event.stage {
+                        PipelineStage::Dispatch => timeline.dispatch = tick,
+                        PipelineStage::Issue => timeline.issue = tick,
+                        _ => timeline.complete = tick,
+                    }
+                }
+            }
+            PipelineStage::Commit | PipelineStage::Squash => {
+                if let Some(timeline) = self.in_flight.remove(&event.seq) {
+                    let retire = match event.stage {
+                        PipelineStage::Commit => tick,
+                        _ => 0,
+                    };
+                    writeln!(
+                        self.out,
+                        "O3PipeView:fetch:{}:0x{:08x}:0:{}:{}",
+                        timeline.fetch,
+                        timeline.pc,
+                        event.seq,
+                        label(&self.labels, timeline.pc)
+                    )?;
+                    // we decode, rename and dispatch in the same stage
+                    writeln!(self.out, "O3PipeView:decode:{}", timeline.dispatch)?;
+                    writeln!(self.out, "O3PipeView:rename:{}", timeline.dispatch)?;
+                    writeln!(self.out, "O3PipeView:dispatch:{}", timeline.dispatch)?;
+                    writeln!(self.out, "O3PipeView:issue:{}", timeline.issue)?;
+                    writeln!(self.out, "O3PipeView:complete:{}", timeline.complete)?;
+                    writeln!(self.out, "O3PipeView:retire:{}:store:0", retire)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::{
+        assembler::assemble_source,
+        cpu::{CpuConfig, CPU},
+    };
+
+    /// the trace of `li $1 3; exit $1` on the default core.
+    fn trace(format: PipelineTraceFormat) -> String {
+        let path = env::temp_dir().join(format!("aca-pipeline-{:?}-{}", format, process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let (memory, instructions, _) =
+            assemble_source(".instructions\n    main:\n        li $1 3\n        exit $1\n");
+        let labels = vec![String::from("li $1 3"), String::from("exit $1")];
+
+        let mut cpu = CPU::new(CpuConfig::default());
+        cpu.set_memory(memory);
+        cpu.set_pipeline_trace(PipelineTrace::create(&path, format, labels).unwrap());
+        cpu.load_program(instructions).unwrap();
+        cpu.run_detailed(u64::MAX);
+        cpu.finish();
+
+        let trace = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        trace
+    }
+
+    #[test]
+    fn konata_trace_of_two_instructions() {
+        let expected = "\
+Kanata\t0004
+C=\t0
+C\t1
+I\t0\t0\t0
+L\t0\t0\t0: li $1 3
+S\t0\t0\tF
+I\t1\t1\t0
+L\t1\t0\t1: exit $1
+S\t1\t0\tF
+C\t1
+E\t0\t0\tF
+S\t0\t0\tDs
+E\t1\t0\tF
+S\t1\t0\tDs
+C\t1
+E\t0\t0\tDs
+S\t0\t0\tIs
+C\t1
+E\t0\t0\tIs
+S\t0\t0\tCm
+E\t1\t0\tDs
+S\t1\t0\tIs
+C\t1
+E\t0\t0\tCm
+R\t0\t0\t0
+E\t1\t0\tIs
+S\t1\t0\tCm
+C\t1
+E\t1\t0\tCm
+R\t1\t1\t0
+";
+        assert_eq!(trace(PipelineTraceFormat::Konata), expected);
+    }
+
+    #[test]
+    fn o3_pipeview_trace_of_two_instructions() {
+        let expected = "\
+O3PipeView:fetch:1000:0x00000000:0:0:li $1 3
+O3PipeView:decode:2000
+O3PipeView:rename:2000
+O3PipeView:dispatch:2000
+O3PipeView:issue:3000
+O3PipeView:complete:4000
+O3PipeView:retire:5000:store:0
+O3PipeView:fetch:1000:0x00000001:0:1:exit $1
+O3PipeView:decode:2000
+O3PipeView:rename:2000
+O3PipeView:dispatch:2000
+O3PipeView:issue:4000
+O3PipeView:complete:5000
+O3PipeView:retire:6000:store:0
+";
+        assert_eq!(trace(PipelineTraceFormat::O3PipeView), expected);
+    }
+}
@@ -1,37 +1,58 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
-use crate::instructions::Register;
+use crate::{instructions::Register, registers::GENERAL_REGISTERS};
 
 pub enum Tag {
     Register(Register),
     Rob(usize),
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RegisterAliasTable {
-    pub table: HashMap<Register, usize>,
+    table: Vec<Option<usize>>, // general registers then vector registers
 }
 impl RegisterAliasTable {
-    pub fn new() -> Self {
+    pub fn new(vector_registers: usize) -> Self {
         Self {
-            table: HashMap::new(),
+            table: vec![None; GENERAL_REGISTERS + vector_registers],
+        }
+    }
+
+    fn slot(reg: Register) -> usize {
+        if reg.is_vector() {
+            GENERAL_REGISTERS + reg.index()
+        } else {
+            reg.index()
         }
     }
 
     pub fn flush(&mut self) {
-        *self = Self::new();
+        self.table.fill(None);
     }
 
     pub fn set(&mut self, reg: Register, rob_index: usize) {
-        self.table.insert(reg, rob_index);
+        self.table[Self::slot(reg)] = Some(rob_index);
     }
 
     pub fn get(&self, reg: Register) -> Tag {
-        self.table
-            .get(&reg)
-            .map_or_else(|| Tag::Register(reg), |rob_index| Tag::Rob(*rob_index))
+        self.table[Self::slot(reg)].map_or(Tag::Register(reg), Tag::Rob)
     }
 
     pub fn remove(&mut self, reg: Register) {
-        self.table.remove(&reg);
+        self.table[Self::slot(reg)] = None;
+    }
+
+    /// every register waiting on the rob, in register order.
+    pub fn aliases(&self) -> impl Iterator<Item = (Register, usize)> + '_ {
+        self.table
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, rob_index)| {
+                let reg = match slot.checked_sub(GENERAL_REGISTERS) {
+                    Some(v) => Register::Vector(v as u32),
+                    None => Register::from_index(slot),
+                };
+                rob_index.map(|rob_index| (reg, rob_index))
+            })
     }
 }
@@ -1,58 +1,107 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
-use crate::instructions::Register;
+use crate::{float::RoundingMode, instructions::Register, vector::Vector};
 
-#[derive(Debug, Clone)]
+/// $pc, $hi, $lo and $0 to $63.
+pub const GENERAL_REGISTERS: usize = 67;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Registers {
-    pub general_registers: HashMap<Register, i32>,
-    pub vector_registers: HashMap<Register, u128>,
+    general_registers: Vec<i32>, // indexed by Register::index
+    vector_registers: Vec<Vector>,
+    vector_lanes: usize,
+    float_flags: u32, // sticky, everything raised since they were last cleared
+    rounding_mode: RoundingMode,
 }
 impl Registers {
-    pub fn new() -> Self {
-        let mut general_registers = HashMap::new();
-        let mut vector_registers = HashMap::new();
-        general_registers.insert(Register::ProgramCounter, 0);
-        general_registers.insert(Register::High, 0);
-        general_registers.insert(Register::Low, 0);
-        for i in 0..64 {
-            general_registers.insert(Register::General(i), 0);
-        }
-        for i in 0..2 {
-            vector_registers.insert(Register::Vector(i), 0);
-        }
-
+    /// `vector_registers` registers of `vector_lanes` lanes each.
+    pub fn new(vector_registers: usize, vector_lanes: usize) -> Self {
         Registers {
-            general_registers,
-            vector_registers,
+            general_registers: vec![0; GENERAL_REGISTERS],
+            vector_registers: vec![Vector::zero(vector_lanes); vector_registers],
+            vector_lanes,
+            float_flags: 0,
+            rounding_mode: RoundingMode::NearestEven,
         }
     }
 
+    pub fn vector_lanes(&self) -> usize {
+        self.vector_lanes
+    }
+
+    pub fn float_flags(&self) -> u32 {
+        self.float_flags
+    }
+
+    pub fn raise_float_flags(&mut self, flags: u32) {
+        self.float_flags |= flags;
+    }
+
+    pub fn clear_float_flags(&mut self) {
+        self.float_flags = 0;
+    }
+
+    pub fn rounding_mode(&self) -> RoundingMode {
+        self.rounding_mode
+    }
+
+    pub fn set_rounding_mode(&mut self, mode: RoundingMode) {
+        self.rounding_mode = mode;
+    }
+
     pub fn pc(&self) -> usize {
-        self.general_registers[&Register::ProgramCounter] as usize
+        self.general_registers[Register::ProgramCounter.index()] as usize
     }
 
     pub fn inc_pc(&mut self) {
-        self.general_registers.insert(
-            Register::ProgramCounter,
-            self.general_registers[&Register::ProgramCounter] + 1,
-        );
+        self.general_registers[Register::ProgramCounter.index()] += 1;
+    }
+
+    pub fn contains(&self, reg: Register) -> bool {
+        if reg.is_vector() {
+            reg.index() < self.vector_registers.len()
+        } else {
+            reg.index() < self.general_registers.len()
+        }
     }
 
     pub fn get(&self, reg: Register) -> i32 {
-        self.general_registers[&reg]
+        self.general_registers[reg.index()]
     }
 
-    pub fn get_vector(&self, reg: Register) -> u128 {
-        self.vector_registers[&reg]
+    pub fn get_vector(&self, reg: Register) -> Vector {
+        *self
+            .vector_registers
+            .get(reg.index())
+            .unwrap_or_else(|| panic!("there is no register {}", reg))
     }
 
     pub fn set(&mut self, reg: Register, val: i32) {
         if reg != Register::General(0) {
-            self.general_registers.insert(reg, val);
+            self.general_registers[reg.index()] = val;
         }
     }
 
-    pub fn set_vector(&mut self, reg: Register, val: u128) {
-        self.vector_registers.insert(reg, val);
+    pub fn set_vector(&mut self, reg: Register, val: Vector) {
+        *self
+            .vector_registers
+            .get_mut(reg.index())
+            .unwrap_or_else(|| panic!("there is no register {}", reg)) = val;
+    }
+
+    /// every general register with its value, in register order.
+    pub fn general(&self) -> impl Iterator<Item = (Register, i32)> + '_ {
+        self.general_registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (Register::from_index(i), *value))
+    }
+
+    /// every vector register with its value, in register order.
+    pub fn vectors(&self) -> impl Iterator<Item = (Register, Vector)> + '_ {
+        self.vector_registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (Register::Vector(i as u32), *value))
     }
 }
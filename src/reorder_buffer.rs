@@ -1,6 +1,11 @@
-use crate::instructions::{Op, Register};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::{
+    instructions::{Op, Register},
+    vector::Vector,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RobType {
     Branch,
     LoadMemory,
@@ -9,10 +14,11 @@ pub enum RobType {
     System,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Destination {
     Reg(Register),
     Memory(usize),
+    Lanes(Vector), // the address each lane of a strided store or scatter goes to
     None,
 }
 impl Destination {
@@ -22,20 +28,14 @@ impl Destination {
             _ => false,
         }
     }
-    pub fn to_mem_addr(&self) -> usize {
-        match self {
-            Self::Memory(addr) => *addr,
-            _ => panic!(),
-        }
-    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RobState {
     Issued,
     Executing,
     Finished,
-    Errored(&'static str),
+    Errored(String),
 }
 impl RobState {
     pub fn is_finished(&self) -> bool {
@@ -46,11 +46,11 @@ impl RobState {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RobValue {
     Value(i32),
     Overflow(i32, i32),
-    Vector(u128),
+    Vector(Vector),
 }
 impl RobValue {
     pub fn to_value(&self) -> i32 {
@@ -67,7 +67,7 @@ impl RobValue {
         }
     }
 
-    pub fn to_vector(&self) -> u128 {
+    pub fn to_vector(&self) -> Vector {
         match self {
             Self::Vector(val) => *val,
             _ => panic!("RobValue is not a Vector!"),
@@ -82,7 +82,7 @@ impl RobValue {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RobInst {
     pub index: usize,
     pub op: Op,
@@ -93,9 +93,12 @@ pub struct RobInst {
     pub _speculative: bool,
     pub taken: bool,
     pub pc: usize,
+    pub target: Option<usize>,
+    pub seq: u64,
+    pub float_flags: u32, // exceptions raised, added to the status register when it commits
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReorderBuffer {
     pub buffer: Vec<Option<RobInst>>,
     size: usize,
@@ -116,6 +119,24 @@ impl ReorderBuffer {
         }
     }
 
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// where the next instruction goes.
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// where the oldest instruction is.
+    pub fn tail(&self) -> usize {
+        self.tail
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.iter().filter(|item| item.is_some()).count()
+    }
+
     pub fn is_full(&self) -> bool {
         self.buffer[self.head].is_some() // our head has reached the tail (could do maths too)
     }
@@ -141,6 +162,17 @@ impl ReorderBuffer {
         }
     }
 
+    pub fn oldest(&self) -> Option<&RobInst> {
+        self.buffer[self.tail].as_ref()
+    }
+
+    /// occupied entries from oldest to youngest.
+    pub fn in_order(&self) -> Vec<&RobInst> {
+        (0..self.size)
+            .filter_map(|i| self.buffer[(self.tail + i) % self.size].as_ref())
+            .collect()
+    }
+
     pub fn get_mut(&mut self, index: usize) -> &mut Option<RobInst> {
         &mut self.buffer[index]
     }
@@ -165,25 +197,14 @@ impl ReorderBuffer {
         return retired;
     }
 
-    pub fn instructions_older(&self, mut index: usize) -> Vec<RobInst> {
-        let mut older = Vec::new();
+    /// everything between the oldest entry and `index`, youngest first. `head` can't mark
+    /// where to stop since it's the same as `tail` once the rob is full.
+    pub fn instructions_older(&self, index: usize) -> impl Iterator<Item = &RobInst> {
+        let count = (index + self.size - self.tail) % self.size;
 
-        if index == 0 {
-            index = self.size - 1;
-        } else {
-            index = index - 1;
-        }
-
-        while self.buffer[index].is_some() && index != self.head {
-            older.push(self.buffer[index].clone().unwrap());
-            if index == 0 {
-                index = self.size - 1;
-            } else {
-                index = index - 1;
-            }
-        }
-
-        older
+        (0..count)
+            .rev()
+            .filter_map(move |i| self.buffer[(self.tail + i) % self.size].as_ref())
     }
 
     //     pub fn remove_speculative_younger(&self, mut index: usize) {
@@ -196,3 +217,44 @@ impl ReorderBuffer {
     //         older
     //     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rob_inst(op: Op, seq: u64) -> RobInst {
+        RobInst {
+            index: 0,
+            op,
+            inst: op.rob_type(),
+            destination: Destination::None,
+            value: RobValue::Value(0),
+            state: RobState::Issued,
+            _speculative: false,
+            taken: false,
+            pc: 0,
+            target: None,
+            seq,
+            float_flags: 0,
+        }
+    }
+
+    #[test]
+    fn instructions_older_includes_the_oldest_when_full() {
+        let mut rob = ReorderBuffer::new(4, 1);
+
+        // retire one first so the oldest isn't in the first slot
+        let first = rob.add_instruction(rob_inst(Op::Add, 0));
+        rob.get_mut(first).as_mut().unwrap().state = RobState::Finished;
+        rob.retire();
+
+        rob.add_instruction(rob_inst(Op::StoreMemory, 1));
+        rob.add_instruction(rob_inst(Op::Add, 2));
+        rob.add_instruction(rob_inst(Op::Add, 3));
+        let load = rob.add_instruction(rob_inst(Op::LoadMemory, 4));
+        assert!(rob.is_full());
+
+        let older: Vec<u64> = rob.instructions_older(load).map(|inst| inst.seq).collect();
+        assert_eq!(older, vec![3, 2, 1]);
+    }
+}
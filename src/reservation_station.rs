@@ -1,15 +1,19 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{
     execution_units::{EUType, ExeInst, ExeOperand},
+    float::RoundingMode,
     instructions::{Op, Register, Word},
-    reorder_buffer::{ReorderBuffer, RobState, RobValue},
+    reorder_buffer::{Destination, ReorderBuffer, RobState, RobValue},
+    vector::Vector,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ResOperand {
     Reg(Register),
     Rob(usize),
     Value(i32),
-    Vector(u128),
+    Vector(Vector),
 }
 impl ResOperand {
     pub fn is_rob(&self) -> bool {
@@ -29,35 +33,160 @@ impl ResOperand {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Memory an instruction reads or writes, either `len` bytes from an address or a word at
+/// every lane's address.
+enum Access {
+    Range(usize, usize),
+    Lanes(Vector),
+}
+impl Access {
+    fn ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (range, lanes) = match self {
+            Self::Range(addr, len) => (Some((*addr, *len)), &[][..]),
+            Self::Lanes(addresses) => (None, addresses.lanes()),
+        };
+        range
+            .into_iter()
+            .chain(lanes.iter().map(|addr| (*addr as usize, 4)))
+    }
+
+    fn overlaps(&self, other: &Access) -> bool {
+        self.ranges().any(|(addr, len)| {
+            other.ranges().any(|(other_addr, other_len)| {
+                other_addr < addr + len && addr < other_addr + other_len
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct ResInst {
     pub word: Word,
     pub pc: usize,
+    pub seq: u64,
     pub rob_index: usize,
     pub branch_taken: bool,
+    pub vector_lanes: usize, // how wide vectors were when it was dispatched
+    pub rounding: RoundingMode, // fsrm flushes everything younger, so this can't go stale
     pub return_op: ResOperand,
     pub left_op: ResOperand,
     pub right_op: ResOperand,
+    pub extra_op: ResOperand, // the mask in $v0 for masked ops and blends, the vector vins writes into
 }
 impl ResInst {
+    pub fn operands_ready(&self) -> bool {
+        !self.return_op.is_rob()
+            && !self.left_op.is_rob()
+            && !self.right_op.is_rob()
+            && !self.extra_op.is_rob()
+    }
+
+    /// whether an older store that hasn't committed might overlap what we access.
+    pub fn has_memory_dependence(&self, rob: &ReorderBuffer) -> bool {
+        // check mem dependency :D
+        if match self.word.op() {
+            Op::LoadChar
+            | Op::LoadHalfWord
+            | Op::LoadMemory
+            | Op::VLoadMemory
+            | Op::VLoadStrided
+            | Op::VGather
+            | Op::Save
+            | Op::StoreChar
+            | Op::StoreMemory
+            | Op::VStoreMemory
+            | Op::VStoreStrided
+            | Op::VScatter => true,
+            _ => false,
+        } {
+            let inst_addr = || {
+                (self.left_op.to_exe_operand().to_value()
+                    + self.right_op.to_exe_operand().to_value()) as usize
+            };
+            let access = match self.word.op() {
+                Op::LoadChar => Access::Range(inst_addr(), 1),
+                Op::LoadHalfWord => Access::Range(inst_addr(), 2),
+                Op::LoadMemory => Access::Range(inst_addr(), 4),
+                Op::VLoadMemory | Op::VStoreMemory => {
+                    Access::Range(inst_addr(), self.vector_lanes * 4)
+                }
+                Op::VLoadStrided | Op::VGather | Op::VStoreStrided | Op::VScatter => {
+                    Access::Lanes(self.to_exe_inst().lane_addresses())
+                }
+                Op::Save => Access::Range(
+                    inst_addr(),
+                    self.return_op.to_exe_operand().to_value() as usize,
+                ),
+                Op::StoreChar => Access::Range(inst_addr(), 1),
+                Op::StoreMemory => Access::Range(inst_addr(), 4),
+                _ => panic!("no len"),
+            };
+
+            return rob.instructions_older(self.rob_index).any(|older| {
+                let mem_inst = match older.op {
+                    Op::StoreChar
+                    | Op::StoreMemory
+                    | Op::VStoreMemory
+                    | Op::VStoreStrided
+                    | Op::VScatter => true,
+                    _ => false,
+                };
+
+                if older.state != RobState::Finished && mem_inst {
+                    true
+                } else if older.state == RobState::Finished && mem_inst {
+                    let older_access = match (&older.destination, older.op) {
+                        (Destination::Lanes(addresses), _) => Access::Lanes(*addresses),
+                        (Destination::Memory(addr), Op::StoreChar) => Access::Range(*addr, 1),
+                        (Destination::Memory(addr), Op::StoreMemory) => Access::Range(*addr, 4),
+                        (Destination::Memory(addr), Op::VStoreMemory) => {
+                            Access::Range(*addr, older.value.to_vector().bytes())
+                        }
+                        _ => panic!("This isnt recognised :("),
+                    };
+
+                    access.overlaps(&older_access)
+                } else {
+                    false
+                }
+            });
+        }
+
+        return false;
+    }
+
+    fn rob_operands(&self) -> impl Iterator<Item = usize> {
+        [self.return_op, self.left_op, self.right_op, self.extra_op]
+            .into_iter()
+            .filter_map(|op| match op {
+                ResOperand::Rob(index) => Some(index),
+                _ => None,
+            })
+    }
+
     pub fn to_exe_inst(&self) -> ExeInst {
         ExeInst {
             word: self.word,
             pc: self.pc,
+            seq: self.seq,
             rob_index: self.rob_index,
             branch_taken: self.branch_taken,
+            vector_lanes: self.vector_lanes,
+            rounding: self.rounding,
             ret: self.return_op.to_exe_operand(),
             left: self.left_op.to_exe_operand(),
             right: self.right_op.to_exe_operand(),
+            extra: self.extra_op.to_exe_operand(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReservationStation {
     pub buffer: Vec<ResInst>,
     capacity: usize,
     reserves_for: EUType,
+    waiting: Vec<usize>, // operands waiting on each rob index, so most results skip the scan
 }
 impl ReservationStation {
     pub fn new(capacity: usize, reserves_for: EUType) -> Self {
@@ -65,6 +194,7 @@ impl ReservationStation {
             buffer: Vec::new(),
             capacity,
             reserves_for,
+            waiting: Vec::new(),
         }
     }
 
@@ -72,6 +202,14 @@ impl ReservationStation {
         self.reserves_for
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
     pub fn is_full(&self) -> bool {
         self.buffer.len() == self.capacity
     }
@@ -86,6 +224,12 @@ impl ReservationStation {
 
     pub fn add_instruction(&mut self, instruction: ResInst) {
         if self.buffer.len() < self.capacity {
+            for rob_index in instruction.rob_operands() {
+                if rob_index >= self.waiting.len() {
+                    self.waiting.resize(rob_index + 1, 0);
+                }
+                self.waiting[rob_index] += 1;
+            }
             self.buffer.push(instruction);
         } else {
             panic!("Tried to add instruction to rs but doesn't have capacity!");
@@ -93,14 +237,24 @@ impl ReservationStation {
     }
 
     pub fn update_operands(&mut self, rob_index: usize, value: RobValue) {
+        if self
+            .waiting
+            .get(rob_index)
+            .is_none_or(|waiting| *waiting == 0)
+        {
+            return;
+        }
         let res_op = ResOperand::Rob(rob_index);
+        let mut resolved = 0;
 
         for inst in self.buffer.iter_mut() {
             if value.is_overflow() {
                 if inst.word.op() == Op::MoveFromHigh && inst.left_op == res_op {
                     inst.left_op = ResOperand::Value(value.to_overflow().0);
+                    resolved += 1;
                 } else if inst.word.op() == Op::MoveFromLow && inst.left_op == res_op {
                     inst.left_op = ResOperand::Value(value.to_overflow().1);
+                    resolved += 1;
                 }
             } else {
                 let res_val = match value {
@@ -110,77 +264,33 @@ impl ReservationStation {
                 };
                 if inst.return_op == res_op {
                     inst.return_op = res_val;
+                    resolved += 1;
                 }
                 if inst.left_op == res_op {
                     inst.left_op = res_val;
+                    resolved += 1;
                 }
                 if inst.right_op == res_op {
                     inst.right_op = res_val;
+                    resolved += 1;
+                }
+                if inst.extra_op == res_op {
+                    inst.extra_op = res_val;
+                    resolved += 1;
                 }
             }
         }
+
+        self.waiting[rob_index] -= resolved;
     }
 
     pub fn take_oldest_valid(&mut self, rob: &mut ReorderBuffer) -> Option<ResInst> {
         for i in 0..self.buffer.len() {
             let inst = &self.buffer[i];
 
-            if !inst.return_op.is_rob() && !inst.left_op.is_rob() && !inst.right_op.is_rob() {
-                // check mem dependency :D
-                let mut mem_dep = false;
-                if match inst.word.op() {
-                    Op::LoadChar
-                    | Op::LoadHalfWord
-                    | Op::LoadMemory
-                    | Op::VLoadMemory
-                    | Op::Save
-                    | Op::StoreChar
-                    | Op::StoreMemory
-                    | Op::VStoreMemory => true,
-                    _ => false,
-                } {
-                    let inst_addr = (inst.left_op.to_exe_operand().to_value()
-                        + inst.right_op.to_exe_operand().to_value())
-                        as usize;
-                    let inst_len = match inst.word.op() {
-                        Op::LoadChar => 1,
-                        Op::LoadHalfWord => 2,
-                        Op::LoadMemory => 4,
-                        Op::VLoadMemory => 16,
-                        Op::Save => inst.return_op.to_exe_operand().to_value() as usize,
-                        Op::StoreChar => 1,
-                        Op::StoreMemory => 4,
-                        Op::VStoreMemory => 16,
-                        _ => panic!("no len"),
-                    };
-
-                    for older in rob.instructions_older(inst.rob_index) {
-                        let mem_inst = match older.op {
-                            Op::StoreChar | Op::StoreMemory | Op::VStoreMemory => true,
-                            _ => false,
-                        };
-
-                        if older.state != RobState::Finished && mem_inst {
-                            mem_dep |= true;
-                        } else if older.state == RobState::Finished && mem_inst {
-                            let old_addr = older.destination.to_mem_addr();
-                            let old_len = match older.op {
-                                Op::StoreChar => 1,
-                                Op::StoreMemory => 4,
-                                Op::VStoreMemory => 16,
-                                _ => panic!("This isnt recognised :("),
-                            };
-
-                            mem_dep |=
-                                old_addr < inst_addr + inst_len && inst_addr < old_addr + old_len;
-                        }
-                    }
-                }
-
-                if !mem_dep {
-                    let inst = self.buffer.remove(i);
-                    return Some(inst);
-                }
+            if inst.operands_ready() && !inst.has_memory_dependence(rob) {
+                let inst = self.buffer.remove(i);
+                return Some(inst);
             }
         }
 
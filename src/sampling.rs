@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    stalls::{CommitStall, DispatchStall},
+    stats::StatsTracker,
+    stats_report::field_name,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SampleUnit {
+    Cycles,
+    Instructions, // committed
+}
+
+/// The running totals an interval is measured from.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    cycles: u64,
+    committed: u64,
+    dispatched: u64,
+    branches: u64,
+    mispredictions: u64,
+    base_slots: u64,
+    commit_slots: Vec<u64>,    // per CommitStall::all()
+    dispatch_cycles: Vec<u64>, // per DispatchStall::all()
+    occupancy: Vec<u64>,       // entries summed over every cycle, per histogram
+}
+impl Snapshot {
+    fn new(stats: &StatsTracker) -> Self {
+        let stalls = &stats.stalls;
+
+        Self {
+            cycles: stats.cycles,
+            committed: stats.instructions_commited,
+            dispatched: stats.instructions_started,
+            branches: stats.committed_predicted_branches,
+            mispredictions: stats.committed_mispredicions,
+            base_slots: stalls.base_slots,
+            commit_slots: CommitStall::all()
+                .iter()
+                .map(|s| stalls.commit_slots.get(s).copied().unwrap_or(0))
+                .collect(),
+            dispatch_cycles: DispatchStall::all()
+                .iter()
+                .map(|s| stalls.dispatch_cycles.get(s).copied().unwrap_or(0))
+                .collect(),
+            occupancy: stats
+                .occupancy
+                .histograms
+                .iter()
+                .map(|h| {
+                    h.cycles
+                        .iter()
+                        .enumerate()
+                        .map(|(n, cycles)| n as u64 * cycles)
+                        .sum()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes how the program performed over every interval of `every` cycles or committed
+/// instructions to a csv, one row per interval.
+pub struct Sampler {
+    every: u64,
+    unit: SampleUnit,
+    out: BufWriter<File>,
+    last: Option<Snapshot>,
+    interval: u64,
+}
+impl Sampler {
+    pub fn create(path: &str, every: u64, unit: SampleUnit) -> io::Result<Self> {
+        if every == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("can't sample every 0 {:?}", unit),
+            ));
+        }
+
+        Ok(Self {
+            every,
+            unit,
+            out: BufWriter::new(File::create(path)?),
+            last: None,
+            interval: 0,
+        })
+    }
+
+    /// Called after every cycle, writes a row once an interval has passed.
+    pub fn sample(&mut self, stats: &StatsTracker) -> io::Result<()> {
+        let last = self.last.as_ref().expect("sampler wasn't started");
+        let elapsed = match self.unit {
+            SampleUnit::Cycles => stats.cycles - last.cycles,
+            SampleUnit::Instructions => stats.instructions_commited - last.committed,
+        };
+        if elapsed >= self.every {
+            self.write_interval(stats)?;
+        }
+
+        Ok(())
+    }
+
+    /// writes whatever is left over as a final shorter interval.
+    pub fn finish(mut self, stats: &StatsTracker) -> io::Result<()> {
+        if self.last.as_ref().expect("sampler wasn't started").cycles < stats.cycles {
+            self.write_interval(stats)?;
+        }
+
+        self.out.flush()
+    }
+
+    /// Writes the header and measures the first interval from `stats`, which aren't zero
+    /// when carrying on from a checkpoint. The columns depend on what structures the cpu has.
+    pub fn start(&mut self, stats: &StatsTracker) -> io::Result<()> {
+        self.last = Some(Snapshot::new(stats));
+
+        let mut header = String::from(
+            "interval,start_cycle,end_cycle,cycles,instructions,ipc,dispatched,branches,mispredictions,misprediction_rate,mpki,cpi_base",
+        );
+        for stall in CommitStall::all() {
+            header += &format!(",cpi_{}", field_name(&stall.name()));
+        }
+        for stall in DispatchStall::all() {
+            header += &format!(",dispatch_{}", field_name(&stall.name()));
+        }
+        for histogram in stats.occupancy.histograms.iter() {
+            header += &format!(",occupancy_{}", field_name(&histogram.name));
+        }
+
+        writeln!(self.out, "{}", header)
+    }
+
+    fn write_interval(&mut self, stats: &StatsTracker) -> io::Result<()> {
+        let now = Snapshot::new(stats);
+        let last = self.last.replace(now.clone()).unwrap();
+
+        let cycles = now.cycles - last.cycles;
+        let instructions = now.committed - last.committed;
+        let mispredictions = now.mispredictions - last.mispredictions;
+        let branches = now.branches - last.branches;
+        let cpi = |slots: u64| {
+            let slots = slots as f64 / stats.stalls.commit_width as f64;
+            ratio(slots, instructions, 4)
+        };
+
+        let mut row = format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.interval,
+            last.cycles,
+            now.cycles,
+            cycles,
+            instructions,
+            ratio(instructions as f64, cycles, 4),
+            now.dispatched - last.dispatched,
+            branches,
+            mispredictions,
+            ratio(mispredictions as f64, branches, 4),
+            ratio(1000.0 * mispredictions as f64, instructions, 4),
+            cpi(now.base_slots - last.base_slots),
+        );
+        for (now, last) in now.commit_slots.iter().zip(last.commit_slots.iter()) {
+            row += &format!(",{}", cpi(now - last));
+        }
+        for (now, last) in now.dispatch_cycles.iter().zip(last.dispatch_cycles.iter()) {
+            row += &format!(",{}", now - last);
+        }
+        for (now, last) in now.occupancy.iter().zip(last.occupancy.iter()) {
+            row += &format!(",{}", ratio((now - last) as f64, cycles, 3));
+        }
+
+        self.interval += 1;
+        writeln!(self.out, "{}", row)
+    }
+}
+
+/// `numerator / denominator` to `places` decimal places, or an empty field when nothing
+/// happened in the interval to divide by.
+fn ratio(numerator: f64, denominator: u64, places: usize) -> String {
+    match denominator {
+        0 => String::new(),
+        _ => format!("{:.*}", places, numerator / denominator as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::{
+        assembler::assemble_source,
+        cpu::{CpuConfig, CPU},
+    };
+
+    #[test]
+    fn intervals_without_branches_leave_the_rate_empty() {
+        let path = env::temp_dir().join(format!("aca-samples-{}.csv", process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let (memory, instructions, _) =
+            assemble_source(".instructions\n    main:\n        li $1 3\n        exit $1\n");
+
+        let mut cpu = CPU::new(CpuConfig::default());
+        cpu.set_memory(memory);
+        cpu.set_sampler(Sampler::create(&path, 1, SampleUnit::Cycles).unwrap());
+        cpu.load_program(instructions).unwrap();
+        cpu.run_detailed(u64::MAX);
+        cpu.finish();
+
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut lines = csv.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        let column = |name| header.iter().position(|h| *h == name).unwrap();
+
+        // the first cycle only fetches, so nothing commits either
+        let first: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(first[column("branches")], "0");
+        assert_eq!(first[column("instructions")], "0");
+        for name in ["misprediction_rate", "mpki", "cpi_base"] {
+            assert_eq!(first[column(name)], "", "{}", name);
+        }
+        assert!(!csv.contains("NaN") && !csv.contains("inf"), "{}", csv);
+    }
+}
@@ -0,0 +1,442 @@
+use std::{env, fs, process};
+
+use clap::Parser;
+
+use crate::{
+    assembler::{assemble_file, memory_labels},
+    checkpoint::{Checkpoint, CheckpointAt},
+    cpu::{CpuConfig, CPU},
+    instructions::Register,
+    stats::StatsTracker,
+    Cli,
+};
+
+/// far longer than any instruction takes to commit, so a deadlock fails the test.
+const MAX_CYCLES_WITHOUT_COMMIT: u64 = 10_000;
+
+/// Every script is run under each of these, the results must be the same whatever the timing.
+const CONFIGS: &[&str] = &[
+    "",
+    "--rob-size 2 --rob-max-retire 1",
+    "--fetch-amount 1 --fetch-buffer-capacity 1 --dispatch-amount 1 --rob-max-retire 1",
+    "--eu-alu-num 1 --eu-fpu-num 1 --eu-vpu-num 1 --eu-lsu-num 1 --eu-branch-num 1 \
+     --rs-alu-size 1 --rs-fpu-size 1 --rs-vpu-size 1 --rs-lsu-size 1 --rs-branch-size 1",
+    "-b always-take",
+    "-b never-take",
+    "-b local",
+    "-b gshare",
+    "-b loop+gshare",
+    "--fast-forward 1000000000", // the functional model on its own
+    "--vector-width 256",
+    "--vector-width 512 --vector-registers 32",
+];
+
+#[derive(Debug)]
+enum Target {
+    Exit,
+    Error(String), // the program stops with this error
+    Register(Register),
+    Memory(String), // label
+}
+
+/// A `// expect: <target> <values>` line. The target is `exit`, a register or a memory
+/// label and the values are written like a memory directive, e.g. `list .int 1, 2, 3` or
+/// `$v1 .float 1.5, 2, 2.5, 3`. `// expect: error <message>` expects the program to stop
+/// with that error.
+#[derive(Debug)]
+struct Expectation {
+    name: String, // the target as written
+    target: Target,
+    float: bool,
+    words: Vec<[u8; 4]>,
+}
+impl Expectation {
+    fn parse(line: &str) -> Self {
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let name = name.to_string();
+
+        if name == "error" {
+            return Self {
+                name,
+                target: Target::Error(rest.trim().to_string()),
+                float: false,
+                words: Vec::new(),
+            };
+        }
+
+        if name == "exit" {
+            let value: i32 = rest.trim().parse().expect("exit value isn't an int");
+            return Self {
+                name,
+                target: Target::Exit,
+                float: false,
+                words: vec![value.to_be_bytes()],
+            };
+        }
+
+        let target = match name.starts_with('$') {
+            true => Target::Register(name.parse().unwrap_or_else(|e| panic!("{}", e))),
+            false => Target::Memory(name.clone()),
+        };
+        let (directive, values) = rest.trim().split_once(' ').expect("expected values");
+        let values = values.split(',').map(|v| v.trim());
+        let (float, words) = match directive {
+            ".int" => (
+                false,
+                values
+                    .map(|v| v.parse::<i32>().expect("not an int").to_be_bytes())
+                    .collect(),
+            ),
+            ".float" => (
+                true,
+                values
+                    .map(|v| v.parse::<f32>().expect("not a float").to_be_bytes())
+                    .collect(),
+            ),
+            other => panic!("unknown directive {}", other),
+        };
+
+        Self {
+            name,
+            target,
+            float,
+            words,
+        }
+    }
+
+    fn show(&self, words: &[[u8; 4]]) -> String {
+        let values: Vec<String> = words
+            .iter()
+            .map(|word| match self.float {
+                true => f32::from_be_bytes(*word).to_string(),
+                false => i32::from_be_bytes(*word).to_string(),
+            })
+            .collect();
+        values.join(", ")
+    }
+}
+
+fn expectations(script: &str) -> Vec<Expectation> {
+    fs::read_to_string(script)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// expect:"))
+        .map(|line| Expectation::parse(line.trim()))
+        .collect()
+}
+
+/// Runs the script to completion.
+fn run(script: &str, flags: &str) -> (CPU, StatsTracker) {
+    let cli = Cli::parse_from(["aca", script].into_iter().chain(flags.split_whitespace()));
+    let args = cli.args.unwrap();
+    let fast_forward = args.fast_forward;
+
+    let (memory, instructions, _) = assemble_file(script);
+    let mut cpu = CPU::new(args.into());
+    cpu.set_memory(memory);
+    cpu.load_program(instructions).unwrap();
+    if let Some(instructions) = fast_forward {
+        cpu.fast_forward(instructions);
+    }
+    let mut last_commit = (0, 0); // (cycle, instructions committed)
+    while cpu.is_running() {
+        cpu.step();
+
+        let stats = cpu.stats();
+        if stats.instructions_commited != last_commit.1 {
+            last_commit = (stats.cycles, stats.instructions_commited);
+        }
+        assert!(
+            stats.cycles - last_commit.0 < MAX_CYCLES_WITHOUT_COMMIT,
+            "{} with `{}` hasn't committed anything since cycle {}",
+            script,
+            flags,
+            last_commit.0
+        );
+    }
+    let stats = cpu.finish();
+    (cpu, stats)
+}
+
+/// Runs the script to completion and checks everything it expects.
+fn run_script(script: &str, flags: &str) {
+    let (cpu, stats) = run(script, flags);
+
+    let labels = memory_labels(script);
+    let expectations = expectations(script);
+    assert!(
+        !expectations.is_empty(),
+        "{} doesn't expect anything",
+        script
+    );
+    let error = expectations.iter().find_map(|e| match &e.target {
+        Target::Error(error) => Some(error.as_str()),
+        _ => None,
+    });
+    assert_eq!(
+        stats.exit_error.as_deref(),
+        error,
+        "{} with `{}` stopped with the wrong error",
+        script,
+        flags
+    );
+
+    for expectation in expectations.iter() {
+        let actual: Vec<[u8; 4]> = match &expectation.target {
+            Target::Error(_) => continue,
+            Target::Exit => match stats.exit_value {
+                Some(value) => vec![value.to_be_bytes()],
+                None => Vec::new(),
+            },
+            // only the lanes it expects, so scripts can be checked at any vector width
+            Target::Register(reg) if reg.is_vector() => cpu
+                .registers()
+                .get_vector(*reg)
+                .lanes()
+                .iter()
+                .take(expectation.words.len())
+                .map(|lane| lane.to_be_bytes())
+                .collect(),
+            Target::Register(reg) => vec![cpu.registers().get(*reg).to_be_bytes()],
+            Target::Memory(label) => {
+                let addr = *labels
+                    .get(label)
+                    .unwrap_or_else(|| panic!("{} has no memory label {}", script, label));
+                cpu.memory()[addr..(addr + 4 * expectation.words.len())]
+                    .chunks(4)
+                    .map(|word| word.try_into().unwrap())
+                    .collect()
+            }
+        };
+
+        assert!(
+            actual == expectation.words,
+            "{} with `{}`: {} is [{}], expected [{}]",
+            script,
+            flags,
+            expectation.name,
+            expectation.show(&actual),
+            expectation.show(&expectation.words)
+        );
+    }
+}
+
+fn check(name: &str) {
+    let script = format!("scripts/{}.acasm", name);
+
+    // each configuration is independent, running them together keeps the slow scripts quick
+    std::thread::scope(|scope| {
+        for flags in CONFIGS {
+            scope.spawn(|| run_script(&script, flags));
+        }
+    });
+}
+
+#[test]
+fn gcd() {
+    check("gcd");
+}
+
+#[test]
+fn factorial() {
+    check("factorial");
+}
+
+#[test]
+fn integer_overflow() {
+    check("integer_overflow");
+}
+
+#[test]
+fn fibonacci() {
+    check("fibonacci");
+}
+
+#[test]
+fn floating_test() {
+    check("floating_test");
+}
+
+#[test]
+fn inner_product() {
+    check("inner_product");
+}
+
+#[test]
+fn simd_inner_product() {
+    check("simd_inner_product");
+}
+
+#[test]
+fn matmul() {
+    check("matmul");
+}
+
+#[test]
+fn merge_sort() {
+    check("merge_sort");
+}
+
+#[test]
+fn vector_add() {
+    check("vector_add");
+}
+
+#[test]
+fn simd_vector_add() {
+    check("simd_vector_add");
+}
+
+#[test]
+fn simd_ops() {
+    check("simd_ops");
+}
+
+#[test]
+fn simd_gather() {
+    check("simd_gather");
+}
+
+#[test]
+fn vector_divide_by_zero() {
+    check("vector_divide_by_zero");
+}
+
+#[test]
+fn integer_ops() {
+    check("integer_ops");
+}
+
+#[test]
+fn float_exceptions() {
+    check("float_exceptions");
+}
+
+#[test]
+fn float_traps() {
+    let (_, stats) = run(
+        "scripts/float_exceptions.acasm",
+        "--fp-traps invalid,overflow",
+    );
+    assert_eq!(
+        stats.exit_error.as_deref(),
+        Some("floating point exception (invalid) at pc 6")
+    );
+}
+
+/// Restoring a checkpoint taken partway through has to end exactly where carrying on did.
+#[test]
+fn checkpoint_round_trip() {
+    let script = "scripts/merge_sort.acasm";
+    let config = || -> CpuConfig {
+        let cli = Cli::parse_from(["aca", script, "-b", "loop+gshare"]);
+        cli.args.unwrap().into()
+    };
+    let path = env::temp_dir().join(format!("aca-round-trip-{}", process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let (memory, instructions, _) = assemble_file(script);
+
+    let mut continued = CPU::new(config());
+    continued.set_memory(memory);
+    continued.set_checkpoint(CheckpointAt::Cycle(400), path.clone());
+    continued.load_program(instructions.clone()).unwrap();
+    continued.run_detailed(u64::MAX);
+    let continued_stats = continued.finish();
+
+    let checkpoint = Checkpoint::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(checkpoint.stats_tracker.cycles, 400);
+    let mut restored = CPU::restore(checkpoint, config());
+    restored.load_program(instructions).unwrap();
+    restored.run_detailed(u64::MAX);
+    let restored_stats = restored.finish();
+
+    assert_eq!(restored.registers(), continued.registers());
+    assert_eq!(restored.memory(), continued.memory());
+    assert_eq!(
+        restored.checkpoint().branch_predictor,
+        continued.checkpoint().branch_predictor
+    );
+    assert_eq!(restored_stats.exit_value, continued_stats.exit_value);
+    let counts = |stats: &StatsTracker| {
+        [
+            stats.cycles,
+            stats.instructions_started,
+            stats.instructions_commited,
+            stats.branch_predictions,
+            stats.branch_mispredictions,
+            stats.committed_mispredicions,
+        ]
+    };
+    assert_eq!(counts(&restored_stats), counts(&continued_stats));
+}
+
+/// Structural flags given alongside --restore have to match the checkpoint, defaults don't.
+#[test]
+fn restoring_with_a_different_structure() {
+    let given = |flags: &[&str]| {
+        let cli = Cli::parse_noting_given(["aca", "scripts/gcd.acasm"].iter().chain(flags));
+        let args = cli.args.unwrap();
+        let given = args.given.clone();
+        (CpuConfig::from(args), given)
+    };
+    let checkpoint = CpuConfig {
+        rob_size: 16,
+        ..CpuConfig::default()
+    };
+
+    let (config, flags) = given(&[]);
+    assert_eq!(config.check_restore(&checkpoint, &flags), Ok(()));
+    let (config, flags) = given(&["--rob-size", "16", "-e", "3"]);
+    assert_eq!(config.check_restore(&checkpoint, &flags), Ok(()));
+    let (config, flags) = given(&["--rob-size", "32"]);
+    assert_eq!(
+        config.check_restore(&checkpoint, &flags),
+        Err(String::from(
+            "--rob-size 32 doesn't match the checkpoint, which was taken with 16"
+        ))
+    );
+    let (config, flags) = given(&["--fp-traps", "invalid"]);
+    assert_eq!(
+        config.check_restore(&checkpoint, &flags),
+        Err(String::from(
+            "--fp-traps invalid doesn't match the checkpoint, which was taken with none"
+        ))
+    );
+
+    // and whichever command they're given to
+    let mut cli = Cli::parse_noting_given(["aca", "debug", "scripts/gcd.acasm", "-r", "32"]);
+    assert!(cli
+        .args_mut()
+        .unwrap()
+        .given
+        .contains(&String::from("rob_size")));
+}
+
+#[test]
+fn default_config_matches_no_options() {
+    let cli = Cli::parse_from(["aca", "scripts/gcd.acasm"]);
+    let config: CpuConfig = cli.args.expect("run arguments").into();
+    assert_eq!(config, CpuConfig::default());
+}
+
+#[test]
+fn missing_vector_register() {
+    let script = "scripts/simd_ops.acasm";
+    let cli = Cli::parse_from(["aca", script, "--vector-registers", "2"]);
+    let mut cpu = CPU::new(cli.args.unwrap().into());
+    let (_, instructions, _) = assemble_file(script);
+
+    assert_eq!(
+        cpu.load_program(instructions),
+        Err(String::from(
+            "$v2 at pc 1 doesn't exist, there are 2 vector registers"
+        ))
+    );
+}
+
+#[test]
+#[ignore = "millions of cycles, slow without --release"]
+fn box_blur() {
+    check("box_blur");
+}
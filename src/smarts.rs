@@ -0,0 +1,138 @@
+use core::fmt::{self, Display};
+
+use crate::cpu::CPU;
+
+const Z_95: f64 = 1.96;
+
+/// SMARTS style sampled simulation. Every `period` instructions the last `warmup` +
+/// `measure` are simulated in detail, the warmup refills the pipeline and the cycles taken by
+/// the measured ones are a sample of the program's CPI. Everything else is fast-forwarded.
+#[derive(Debug, Clone, Copy)]
+pub struct Smarts {
+    pub period: u64,
+    pub warmup: u64,
+    pub measure: u64,
+}
+impl Smarts {
+    /// An error naming the flags to change if the sample doesn't fit in the period.
+    pub fn new(period: u64, warmup: u64, measure: u64) -> Result<Self, String> {
+        if measure == 0 {
+            return Err(String::from(
+                "SMARTS samples need at least one instruction, --detailed can't be 0",
+            ));
+        }
+        if warmup.saturating_add(measure) > period {
+            return Err(format!(
+                "--smarts-period {} can't fit --smarts-warmup {} and --detailed {} instructions",
+                period, warmup, measure
+            ));
+        }
+
+        Ok(Self {
+            period,
+            warmup,
+            measure,
+        })
+    }
+
+    /// Runs the rest of the program, sampling as it goes.
+    pub fn run(&self, cpu: &mut CPU) -> SmartsEstimate {
+        let mut samples = Vec::new();
+
+        while cpu.is_running() {
+            cpu.fast_forward(self.period - self.warmup - self.measure);
+            if !cpu.is_running() {
+                break;
+            }
+            cpu.run_detailed(self.warmup);
+
+            let stats = cpu.stats();
+            let (cycles, instructions) = (stats.cycles, stats.instructions_commited);
+            cpu.run_detailed(self.measure);
+
+            // a sample cut short by the program ending isn't the same size as the others
+            let stats = cpu.stats();
+            if stats.instructions_commited - instructions >= self.measure {
+                samples.push(
+                    (stats.cycles - cycles) as f64
+                        / (stats.instructions_commited - instructions) as f64,
+                );
+            }
+        }
+
+        let stats = cpu.stats();
+        SmartsEstimate {
+            sampling: *self,
+            samples,
+            instructions: stats.instructions_commited + stats.instructions_fast_forwarded,
+        }
+    }
+}
+
+pub struct SmartsEstimate {
+    pub sampling: Smarts,
+    pub samples: Vec<f64>, // cpi of each sample
+    pub instructions: u64, // in the whole program
+}
+impl SmartsEstimate {
+    pub fn cpi(&self) -> f64 {
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+
+    /// half the width of the 95% confidence interval of the CPI.
+    pub fn confidence(&self) -> f64 {
+        let n = self.samples.len() as f64;
+        let mean = self.cpi();
+        let variance = self.samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        Z_95 * (variance / n).sqrt()
+    }
+}
+impl Display for SmartsEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sampling = &self.sampling;
+        writeln!(
+            f,
+            "SMARTS estimate ({} samples of {} instructions after {} warmup, every {}):",
+            self.samples.len(),
+            sampling.measure,
+            sampling.warmup,
+            sampling.period
+        )?;
+        if self.samples.len() < 2 {
+            return writeln!(f, " - Not enough samples, the program is too short");
+        }
+
+        let (cpi, confidence) = (self.cpi(), self.confidence());
+        writeln!(
+            f,
+            " - CPI: {:.3} ± {:.3} ({:.1}%, 95% confidence)",
+            cpi,
+            confidence,
+            100.0 * confidence / cpi
+        )?;
+        // the interval can reach 0 CPI, which puts no upper bound on the IPC
+        if confidence < cpi {
+            writeln!(
+                f,
+                " - IPC: {:.3} ({:.3} to {:.3})",
+                1.0 / cpi,
+                1.0 / (cpi + confidence),
+                1.0 / (cpi - confidence)
+            )?;
+        } else {
+            writeln!(
+                f,
+                " - IPC: {:.3} (≥ {:.3}, no upper bound)",
+                1.0 / cpi,
+                1.0 / (cpi + confidence)
+            )?;
+        }
+        writeln!(f, " - Instructions: {}", self.instructions)?;
+        writeln!(
+            f,
+            " - Estimated Cycles: {:.0} ± {:.0}",
+            cpi * self.instructions as f64,
+            confidence * self.instructions as f64
+        )
+    }
+}
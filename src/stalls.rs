@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::execution_units::EUType;
+
+/// Why the oldest instruction couldn't commit, so fewer than the commit width did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum CommitStall {
+    Frontend,         // rob empty, nothing has been fetched and dispatched
+    BranchMispredict, // flushing, or the rob is empty refilling after a flush
+    Serialize,        // the same after a flush for an instruction like fsrm
+    RobFull,          // nothing more can dispatch behind the oldest until it commits
+    RsFull(EUType),   // the next instruction to dispatch has no room in its reservation station
+    Operands,         // waiting in a reservation station for older results
+    MemoryDependence, // operands ready but an older store overlaps it
+    EuBusy(EUType),   // ready to go but every unit it can use is busy
+    Issue,            // ready, it issues next cycle
+    Execute(EUType),  // still executing
+}
+impl CommitStall {
+    pub fn all() -> Vec<Self> {
+        let mut all = vec![
+            Self::Frontend,
+            Self::BranchMispredict,
+            Self::Serialize,
+            Self::RobFull,
+        ];
+        all.extend(EUType::ALL.map(Self::RsFull));
+        all.push(Self::Operands);
+        all.push(Self::MemoryDependence);
+        all.extend(EUType::ALL.map(Self::EuBusy));
+        all.push(Self::Issue);
+        all.extend(EUType::ALL.map(Self::Execute));
+        all
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Frontend => String::from("frontend"),
+            Self::BranchMispredict => String::from("branch mispredict"),
+            Self::Serialize => String::from("serializing"),
+            Self::RobFull => String::from("rob full"),
+            Self::RsFull(eu) => format!("{:?} rs full", eu),
+            Self::Operands => String::from("waiting on operands"),
+            Self::MemoryDependence => String::from("memory dependence"),
+            Self::EuBusy(eu) => format!("{:?} busy", eu),
+            Self::Issue => String::from("issue"),
+            Self::Execute(eu) => format!("{:?} execute", eu),
+        }
+    }
+}
+
+/// Why dispatch stopped before dispatching its full amount in a cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum DispatchStall {
+    FetchBufferEmpty,
+    RobFull,
+    RsFull(EUType),
+}
+impl DispatchStall {
+    pub fn all() -> Vec<Self> {
+        let mut all = vec![Self::FetchBufferEmpty, Self::RobFull];
+        all.extend(EUType::ALL.map(Self::RsFull));
+        all
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::FetchBufferEmpty => String::from("fetch buffer empty"),
+            Self::RobFull => String::from("rob full"),
+            Self::RsFull(eu) => format!("{:?} rs full", eu),
+        }
+    }
+}
+
+/// Where the cycles went. Every cycle has `commit_width` commit slots, each used slot counts
+/// towards the base CPI and each unused one towards whatever stopped the oldest instruction
+/// committing, so the CPI stack adds up to the real CPI.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StallStats {
+    pub commit_width: usize,
+    pub base_slots: u64,
+    pub commit_slots: BTreeMap<CommitStall, u64>,
+    pub commit_cycles: BTreeMap<CommitStall, u64>, // cycles that committed under the width
+    pub dispatch_cycles: BTreeMap<DispatchStall, u64>,
+    pub fetch_buffer_full_cycles: u64,
+}
+impl StallStats {
+    pub fn new(commit_width: usize) -> Self {
+        Self {
+            commit_width,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_commit(&mut self, committed: u64, stall: CommitStall) {
+        let width = self.commit_width as u64;
+        self.base_slots += committed;
+        if committed < width {
+            *self.commit_slots.entry(stall).or_default() += width - committed;
+            *self.commit_cycles.entry(stall).or_default() += 1;
+        }
+    }
+
+    pub fn record_dispatch(&mut self, stall: DispatchStall) {
+        *self.dispatch_cycles.entry(stall).or_default() += 1;
+    }
+
+    /// (component, cpi) for base then every `CommitStall`, adding up to cycles / instructions.
+    pub fn cpi_stack(&self, instructions: u64) -> Vec<(String, f64)> {
+        let cpi = |slots: u64| slots as f64 / self.commit_width as f64 / instructions as f64;
+
+        let mut stack = vec![(String::from("base"), cpi(self.base_slots))];
+        for stall in CommitStall::all() {
+            let slots = self.commit_slots.get(&stall).copied().unwrap_or(0);
+            stack.push((stall.name(), cpi(slots)));
+        }
+        stack
+    }
+
+    pub fn report(&self, cycles: u64, instructions: u64) -> String {
+        let stack = self.cpi_stack(instructions);
+        let total: f64 = stack.iter().map(|(_, cpi)| cpi).sum();
+
+        let mut out = format!("CPI stack (commit width {}):\n", self.commit_width);
+        for (name, cpi) in stack.iter().filter(|(_, cpi)| *cpi != 0.0) {
+            out += &format!(
+                "  {:<20} {:>8.3} {:>6.1}%\n",
+                name,
+                cpi,
+                100.0 * cpi / total
+            );
+        }
+        out += &format!("  {:<20} {:>8.3}\n", "total", total);
+
+        let percent = |n: u64| 100.0 * n as f64 / cycles as f64;
+        out += "Commit stall cycles:\n";
+        for (stall, n) in &self.commit_cycles {
+            out += &format!("  {:<20} {:>8} {:>6.1}%\n", stall.name(), n, percent(*n));
+        }
+        out += "Dispatch stall cycles:\n";
+        for (stall, n) in &self.dispatch_cycles {
+            out += &format!("  {:<20} {:>8} {:>6.1}%\n", stall.name(), n, percent(*n));
+        }
+        out += "Fetch stall cycles:\n";
+        out += &format!(
+            "  {:<20} {:>8} {:>6.1}%\n",
+            "fetch buffer full",
+            self.fetch_buffer_full_cycles,
+            percent(self.fetch_buffer_full_cycles)
+        );
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::assemble_source,
+        cpu::{CpuConfig, CPU},
+        stats::StatsTracker,
+    };
+
+    fn run(source: &str) -> StatsTracker {
+        let (memory, instructions, _) = assemble_source(source);
+        let mut cpu = CPU::new(CpuConfig::default());
+        cpu.set_memory(memory);
+        cpu.load_program(instructions).unwrap();
+        cpu.run_detailed(u64::MAX);
+        cpu.finish()
+    }
+
+    /// a loop with a load feeding a multiply, so the oldest waits on a bit of everything.
+    const LOOP: &str = "
+.memory
+    values: .int 3, 1, 4, 1, 5, 9, 2, 6
+.instructions
+    main:
+        li $1 0
+        li $2 0
+        li $4 8
+    loop:
+        lw $3 $1 values
+        mult $2 $2 $3
+        addi $2 $2 1
+        addi $1 $1 1
+        bl $1 $4 loop
+        exit $2
+";
+
+    #[test]
+    fn cpi_stack_adds_up_to_the_cpi() {
+        let stats = run(LOOP);
+        let instructions = stats.instructions_commited;
+        let cpi = stats.cycles as f64 / instructions as f64;
+
+        let stack = stats.stalls.cpi_stack(instructions);
+        let total: f64 = stack.iter().map(|(_, cpi)| cpi).sum();
+        assert!((total - cpi).abs() < 1e-9, "{} != {}", total, cpi);
+
+        let component = |name: &str| stack.iter().find(|(n, _)| n == name).unwrap().1;
+        assert!(component("base") > 0.0);
+        assert!(component("branch mispredict") > 0.0);
+        assert_eq!(component("serializing"), 0.0);
+    }
+
+    #[test]
+    fn serializing_flushes_arent_blamed_on_branches() {
+        let stats = run(".instructions\n    main:\n        fsrm rtz\n        li $1 1\n        li $2 2\n        exit $2\n");
+        assert_eq!(stats.committed_mispredicions, 0);
+
+        let slots = |stall| stats.stalls.commit_slots.get(&stall).copied().unwrap_or(0);
+        assert!(slots(CommitStall::Serialize) > 0);
+        assert_eq!(slots(CommitStall::BranchMispredict), 0);
+    }
+}
@@ -1,14 +1,56 @@
 use core::fmt::{self, Display};
 
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    branch_profile::BranchProfile,
+    branch_trace::BranchTrace,
+    instruction_profile::InstructionProfile,
+    instructions::Register,
+    occupancy::Occupancy,
+    pipeline_trace::{PipelineEvent, PipelineStage},
+    stalls::StallStats,
+};
+
+/// Where a committed instruction wrote to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Written {
+    Register(Register),
+    Memory(usize, usize), // address, length
+}
+
 #[derive(Debug, Clone, Copy)]
+pub struct CommittedWrite {
+    pub pc: usize,
+    pub written: Written,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StatsTracker {
     pub branch_predictions: u64,
     pub branch_mispredictions: u64,
     pub committed_predicted_branches: u64,
     pub committed_mispredicions: u64,
+    pub branch_predictor_storage_bits: u64,
+    #[serde(skip)] // filled in when the program finishes
+    pub branch_predictor_stats: Vec<(&'static str, u64)>,
     pub cycles: u64,
     pub instructions_started: u64,
     pub instructions_commited: u64,
+    pub instructions_fast_forwarded: u64, // run functionally, not in any of the other stats
+    pub exit_value: Option<i32>,
+    pub exit_error: Option<String>,
+    pub stalls: StallStats,
+    pub occupancy: Occupancy,
+    pub branch_profile: BranchProfile,
+    pub branch_trace: Option<BranchTrace>,
+    pub instruction_profile: Option<InstructionProfile>,
+    pub trace_pipeline: bool,
+    pub pipeline_events: Vec<PipelineEvent>, // this cycle's events, when tracing the pipeline
+    #[serde(skip)]
+    pub record_writes: bool,
+    #[serde(skip)] // since they were last taken, when recording writes
+    pub committed_writes: Vec<CommittedWrite>,
 }
 impl StatsTracker {
     pub fn new() -> Self {
@@ -17,9 +59,35 @@ impl StatsTracker {
             branch_mispredictions: 0,
             committed_predicted_branches: 0,
             committed_mispredicions: 0,
+            branch_predictor_storage_bits: 0,
+            branch_predictor_stats: Vec::new(),
             cycles: 0,
             instructions_started: 0,
             instructions_commited: 0,
+            instructions_fast_forwarded: 0,
+            exit_value: None,
+            exit_error: None,
+            stalls: StallStats::default(),
+            occupancy: Occupancy::new(),
+            branch_profile: BranchProfile::new(),
+            branch_trace: None,
+            instruction_profile: None,
+            trace_pipeline: false,
+            pipeline_events: Vec::new(),
+            record_writes: false,
+            committed_writes: Vec::new(),
+        }
+    }
+
+    pub fn pipeline_event(&mut self, seq: u64, pc: usize, stage: PipelineStage) {
+        if self.trace_pipeline {
+            self.pipeline_events.push(PipelineEvent { seq, pc, stage });
+        }
+    }
+
+    pub fn committed_write(&mut self, pc: usize, written: Written) {
+        if self.record_writes {
+            self.committed_writes.push(CommittedWrite { pc, written });
         }
     }
 }
@@ -35,7 +103,7 @@ impl Display for StatsTracker {
         writeln!(
             f,
             " - Comitted Ops/Cycle: {:.2}",
-            self.instructions_started as f64 / self.cycles as f64
+            self.instructions_commited as f64 / self.cycles as f64
         )?;
         writeln!(f, " - Instructions Started: {}", self.instructions_started)?;
         writeln!(
@@ -48,6 +116,13 @@ impl Display for StatsTracker {
             " - Instructions Completed rate: {:.2}",
             100.0 * self.instructions_commited as f64 / self.instructions_started as f64
         )?;
+        if self.instructions_fast_forwarded != 0 {
+            writeln!(
+                f,
+                " - Instructions Fast-forwarded: {}",
+                self.instructions_fast_forwarded
+            )?;
+        }
         writeln!(f, " - Branch Predictions: {}", self.branch_predictions)?;
         writeln!(
             f,
@@ -68,6 +143,15 @@ impl Display for StatsTracker {
             f,
             " - Committed Branch Misprediction rate: {:.2}",
             100.0 * self.committed_mispredicions as f64 / self.committed_predicted_branches as f64
-        )
+        )?;
+        writeln!(
+            f,
+            " - Branch Predictor Storage: {} bits",
+            self.branch_predictor_storage_bits
+        )?;
+        for (name, value) in &self.branch_predictor_stats {
+            writeln!(f, " - {}: {}", name, value)?;
+        }
+        Ok(())
     }
 }
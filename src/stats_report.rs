@@ -0,0 +1,307 @@
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use crate::{
+    cpu::CPU,
+    float,
+    instructions::Register,
+    stalls::{CommitStall, DispatchStall},
+    stats::StatsTracker,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum StatsFormat {
+    /// the human readable summary
+    Text,
+    /// one json object, nested by section
+    Json,
+    /// `field,value` rows with dotted field names
+    Csv,
+}
+
+/// A json object whose keys depend on the configuration, like the branch predictor's counters
+/// or the occupancy histograms, kept in the order they were collected.
+struct Fields<T>(Vec<(String, T)>);
+impl<T: Serialize> Serialize for Fields<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+    }
+}
+impl<T> FromIterator<(String, T)> for Fields<T> {
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// Everything about a finished run: its configuration, every counter, the metrics derived
+/// from them and the architectural state it finished in. Field names are stable so scripts
+/// can rely on them, and rates with nothing to divide by are null.
+#[derive(Serialize)]
+pub struct Report {
+    config: Config,
+    stats: Counters,
+    derived: Derived,
+    branch_predictor: Fields<u64>,
+    stalls: Stalls,
+    occupancy: Fields<Occupancy>,
+    state: State,
+}
+
+#[derive(Serialize)]
+struct Config {
+    rob_size: usize,
+    rob_max_retire: usize,
+    fetch_amount: usize,
+    fetch_buffer_capacity: usize,
+    dispatch_amount: usize,
+    rs_alu_size: usize,
+    rs_fpu_size: usize,
+    rs_vpu_size: usize,
+    rs_lsu_size: usize,
+    rs_branch_size: usize,
+    eu_alu_num: usize,
+    eu_fpu_num: usize,
+    eu_vpu_num: usize,
+    eu_lsu_num: usize,
+    eu_branch_num: usize,
+    vector_registers: usize,
+    vector_width: usize,
+    fp_traps: String,
+    branch_predictor: String,
+}
+
+#[derive(Serialize)]
+struct Counters {
+    cycles: u64,
+    instructions_dispatched: u64,
+    instructions_committed: u64,
+    instructions_fast_forwarded: u64,
+    branch_predictions: u64,
+    committed_predicted_branches: u64,
+    committed_mispredictions: u64,
+    branch_predictor_storage_bits: u64,
+}
+
+#[derive(Serialize)]
+struct Derived {
+    ipc: Option<f64>,
+    cpi: Option<f64>,
+    dispatched_per_cycle: Option<f64>,
+    commit_rate: Option<f64>,
+    misprediction_rate: Option<f64>,
+    mpki: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct Stalls {
+    cpi_stack: Fields<Option<f64>>,
+    commit_cycles: Fields<u64>,
+    dispatch_cycles: Fields<u64>,
+    fetch_buffer_full_cycles: u64,
+}
+
+#[derive(Serialize)]
+struct Occupancy {
+    capacity: usize,
+    mean: Option<f64>,
+    max: usize,
+    full_rate: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct State {
+    pc: usize,
+    exit_value: Option<i32>,
+    exit_error: Option<String>,
+    registers: Fields<i32>,
+    vector_registers: Fields<String>,
+    float_flags: String,
+    rounding_mode: &'static str,
+    memory_bytes: usize,
+    memory_checksum: String,
+}
+
+/// `Loop overrides` -> `loop_overrides`, so names made for people can be field names.
+pub fn field_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// `numerator / denominator`, or None when there's nothing to divide by.
+fn ratio(numerator: f64, denominator: u64) -> Option<f64> {
+    match denominator {
+        0 => None,
+        _ => Some(numerator / denominator as f64),
+    }
+}
+
+/// None for the NaN an empty histogram's mean is.
+fn finite(value: f64) -> Option<f64> {
+    value.is_finite().then_some(value)
+}
+
+/// 64 bit FNV-1a, enough to tell whether two runs left the same memory behind.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn report(cpu: &CPU, stats: &StatsTracker) -> Report {
+    let config = cpu.config();
+    let config = Config {
+        rob_size: config.rob_size,
+        rob_max_retire: config.rob_max_retire,
+        fetch_amount: config.fetch_amount,
+        fetch_buffer_capacity: config.fetch_buffer_capacity,
+        dispatch_amount: config.dispatch_amount,
+        rs_alu_size: config.rs_alu_size,
+        rs_fpu_size: config.rs_fpu_size,
+        rs_vpu_size: config.rs_vpu_size,
+        rs_lsu_size: config.rs_lsu_size,
+        rs_branch_size: config.rs_branch_size,
+        eu_alu_num: config.eu_alu_num,
+        eu_fpu_num: config.eu_fpu_num,
+        eu_vpu_num: config.eu_vpu_num,
+        eu_lsu_num: config.eu_lsu_num,
+        eu_branch_num: config.eu_branch_num,
+        vector_registers: config.vector_registers,
+        vector_width: config.vector_width,
+        fp_traps: float::flag_names(config.float_traps),
+        branch_predictor: config.branch_predictor_mode.to_string(),
+    };
+
+    let counters = Counters {
+        cycles: stats.cycles,
+        instructions_dispatched: stats.instructions_started,
+        instructions_committed: stats.instructions_commited,
+        instructions_fast_forwarded: stats.instructions_fast_forwarded,
+        branch_predictions: stats.branch_predictions,
+        committed_predicted_branches: stats.committed_predicted_branches,
+        committed_mispredictions: stats.committed_mispredicions,
+        branch_predictor_storage_bits: stats.branch_predictor_storage_bits,
+    };
+
+    let committed = stats.instructions_commited;
+    let derived = Derived {
+        ipc: ratio(committed as f64, stats.cycles),
+        cpi: ratio(stats.cycles as f64, committed),
+        dispatched_per_cycle: ratio(stats.instructions_started as f64, stats.cycles),
+        commit_rate: ratio(committed as f64, stats.instructions_started),
+        misprediction_rate: ratio(
+            stats.committed_mispredicions as f64,
+            stats.committed_predicted_branches,
+        ),
+        mpki: ratio(1000.0 * stats.committed_mispredicions as f64, committed),
+    };
+
+    let branch_predictor = stats
+        .branch_predictor_stats
+        .iter()
+        .map(|(name, value)| (field_name(name), *value))
+        .collect();
+
+    let stalls = &stats.stalls;
+    let stalls = Stalls {
+        cpi_stack: stalls
+            .cpi_stack(committed)
+            .into_iter()
+            .map(|(name, cpi)| (field_name(&name), finite(cpi)))
+            .collect(),
+        commit_cycles: CommitStall::all()
+            .into_iter()
+            .map(|stall| {
+                let n = stalls.commit_cycles.get(&stall).copied().unwrap_or(0);
+                (field_name(&stall.name()), n)
+            })
+            .collect(),
+        dispatch_cycles: DispatchStall::all()
+            .into_iter()
+            .map(|stall| {
+                let n = stalls.dispatch_cycles.get(&stall).copied().unwrap_or(0);
+                (field_name(&stall.name()), n)
+            })
+            .collect(),
+        fetch_buffer_full_cycles: stalls.fetch_buffer_full_cycles,
+    };
+
+    let occupancy = stats
+        .occupancy
+        .histograms
+        .iter()
+        .map(|h| {
+            let occupancy = Occupancy {
+                capacity: h.capacity,
+                mean: finite(h.mean()),
+                max: h.max(),
+                full_rate: finite(h.full_rate() / 100.0),
+            };
+            (field_name(&h.name), occupancy)
+        })
+        .collect();
+
+    let registers = cpu.registers();
+    let state = State {
+        pc: registers.pc(),
+        exit_value: stats.exit_value,
+        exit_error: stats.exit_error.clone(),
+        registers: registers
+            .general()
+            .filter(|(reg, _)| *reg != Register::ProgramCounter)
+            .map(|(reg, value)| (reg.to_string(), value))
+            .collect(),
+        vector_registers: registers
+            .vectors()
+            .map(|(reg, value)| (reg.to_string(), value.to_string()))
+            .collect(),
+        float_flags: float::flag_names(registers.float_flags()),
+        rounding_mode: registers.rounding_mode().name(),
+        memory_bytes: cpu.memory().len(),
+        memory_checksum: format!("{:016x}", checksum(cpu.memory())),
+    };
+
+    Report {
+        config,
+        stats: counters,
+        derived,
+        branch_predictor,
+        stalls,
+        occupancy,
+        state,
+    }
+}
+
+pub fn to_json(report: &Report) -> String {
+    serde_json::to_string_pretty(report).expect("the stats report is always valid json")
+}
+
+/// `field,value` rows, with the json's nested field names joined by dots.
+pub fn to_csv(report: &Report) -> String {
+    let report = serde_json::to_value(report).expect("the stats report is always valid json");
+    let mut out = String::from("field,value\n");
+    csv_rows(&mut out, "", &report);
+    out
+}
+
+fn csv_rows(out: &mut String, path: &str, value: &Value) {
+    let value = match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                csv_rows(out, &path, value);
+            }
+            return;
+        }
+        Value::Null => String::new(),
+        Value::String(s) => format!("\"{}\"", s.replace('"', "\"\"")),
+        value => value.to_string(),
+    };
+    *out += &format!("{},{}\n", path, value);
+}
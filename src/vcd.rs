@@ -0,0 +1,314 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    cpu::CPU,
+    execution_units::EUType,
+    pipeline_trace::{PipelineEvent, PipelineStage},
+};
+
+const OP_CHARS: usize = 20; // long enough for every op's name
+
+/// bits needed to hold every value up to and including `max`.
+fn width(max: usize) -> usize {
+    (usize::BITS - max.leading_zeros()).max(1) as usize
+}
+
+/// identifiers are short strings of printable ascii.
+fn identifier(mut index: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
+
+fn binary(value: u64, width: usize) -> String {
+    format!("{:0width$b}", value, width = width)
+}
+
+/// The op's name packed into a vector of ascii, set GTKWave's data format to ascii to read it.
+fn ascii(text: &str) -> String {
+    let mut bits = String::new();
+    for i in 0..OP_CHARS {
+        let pad = OP_CHARS - text.len().min(OP_CHARS);
+        let byte = match i.checked_sub(pad) {
+            Some(j) => text.as_bytes()[j],
+            None => 0,
+        };
+        bits += &binary(byte as u64, 8);
+    }
+    bits
+}
+
+#[derive(Debug)]
+struct Signal {
+    scope: String,
+    name: String,
+    width: usize,
+    id: String,
+}
+
+/// Dumps the state of the pipeline after every cycle as a value change dump, one time step per
+/// cycle, for waveform viewers like GTKWave.
+pub struct VcdWriter {
+    out: BufWriter<File>,
+    signals: Vec<Signal>,
+    last: Vec<String>, // the value each signal was last dumped with
+    eu_types: Vec<EUType>,
+    started: bool,
+}
+impl VcdWriter {
+    /// The signals depend on how many of each structure the cpu was configured with.
+    pub fn create(path: &str, cpu: &CPU) -> io::Result<Self> {
+        let config = cpu.config();
+        let mut eu_types: Vec<EUType> = cpu.execution_units().iter().map(|eu| eu.flavour).collect();
+        eu_types.dedup();
+
+        let mut signals = Vec::new();
+        let mut add = |scope: &str, name: &str, width: usize| {
+            signals.push(Signal {
+                scope: scope.to_string(),
+                name: name.to_string(),
+                width,
+                id: identifier(signals.len()),
+            });
+        };
+
+        add("cpu", "pc", 32);
+        add("cpu", "fetched", width(config.fetch_amount));
+        add("cpu", "dispatched", width(config.dispatch_amount));
+        add("cpu", "committed", width(config.rob_max_retire));
+        add("cpu", "flush", 1);
+
+        let rob_width = width(config.rob_size);
+        add("rob", "head", rob_width);
+        add("rob", "tail", rob_width);
+        add("rob", "occupancy", rob_width);
+
+        let eus = cpu.execution_units();
+        for eu_type in eu_types.iter() {
+            let units = eus.iter().filter(|eu| eu.flavour == *eu_type).count();
+            add(
+                "issued",
+                &format!("{:?}", eu_type).to_lowercase(),
+                width(units),
+            );
+        }
+
+        for (i, eu) in eus.iter().enumerate() {
+            let scope = format!("eu{}_{:?}", i, eu.flavour).to_lowercase();
+            add(&scope, "issue", 1);
+            add(&scope, "busy", 1);
+            add(&scope, "pc", 32);
+            add(&scope, "op", 8 * OP_CHARS);
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "$version aca $end")?;
+        writeln!(out, "$timescale 1 ns $end")?;
+        writeln!(out, "$scope module aca $end")?;
+        let mut scope = "";
+        for signal in signals.iter() {
+            if signal.scope != scope {
+                if !scope.is_empty() {
+                    writeln!(out, "$upscope $end")?;
+                }
+                scope = &signal.scope;
+                writeln!(out, "$scope module {} $end", scope)?;
+            }
+            writeln!(
+                out,
+                "$var wire {} {} {} $end",
+                signal.width, signal.id, signal.name
+            )?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+
+        let last = vec![String::new(); signals.len()];
+        Ok(Self {
+            out,
+            signals,
+            last,
+            eu_types,
+            started: false,
+        })
+    }
+
+    /// Called after every cycle with that cycle's pipeline events, dumps whatever changed.
+    pub fn record(&mut self, cpu: &CPU, cycle: u64, events: &[PipelineEvent]) -> io::Result<()> {
+        let count = |stage: PipelineStage| events.iter().filter(|e| e.stage == stage).count();
+        let issued: Vec<u64> = events
+            .iter()
+            .filter(|e| e.stage == PipelineStage::Issue)
+            .map(|e| e.seq)
+            .collect();
+        let eus = cpu.execution_units();
+        let rob = cpu.rob();
+
+        let mut values = vec![
+            cpu.registers().pc() as u64,
+            count(PipelineStage::Fetch) as u64,
+            count(PipelineStage::Dispatch) as u64,
+            count(PipelineStage::Commit) as u64,
+            cpu.is_flushing() as u64,
+            rob.head() as u64,
+            rob.tail() as u64,
+            rob.len() as u64,
+        ];
+        for eu_type in self.eu_types.iter() {
+            let n = eus
+                .iter()
+                .filter(|eu| eu.flavour == *eu_type)
+                .filter(|eu| eu.inst.is_some_and(|inst| issued.contains(&inst.seq)))
+                .count();
+            values.push(n as u64);
+        }
+
+        let mut values: Vec<String> = values
+            .into_iter()
+            .zip(self.signals.iter())
+            .map(|(value, signal)| binary(value, signal.width))
+            .collect();
+        for eu in eus {
+            let inst = eu.inst;
+            let issue = inst.is_some_and(|inst| issued.contains(&inst.seq));
+            values.push(binary(issue as u64, 1));
+            values.push(binary(eu.is_busy() as u64, 1));
+            values.push(binary(inst.map_or(0, |inst| inst.pc as u64), 32));
+            values.push(ascii(
+                &inst.map_or(String::new(), |inst| format!("{:?}", inst.word.op())),
+            ));
+        }
+
+        let first = !self.started;
+        self.started = true;
+        let mut changes = String::new();
+        for ((value, last), signal) in values
+            .into_iter()
+            .zip(self.last.iter_mut())
+            .zip(self.signals.iter())
+        {
+            if first || value != *last {
+                if signal.width == 1 {
+                    changes += &format!("{}{}\n", value, signal.id);
+                } else {
+                    let trimmed = value.trim_start_matches('0');
+                    let trimmed = if trimmed.is_empty() { "0" } else { trimmed };
+                    changes += &format!("b{} {}\n", trimmed, signal.id);
+                }
+                *last = value;
+            }
+        }
+
+        if first {
+            write!(self.out, "#{}\n$dumpvars\n{}$end\n", cycle, changes)
+        } else if !changes.is_empty() {
+            write!(self.out, "#{}\n{}", cycle, changes)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// marks when the program ended so the last cycle is visible.
+    pub fn finish(mut self, cycles: u64) -> io::Result<()> {
+        writeln!(self.out, "#{}", cycles)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::{assembler::assemble_source, cpu::CpuConfig};
+
+    #[test]
+    fn header_then_every_signal_in_the_first_timestep() {
+        let path = env::temp_dir().join(format!("aca-{}.vcd", process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let (memory, instructions, _) =
+            assemble_source(".instructions\n    main:\n        li $1 3\n        exit $1\n");
+
+        let mut cpu = CPU::new(CpuConfig {
+            rob_size: 4,
+            fetch_amount: 2,
+            dispatch_amount: 2,
+            rob_max_retire: 2,
+            eu_alu_num: 1,
+            eu_fpu_num: 1,
+            eu_vpu_num: 1,
+            eu_lsu_num: 1,
+            eu_branch_num: 1,
+            ..CpuConfig::default()
+        });
+        cpu.set_memory(memory);
+        cpu.load_program(instructions).unwrap();
+        let vcd = VcdWriter::create(&path, &cpu).unwrap();
+        cpu.set_vcd(vcd);
+        cpu.run_detailed(u64::MAX);
+        cpu.finish();
+
+        let vcd = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let (header, dump) = vcd.split_once("$enddefinitions $end\n").unwrap();
+
+        let expected = "\
+$version aca $end
+$timescale 1 ns $end
+$scope module aca $end
+$scope module cpu $end
+$var wire 32 ! pc $end
+$var wire 2 \" fetched $end
+$var wire 2 # dispatched $end
+$var wire 2 $ committed $end
+$var wire 1 % flush $end
+$upscope $end
+$scope module rob $end
+$var wire 3 & head $end
+$var wire 3 ' tail $end
+$var wire 3 ( occupancy $end
+$upscope $end
+$scope module issued $end
+$var wire 1 ) system $end
+$var wire 1 * alu $end
+$var wire 1 + fpu $end
+$var wire 1 , vpu $end
+$var wire 1 - branch $end
+$var wire 1 . memory $end
+$upscope $end
+$scope module eu0_system $end
+$var wire 1 / issue $end
+$var wire 1 0 busy $end
+$var wire 32 1 pc $end
+$var wire 160 2 op $end
+$upscope $end
+";
+        assert!(header.starts_with(expected), "{}", header);
+        assert!(header.ends_with("$var wire 160 F op $end\n$upscope $end\n$upscope $end\n"));
+        let signals = header.matches("$var").count();
+        assert_eq!(signals, 5 + 3 + 6 + 6 * 4);
+
+        // the first timestep dumps every signal, the pc is past both instructions
+        let first: Vec<&str> = dump.split("$end\n").next().unwrap().lines().collect();
+        assert_eq!(first[..5], ["#0", "$dumpvars", "b10 !", "b10 \"", "b0 #"]);
+        assert_eq!(first.len(), 2 + signals);
+        for change in &first[4..] {
+            assert!(
+                change.starts_with('0') || change.starts_with("b0 "),
+                "{}",
+                change
+            );
+        }
+        assert!(dump.split("$end\n").nth(1).unwrap().starts_with("#1\n"));
+    }
+}
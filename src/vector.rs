@@ -0,0 +1,125 @@
+use core::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// 32 bit lanes in the widest vector registers can be configured with, 512 bits.
+pub const MAX_LANES: usize = 16;
+
+/// The value of a vector register, `--vector-width` bits split into 32 bit lanes. Lane 0
+/// comes from the lowest address and is printed first. As a mask a lane is set if it isn't
+/// zero, compares set every bit of a true lane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vector {
+    lanes: [u32; MAX_LANES],
+    len: usize,
+}
+impl Vector {
+    pub fn zero(lanes: usize) -> Self {
+        assert!(
+            lanes <= MAX_LANES,
+            "vectors are at most {} lanes",
+            MAX_LANES
+        );
+        Self {
+            lanes: [0; MAX_LANES],
+            len: lanes,
+        }
+    }
+
+    /// every lane set to `lane`.
+    pub fn splat(lane: u32, lanes: usize) -> Self {
+        let mut vector = Self::zero(lanes);
+        vector.lanes[..lanes].fill(lane);
+        vector
+    }
+
+    /// `lanes` big endian words from the start of `memory`.
+    pub fn read(memory: &[u8], lanes: usize) -> Self {
+        let mut vector = Self::zero(lanes);
+        for (lane, word) in vector.lanes[..lanes].iter_mut().zip(memory.chunks_exact(4)) {
+            *lane = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        vector
+    }
+
+    pub fn write(&self, memory: &mut [u8]) {
+        for (lane, word) in self.lanes().iter().zip(memory.chunks_exact_mut(4)) {
+            word.copy_from_slice(&lane.to_be_bytes());
+        }
+    }
+
+    pub fn lanes(&self) -> &[u32] {
+        &self.lanes[..self.len]
+    }
+
+    pub fn lanes_mut(&mut self) -> &mut [u32] {
+        &mut self.lanes[..self.len]
+    }
+
+    /// how much memory it takes up.
+    pub fn bytes(&self) -> usize {
+        self.len * 4
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.lanes().iter().all(|lane| *lane == 0)
+    }
+
+    /// `f` applied to each lane.
+    pub fn map(&self, f: impl Fn(u32) -> u32) -> Vector {
+        let mut out = *self;
+        out.lanes_mut().iter_mut().for_each(|lane| *lane = f(*lane));
+        out
+    }
+
+    /// lanes of `self` where `mask` is set and of `other` where it isn't.
+    pub fn select(&self, other: &Vector, mask: &Vector) -> Vector {
+        let mut out = *other;
+        for ((lane, ours), set) in out
+            .lanes_mut()
+            .iter_mut()
+            .zip(self.lanes())
+            .zip(mask.lanes())
+        {
+            if *set != 0 {
+                *lane = *ours;
+            }
+        }
+        out
+    }
+
+    pub fn floats(&self) -> Vec<f32> {
+        self.lanes()
+            .iter()
+            .map(|lane| f32::from_bits(*lane))
+            .collect()
+    }
+}
+impl Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x")?;
+        for lane in self.lanes() {
+            write!(f, "{:08x}", lane)?;
+        }
+        Ok(())
+    }
+}
+
+/// Most vector registers `--vector-registers` can ask for, like RISC-V's 32.
+pub const MAX_REGISTERS: usize = 32;
+
+/// `--vector-registers`, between 1 and `MAX_REGISTERS`. There's always a `$v0` to hold masks.
+pub fn parse_registers(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(registers @ 1..=MAX_REGISTERS) => Ok(registers),
+        _ => Err(format!("{} isn't between 1 and {}", s, MAX_REGISTERS)),
+    }
+}
+
+/// `--vector-width`, one of 128, 256 or 512 bits.
+pub fn parse_width(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(bits @ (128 | 256 | 512)) => Ok(bits),
+        _ => Err(format!("{} isn't 128, 256 or 512", s)),
+    }
+}
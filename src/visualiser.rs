@@ -0,0 +1,238 @@
+use std::{collections::VecDeque, io};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    assembler::SourceInfo,
+    checkpoint::Checkpoint,
+    cpu::CPU,
+    debugger::{describe_state, SECTIONS},
+};
+
+const SNAPSHOT_EVERY: u64 = 1000; // cycles
+const SNAPSHOTS_KEPT: usize = 64;
+
+const KEYS: &str =
+    "→/l step  ←/h back  PgDn/L +10  PgUp/H -10  End/G run to end  Home/g restart  q quit";
+
+/// Full screen view of the whole pipeline that can be stepped forwards and backwards a cycle
+/// at a time.
+pub struct Visualiser {
+    start: Checkpoint,               // where going back replays from
+    snapshots: VecDeque<Checkpoint>, // every SNAPSHOT_EVERY cycles after start, oldest first
+    source: Vec<SourceInfo>,
+    cpu: CPU,
+}
+impl Visualiser {
+    /// Starts from wherever `cpu` has got to, which has its program loaded.
+    pub fn new(cpu: CPU, source: Vec<SourceInfo>) -> Self {
+        Self {
+            start: cpu.checkpoint(),
+            snapshots: VecDeque::new(),
+            source,
+            cpu,
+        }
+    }
+
+    pub fn show(&mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let cycle = self.cpu.stats().cycles;
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Right | KeyCode::Char('l') | KeyCode::Char(' ') => self.goto(cycle + 1),
+                KeyCode::Left | KeyCode::Char('h') => self.goto(cycle.saturating_sub(1)),
+                KeyCode::PageDown | KeyCode::Char('L') => self.goto(cycle + 10),
+                KeyCode::PageUp | KeyCode::Char('H') => self.goto(cycle.saturating_sub(10)),
+                KeyCode::End | KeyCode::Char('G') => self.goto(u64::MAX),
+                KeyCode::Home | KeyCode::Char('g') => self.goto(0),
+                _ => (),
+            }
+        }
+    }
+
+    /// Runs to `cycle`, or the end of the program if that comes first. The simulator can only
+    /// go forwards so going back replays from the latest snapshot at or before `cycle`, or the
+    /// start if it's older than any we kept.
+    fn goto(&mut self, cycle: u64) {
+        if cycle < self.cpu.stats().cycles {
+            let snapshot = self
+                .snapshots
+                .iter()
+                .rev()
+                .find(|snapshot| snapshot.stats_tracker.cycles <= cycle)
+                .unwrap_or(&self.start);
+            self.cpu = CPU::restore(snapshot.clone(), self.cpu.config().clone());
+        }
+
+        while self.cpu.stats().cycles < cycle && self.cpu.is_running() {
+            self.cpu.step();
+            self.snapshot();
+        }
+    }
+
+    /// Keeps a snapshot every `SNAPSHOT_EVERY` cycles the first time we get there.
+    fn snapshot(&mut self) {
+        let cycles = self.cpu.stats().cycles;
+        let latest = self
+            .snapshots
+            .back()
+            .unwrap_or(&self.start)
+            .stats_tracker
+            .cycles;
+        if !cycles.is_multiple_of(SNAPSHOT_EVERY) || cycles <= latest {
+            return;
+        }
+
+        if self.snapshots.len() == SNAPSHOTS_KEPT {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(self.cpu.checkpoint());
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [left, middle, right] = Layout::horizontal([
+            Constraint::Percentage(45),
+            Constraint::Percentage(35),
+            Constraint::Percentage(20),
+        ])
+        .areas(body);
+        let [fetch, rob] =
+            Layout::vertical([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(left);
+        let [rs, eu] = Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(middle);
+        let [rat, regs] =
+            Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(right);
+
+        frame.render_widget(Line::from(self.status()).bold().reversed(), header);
+        frame.render_widget(Line::from(KEYS).dim(), footer);
+
+        for ((section, title), area) in SECTIONS.iter().zip([fetch, rs, eu, rob, rat, regs]) {
+            self.draw_section(frame, section, title, area);
+        }
+    }
+
+    fn draw_section(&self, frame: &mut Frame, section: &str, title: &str, area: Rect) {
+        let text = describe_state(&self.cpu, &self.source, section);
+        let block = Block::bordered()
+            .title(format!(" {} ", title))
+            .border_style(Style::new().dim());
+        frame.render_widget(Paragraph::new(text).block(block), area);
+    }
+
+    fn status(&mut self) -> String {
+        let stats = self.cpu.stats();
+        let mut status = format!(
+            " cycle {}  committed {}",
+            stats.cycles, stats.instructions_commited
+        );
+
+        if let Some(value) = stats.exit_value {
+            status += &format!("  exited with value {}", value);
+        } else if let Some(error) = &stats.exit_error {
+            status += &format!("  exited with error {}", error);
+        } else {
+            let pc = self.cpu.registers().pc();
+            status += &match self.source.get(pc) {
+                Some(source) => format!(
+                    "  fetching pc {} {}: {}",
+                    pc,
+                    source.location(),
+                    source.text
+                ),
+                None => format!("  fetching pc {}", pc),
+            };
+        }
+
+        if !self.cpu.is_running() {
+            status += "  (finished)";
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assembler::assemble_source, cpu::CpuConfig};
+
+    /// long enough to take a few snapshots.
+    const COUNT: &str = "
+.instructions
+    main:
+        li $1 0
+        li $2 3000
+    loop:
+        addi $1 $1 1
+        bl $1 $2 loop
+        exit $1
+";
+
+    fn cpu() -> CPU {
+        let (memory, instructions, _) = assemble_source(COUNT);
+        let mut cpu = CPU::new(CpuConfig::default());
+        cpu.set_memory(memory);
+        cpu.load_program(instructions).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn going_back_replays_from_the_nearest_snapshot() {
+        let mut visualiser = Visualiser::new(cpu(), Vec::new());
+        visualiser.goto(3500);
+        let snapshots: Vec<u64> = visualiser
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.stats_tracker.cycles)
+            .collect();
+        assert_eq!(snapshots, vec![1000, 2000, 3000]);
+
+        visualiser.goto(2100);
+        let mut straight = cpu();
+        while straight.stats().cycles < 2100 {
+            straight.step();
+        }
+        assert_eq!(visualiser.cpu.stats().cycles, 2100);
+        assert_eq!(
+            visualiser.cpu.stats().instructions_commited,
+            straight.stats().instructions_commited
+        );
+        assert_eq!(visualiser.cpu.registers(), straight.registers());
+
+        // replaying past a snapshot we already have doesn't take it again
+        visualiser.goto(3200);
+        assert_eq!(visualiser.snapshots.len(), 3);
+
+        visualiser.goto(0);
+        assert_eq!(visualiser.cpu.stats().cycles, 0);
+    }
+}